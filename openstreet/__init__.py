@@ -1,3 +1,3 @@
 from ._binding import *
 
-__all__ = [ 'Map', 'Way', 'Node', 'Bounds', 'WayQueryBuilder', 'NodeQueryBuilder', 'StreetNetwork' ]
+__all__ = [ 'Map', 'Way', 'Node', 'Relation', 'Member', 'Bounds', 'WayQueryBuilder', 'NodeQueryBuilder', 'RelationQueryBuilder', 'RoutingProfile', 'Route', 'Leg', 'Step', 'Intersection', 'Snap', 'Matching', 'StreetNetwork', 'NoRouteError', 'UnknownNodeError' ]
@@ -0,0 +1,228 @@
+/* alternatives.rs */
+//! Several routes between the same two nodes.
+//!
+//! [`k_shortest`] follows Yen's algorithm and returns the k best loopless
+//! routes, which tend to differ by a single block. [`alternatives`] uses the
+//! penalty method instead: the edges of each found route get more expensive
+//! and the search is repeated, candidates are kept when they are not much
+//! longer than the best route and do not share too much of it.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::network::{Edge, StreetNetwork};
+use crate::route::{Route, RouteError};
+use crate::search;
+
+/// Factor applied to the weight of an edge each time a route uses it
+const PENALTY: f64 = 1.5;
+
+/// Searches made for each alternative asked for, before giving up
+const ATTEMPTS: usize = 4;
+
+/// Edges of the cheapest path from `source` to any of `targets` with the
+/// given edge cost
+///
+/// Paths are edge sequences rather than node sequences, as simplified
+/// networks may have parallel edges between two junctions.
+fn cheapest<C>(network: &StreetNetwork, source: usize, targets: &[usize], cost: C) -> Option<Vec<usize>>
+where
+    C: Fn(usize, &Edge) -> Option<f64>,
+{
+    let tree = search::dijkstra(network, &[(source, 0.0)], f64::INFINITY, cost, |n| targets.contains(&n));
+    let target = targets.iter()
+    .filter(|t| tree.is_reached(**t))
+    .min_by(|a, b| tree.cost[**a].partial_cmp(&tree.cost[**b]).unwrap())?;
+    Some(tree.edges(network, *target))
+}
+
+/// Profile weight of the path along `edges`
+fn weight(network: &StreetNetwork, edges: &[usize]) -> usize {
+    edges.iter().map(|i| network.edges[*i].weight).sum()
+}
+
+/// Origin and destinations of a search in the graph
+fn endpoints(network: &StreetNetwork, a: i64, b: i64) -> Result<(usize, Vec<usize>), RouteError> {
+    let source = network.node_idx.find(a).ok_or(RouteError::UnknownNode(a))?;
+    let targets = network.arrivals(b);
+    if targets.is_empty() {
+        return Err(RouteError::UnknownNode(b));
+    }
+    Ok((source, targets))
+}
+
+/// The `k` best loopless routes from `a` to `b`, by increasing weight
+///
+/// Fewer routes are returned when the network does not have `k` distinct
+/// ones. Fails like [`StreetNetwork::route`] when there is none at all.
+pub fn k_shortest(network: &StreetNetwork, a: i64, b: i64, k: usize) -> Result<Vec<Route>, RouteError> {
+    let (source, targets) = endpoints(network, a, b)?;
+    let base = |_: usize, e: &Edge| Some(e.weight as f64);
+    let first = cheapest(network, source, &targets, base).ok_or(RouteError::NoRoute(a, b))?;
+
+    let mut found: Vec<Vec<usize>> = vec![first];
+    let mut candidates: Vec<(usize, Vec<usize>)> = vec![];
+    while found.len() < k {
+        let previous = found.last().unwrap().clone();
+        let nodes = std::iter::once(source)
+        .chain(previous.iter().map(|i| network.edges[*i].to))
+        .collect::<Vec<usize>>();
+        for i in 0..previous.len() {
+            let (spur, root) = (nodes[i], &previous[..i]);
+
+            // Leave out the next edge of every known path sharing the root,
+            // and the nodes of the root so that paths stay loopless
+            let banned_edges = found.iter()
+            .filter(|p| p.len() > i && p[..i] == *root)
+            .map(|p| p[i])
+            .collect::<HashSet<usize>>();
+            let banned_nodes = nodes[..i].iter().copied().collect::<HashSet<usize>>();
+
+            let cost = |j: usize, e: &Edge| {
+                if banned_nodes.contains(&e.to) || banned_edges.contains(&j) {
+                    return None;
+                }
+                Some(e.weight as f64)
+            };
+            if let Some(tail) = cheapest(network, spur, &targets, cost) {
+                let path = root.iter().chain(tail.iter()).copied().collect::<Vec<usize>>();
+                if !found.contains(&path) && !candidates.iter().any(|(_, p)| *p == path) {
+                    candidates.push((weight(network, &path), path));
+                }
+            }
+        }
+
+        // Cheapest candidate, the first one found on ties
+        let best = match candidates.iter().enumerate().min_by_key(|(i, (w, _))| (*w, *i)) {
+            Some((i, _)) => i,
+            None => break,
+        };
+        found.push(candidates.remove(best).1);
+    }
+    found.truncate(k);
+
+    Ok(found.iter().map(|p| network.route_over(source, p, weight(network, p))).collect())
+}
+
+/// Up to `count` reasonable routes from `a` to `b`, the best one first
+///
+/// An alternative weighs at most `max_stretch` times the best route and
+/// shares at most `max_overlap` of its length with each route already
+/// kept. Fails like [`StreetNetwork::route`] when there is no route.
+pub fn alternatives(network: &StreetNetwork, a: i64, b: i64, count: usize, max_stretch: f64, max_overlap: f64) -> Result<Vec<Route>, RouteError> {
+    let (source, targets) = endpoints(network, a, b)?;
+    let mut penalties: HashMap<usize, f64> = HashMap::new();
+    let mut routes: Vec<Route> = vec![];
+
+    for _ in 0..count * ATTEMPTS {
+        if routes.len() >= count {
+            break;
+        }
+        let cost = |i: usize, e: &Edge| Some(e.weight as f64 * penalties.get(&i).unwrap_or(&1.0));
+        let path = match cheapest(network, source, &targets, cost) {
+            Some(path) => path,
+            None => break,
+        };
+
+        for i in &path {
+            *penalties.entry(*i).or_insert(1.0) *= PENALTY;
+        }
+
+        let route = network.route_over(source, &path, weight(network, &path));
+        let acceptable = match routes.first() {
+            None => true,
+            Some(best) => route.weight as f64 <= best.weight as f64 * max_stretch
+                && routes.iter().all(|r| overlap(&route, r) <= max_overlap * route.distance),
+        };
+        if acceptable && !routes.iter().any(|r| r.nodes == route.nodes) {
+            routes.push(route);
+        }
+    }
+
+    if routes.is_empty() {
+        return Err(RouteError::NoRoute(a, b));
+    }
+    Ok(routes)
+}
+
+/// Length in meters of the segments of `route` also traversed by `other`
+fn overlap(route: &Route, other: &Route) -> f64 {
+    let shared = other.nodes.windows(2).map(|w| (w[0], w[1])).collect::<HashSet<(i64, i64)>>();
+    route.nodes.windows(2)
+    .zip(route.segments.iter())
+    .filter(|(w, _)| shared.contains(&(w[0], w[1])))
+    .map(|(_, s)| s.distance)
+    .sum()
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::map::Map;
+    use crate::network::StreetNetwork;
+    use crate::profile::RoutingProfile;
+    use crate::route::RouteError;
+
+    /// Ladder of three parallel streets from 1 to 4, the middle one
+    /// through 5 and 6 being the shortest, with rungs at both ends
+    const LADDER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.010"/>
+      <node id="3" lat="0.001" lon="0.000"/>
+      <node id="4" lat="0.001" lon="0.010"/>
+      <node id="5" lat="0.0005" lon="0.003"/>
+      <node id="6" lat="0.0005" lon="0.007"/>
+      <node id="7" lat="0.020" lon="0.005"/>
+      <node id="9" lat="0.050" lon="0.050"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="residential"/></way>
+      <way id="11"><nd ref="3"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+      <way id="12"><nd ref="1"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+      <way id="13"><nd ref="2"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+      <way id="14"><nd ref="1"/><nd ref="5"/><nd ref="6"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+      <way id="15"><nd ref="1"/><nd ref="7"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+      <way id="16"><nd ref="9"/><nd ref="7"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+    </osm>"#;
+
+    #[test]
+    fn k_shortest_paths_are_sorted_and_distinct() {
+        let map = Map::from_xml(LADDER.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let routes = network.k_shortest_paths(1, 4, 3).unwrap();
+        assert_eq!(routes.len(), 3);
+        assert_eq!(routes[0].nodes, network.route(1, 4).unwrap().nodes);
+        assert_eq!(routes[0].weight, network.route(1, 4).unwrap().weight);
+        assert_eq!(routes[0].nodes, vec![1, 5, 6, 4]);
+        assert!(routes.windows(2).all(|w| w[0].weight <= w[1].weight));
+        for (i, a) in routes.iter().enumerate() {
+            assert!(routes[i + 1..].iter().all(|b| a.nodes != b.nodes));
+        }
+
+        // Only four loopless paths exist
+        let routes = network.k_shortest_paths(1, 4, 10).unwrap();
+        assert_eq!(routes.len(), 4);
+        assert_eq!(routes[3].nodes, vec![1, 7, 4]);
+
+        assert_eq!(network.k_shortest_paths(1, 9, 2), Err(RouteError::NoRoute(1, 9)));
+        assert_eq!(network.k_shortest_paths(1, 99, 2), Err(RouteError::UnknownNode(99)));
+    }
+
+    #[test]
+    fn alternatives_respect_stretch_and_overlap() {
+        let map = Map::from_xml(LADDER.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let routes = network.alternatives(1, 4, 3, 1.2, 0.5).unwrap();
+        assert_eq!(routes.len(), 3);
+        assert_eq!(routes[0].nodes, vec![1, 5, 6, 4]);
+        let mut others = routes[1..].iter().map(|r| r.nodes.clone()).collect::<Vec<_>>();
+        others.sort();
+        assert_eq!(others, vec![vec![1, 2, 4], vec![1, 3, 4]]);
+
+        // The detour through 7 is four times longer
+        let routes = network.alternatives(1, 4, 5, 5.0, 0.5).unwrap();
+        assert_eq!(routes.len(), 4);
+        assert_eq!(network.alternatives(1, 4, 5, 1.2, 0.5).unwrap().len(), 3);
+        assert_eq!(network.alternatives(1, 9, 2, 1.2, 0.5), Err(RouteError::NoRoute(1, 9)));
+    }
+}
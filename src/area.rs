@@ -0,0 +1,337 @@
+/* area.rs */
+//! Assembly of closed ways and multipolygon relations into polygons.
+//!
+//! Member ways of a multipolygon are stitched end to end into closed rings,
+//! then every ring is classified as outer or inner by how deep it is nested
+//! inside the other rings. The member roles are not trusted since they are
+//! often missing or wrong in the wild.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::geo;
+use crate::map::{Map, MemberType, Relation, Way};
+
+/// Geometry error found while assembling an area
+#[derive(Debug, Clone, PartialEq)]
+pub enum AreaError {
+    /// A referenced node is not in the map
+    MissingNode(i64),
+    /// A referenced member way is not in the map
+    MissingWay(i64),
+    /// The way is not closed or the relation is not a multipolygon
+    NotAnArea(i64),
+    /// The member ways can not be joined into a closed ring,
+    /// the ring is left open between the two node ids
+    UnclosedRing(i64, i64),
+    /// A ring has less than three distinct nodes
+    DegenerateRing,
+    /// An inner ring is not contained in any outer ring
+    InnerWithoutOuter,
+}
+
+impl fmt::Display for AreaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingNode(id) => write!(f, "Node {} not found", id),
+            Self::MissingWay(id) => write!(f, "Way {} not found", id),
+            Self::NotAnArea(id) => write!(f, "Element {} is not an area", id),
+            Self::UnclosedRing(a, b) => write!(f, "Ring is not closed between node {} and {}", a, b),
+            Self::DegenerateRing => write!(f, "Ring has less than three nodes"),
+            Self::InnerWithoutOuter => write!(f, "Inner ring lies outside every outer ring"),
+        }
+    }
+}
+
+impl std::error::Error for AreaError {}
+
+#[derive(Clone, Debug)]
+/// Closed sequence of nodes, the first node is repeated at the end
+pub struct Ring {
+    pub nodes: Vec<i64>,
+    /// (lat, lon) pairs of each node
+    pub coords: Vec<(f64, f64)>,
+}
+
+impl Ring {
+    fn new(map: &Map, nodes: Vec<i64>) -> Result<Ring, AreaError> {
+        if nodes.len() < 4 {
+            return Err(AreaError::DegenerateRing);
+        }
+
+        let coords = nodes.iter()
+        .map(|id| {
+            map.node(*id)
+            .map(|n| (n.lat, n.lon))
+            .ok_or(AreaError::MissingNode(*id))
+        })
+        .collect::<Result<Vec<(f64, f64)>, AreaError>>()?;
+
+        Ok(Ring { nodes, coords })
+    }
+
+    /// Planar signed area in square degrees, positive when counter-clockwise
+    pub fn signed_area(&self) -> f64 {
+        self.coords.windows(2)
+        .map(|w| w[0].1 * w[1].0 - w[1].1 * w[0].0)
+        .sum::<f64>() / 2.0
+    }
+
+    /// Whether the point lies inside the ring (even-odd rule)
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        geo::ring_contains(&self.coords, lat, lon)
+    }
+
+    /// Whether `other` lies inside this ring
+    ///
+    /// Rings of a valid multipolygon never cross, so testing a single vertex
+    /// that is not shared with this ring is enough.
+    fn contains_ring(&self, other: &Ring) -> bool {
+        other.nodes.iter().zip(other.coords.iter())
+        .find(|(id, _)| !self.nodes.contains(id))
+        .map(|(_, (lat, lon))| self.contains(*lat, *lon))
+        .unwrap_or(false)
+    }
+
+    fn orient(&mut self, counter_clockwise: bool) {
+        if (self.signed_area() > 0.0) != counter_clockwise {
+            self.nodes.reverse();
+            self.coords.reverse();
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Polygon with holes
+///
+/// The outer ring is counter-clockwise and inner rings are clockwise.
+pub struct Polygon {
+    pub outer: Ring,
+    pub inners: Vec<Ring>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// OSM element an area is built from
+pub enum AreaSource {
+    Way(i64),
+    Relation(i64),
+}
+
+#[derive(Clone, Debug)]
+/// Area geometry of a closed way or a multipolygon relation
+pub struct Area {
+    pub source: AreaSource,
+    pub tags: HashMap<String, String>,
+    pub polygons: Vec<Polygon>,
+}
+
+/// Whether the way should be assembled as an area
+pub fn is_area_way(way: &Way) -> bool {
+    way.nodes.len() >= 4
+        && way.nodes.first() == way.nodes.last()
+        && !way.tags.is_empty()
+        && way.tags.get("area").map(|v| v.as_str()) != Some("no")
+        && way.is_polygon()
+}
+
+/// Whether the relation should be assembled as an area
+pub fn is_area_relation(relation: &Relation) -> bool {
+    matches!(relation.relation_type(), Some("multipolygon") | Some("boundary"))
+}
+
+/// Build the area of a single closed way
+pub fn from_way(map: &Map, way: &Way) -> Result<Area, AreaError> {
+    if way.nodes.first() != way.nodes.last() {
+        return Err(AreaError::NotAnArea(way.id));
+    }
+
+    let mut outer = Ring::new(map, way.nodes.clone())?;
+    outer.orient(true);
+
+    Ok(Area {
+        source: AreaSource::Way(way.id),
+        tags: way.tags.clone(),
+        polygons: vec![Polygon { outer, inners: vec![] }],
+    })
+}
+
+/// Build the area of a multipolygon (or boundary) relation
+pub fn from_relation(map: &Map, relation: &Relation) -> Result<Area, AreaError> {
+    if !is_area_relation(relation) {
+        return Err(AreaError::NotAnArea(relation.id));
+    }
+
+    let mut segments = vec![];
+    for member in &relation.members {
+        if member.member_type != MemberType::Way {
+            continue;
+        }
+        let way = map.way(member.id).ok_or(AreaError::MissingWay(member.id))?;
+        if way.nodes.len() >= 2 {
+            segments.push(way.nodes.clone());
+        }
+    }
+
+    let rings = join_rings(segments)?.into_iter()
+    .map(|nodes| Ring::new(map, nodes))
+    .collect::<Result<Vec<Ring>, AreaError>>()?;
+
+    // Rings nested inside an even number of rings are outer, the others are holes
+    let depths = rings.iter()
+    .map(|r| rings.iter().filter(|o| o.contains_ring(r)).count())
+    .collect::<Vec<usize>>();
+
+    let mut polygons: Vec<Polygon> = vec![];
+    let mut outer_idx: Vec<usize> = vec![];
+    for (i, ring) in rings.iter().enumerate() {
+        if depths[i] % 2 == 0 {
+            let mut outer = ring.clone();
+            outer.orient(true);
+            polygons.push(Polygon { outer, inners: vec![] });
+            outer_idx.push(i);
+        }
+    }
+
+    for (i, ring) in rings.iter().enumerate() {
+        if depths[i] % 2 == 0 {
+            continue;
+        }
+        // The hole belongs to the deepest outer ring that contains it
+        let owner = outer_idx.iter()
+        .enumerate()
+        .filter(|(_, o)| rings[**o].contains_ring(ring))
+        .max_by_key(|(_, o)| depths[**o])
+        .map(|(p, _)| p)
+        .ok_or(AreaError::InnerWithoutOuter)?;
+
+        let mut inner = ring.clone();
+        inner.orient(false);
+        polygons[owner].inners.push(inner);
+    }
+
+    let mut tags = relation.tags.clone();
+    tags.remove("type");
+
+    Ok(Area {
+        source: AreaSource::Relation(relation.id),
+        tags,
+        polygons,
+    })
+}
+
+/// Join open node sequences end to end until every sequence is a closed ring
+fn join_rings(mut segments: Vec<Vec<i64>>) -> Result<Vec<Vec<i64>>, AreaError> {
+    let mut rings = vec![];
+
+    while let Some(mut ring) = segments.pop() {
+        while ring.first() != ring.last() {
+            let end = *ring.last().unwrap();
+            let next = segments.iter()
+            .position(|s| s.first() == Some(&end) || s.last() == Some(&end));
+
+            match next {
+                Some(pos) => {
+                    let mut segment = segments.swap_remove(pos);
+                    if segment.first() != Some(&end) {
+                        segment.reverse();
+                    }
+                    ring.extend_from_slice(&segment[1..]);
+                },
+                None => return Err(AreaError::UnclosedRing(ring[0], end)),
+            }
+        }
+        rings.push(ring);
+    }
+
+    Ok(rings)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SQUARES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.0" lon="0.0"/>
+      <node id="2" lat="0.0" lon="4.0"/>
+      <node id="3" lat="4.0" lon="4.0"/>
+      <node id="4" lat="4.0" lon="0.0"/>
+      <node id="5" lat="1.0" lon="1.0"/>
+      <node id="6" lat="1.0" lon="3.0"/>
+      <node id="7" lat="3.0" lon="3.0"/>
+      <node id="8" lat="3.0" lon="1.0"/>
+      <node id="9" lat="10.0" lon="10.0"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/></way>
+      <way id="11"><nd ref="1"/><nd ref="4"/><nd ref="3"/></way>
+      <way id="12"><nd ref="5"/><nd ref="6"/><nd ref="7"/><nd ref="8"/><nd ref="5"/></way>
+      <way id="13"><nd ref="1"/><nd ref="9"/></way>
+      <way id="14">
+        <nd ref="5"/><nd ref="6"/><nd ref="7"/><nd ref="5"/>
+        <tag k="building" v="yes"/>
+      </way>
+      <relation id="100">
+        <member type="way" ref="10" role="outer"/>
+        <member type="way" ref="12" role=""/>
+        <member type="way" ref="11" role="outer"/>
+        <tag k="type" v="multipolygon"/>
+        <tag k="natural" v="water"/>
+      </relation>
+      <relation id="101">
+        <member type="way" ref="10" role="outer"/>
+        <member type="way" ref="13" role="outer"/>
+        <tag k="type" v="multipolygon"/>
+      </relation>
+      <relation id="102">
+        <member type="way" ref="99" role="outer"/>
+        <tag k="type" v="multipolygon"/>
+      </relation>
+    </osm>"#;
+
+    #[test]
+    fn assemble_multipolygon_with_hole() {
+        let map = Map::from_xml(SQUARES.as_bytes()).unwrap();
+        let area = from_relation(&map, map.relation(100).unwrap()).unwrap();
+
+        assert_eq!(area.source, AreaSource::Relation(100));
+        assert_eq!(area.tags.get("natural").map(|v| v.as_str()), Some("water"));
+        assert_eq!(area.polygons.len(), 1);
+
+        let polygon = &area.polygons[0];
+        assert_eq!(polygon.outer.nodes.len(), 5);
+        assert!(polygon.outer.signed_area() > 0.0);
+        assert_eq!(polygon.inners.len(), 1);
+        assert!(polygon.inners[0].signed_area() < 0.0);
+        assert!(polygon.outer.contains(0.5, 0.5));
+        assert!(polygon.inners[0].contains(2.0, 2.0));
+    }
+
+    #[test]
+    fn assemble_closed_way() {
+        let map = Map::from_xml(SQUARES.as_bytes()).unwrap();
+        let area = from_way(&map, map.way(14).unwrap()).unwrap();
+
+        assert_eq!(area.polygons.len(), 1);
+        assert!(area.polygons[0].inners.is_empty());
+        assert!(area.polygons[0].outer.signed_area() > 0.0);
+        assert_eq!(from_way(&map, map.way(13).unwrap()).unwrap_err(), AreaError::NotAnArea(13));
+    }
+
+    #[test]
+    fn broken_geometries_are_errors() {
+        let map = Map::from_xml(SQUARES.as_bytes()).unwrap();
+
+        match from_relation(&map, map.relation(101).unwrap()) {
+            Err(AreaError::UnclosedRing(_, _)) => (),
+            other => panic!("Expected unclosed ring, got {:?}", other),
+        }
+        assert_eq!(
+            from_relation(&map, map.relation(102).unwrap()).unwrap_err(),
+            AreaError::MissingWay(99)
+        );
+
+        let areas = map.areas();
+        assert_eq!(areas.iter().filter(|a| a.is_ok()).count(), 2);
+        assert_eq!(areas.iter().filter(|a| a.is_err()).count(), 2);
+    }
+}
@@ -0,0 +1,229 @@
+/* avoid.rs */
+//! Routes avoiding ways, nodes, tagged streets or areas.
+//!
+//! The prepared hierarchy knows nothing about exclusions. Its route is used
+//! when it does not touch any of them, otherwise a Dijkstra search over the
+//! remaining edges takes over.
+
+use std::collections::HashSet;
+
+use crate::area::Area;
+use crate::geo;
+use crate::isochrone::Polygon;
+use crate::network::{Edge, StreetNetwork};
+use crate::route::{Route, RouteError};
+use crate::search;
+
+#[derive(Clone, Debug, Default)]
+/// What a route has to stay away from
+///
+/// ```ignore
+/// let avoid = Avoid::new()
+///     .way(10)
+///     .tag("toll", "yes")
+///     .polygon(vec![(0.0, 0.0), (0.0, 0.01), (0.01, 0.01), (0.0, 0.0)]);
+/// let route = network.route_avoiding(1, 2, &avoid)?;
+/// ```
+pub struct Avoid {
+    /// Ids of closed ways
+    pub ways: HashSet<i64>,
+    /// Ids of closed nodes, no route passes through them
+    pub nodes: HashSet<i64>,
+    /// Ways with any of these (key, value) tags are closed
+    pub tags: Vec<(String, String)>,
+    /// Closed areas, no route enters them
+    pub polygons: Vec<Polygon>,
+}
+
+impl Avoid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn way(mut self, id: i64) -> Self {
+        self.ways.insert(id);
+        self
+    }
+
+    pub fn node(mut self, id: i64) -> Self {
+        self.nodes.insert(id);
+        self
+    }
+
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Area enclosed by a closed ring of (lat, lon) pairs
+    pub fn polygon(mut self, exterior: Vec<(f64, f64)>) -> Self {
+        self.polygons.push(Polygon { exterior, interiors: vec![] });
+        self
+    }
+
+    /// Every polygon of an area of the map, holes stay open
+    pub fn area(mut self, area: &Area) -> Self {
+        for polygon in &area.polygons {
+            self.polygons.push(Polygon {
+                exterior: polygon.outer.coords.clone(),
+                interiors: polygon.inners.iter().map(|r| r.coords.clone()).collect(),
+            });
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ways.is_empty() && self.nodes.is_empty() && self.tags.is_empty() && self.polygons.is_empty()
+    }
+
+    fn covers(&self, (lat, lon): (f64, f64)) -> bool {
+        self.polygons.iter().any(|p| {
+            geo::ring_contains(&p.exterior, lat, lon) && !p.interiors.iter().any(|r| geo::ring_contains(r, lat, lon))
+        })
+    }
+
+    fn crosses(&self, a: (f64, f64), b: (f64, f64)) -> bool {
+        self.polygons.iter()
+        .flat_map(|p| std::iter::once(&p.exterior).chain(p.interiors.iter()))
+        .any(|ring| ring.windows(2).any(|w| geo::segments_cross(a, b, w[0], w[1])))
+    }
+
+    /// Whether the step along `way` from node `a` to node `b`, both given
+    /// as (OSM id, (lat, lon)), is closed
+    fn blocks(&self, network: &StreetNetwork, way: i64, a: (i64, (f64, f64)), b: (i64, (f64, f64))) -> bool {
+        self.ways.contains(&way)
+        || self.nodes.contains(&a.0)
+        || self.nodes.contains(&b.0)
+        || network.ways.get(&way).is_some_and(|w| self.tags.iter().any(|(k, v)| w.tags.get(k) == Some(v)))
+        || self.covers(a.1)
+        || self.covers(b.1)
+        || self.crosses(a.1, b.1)
+    }
+
+    fn blocks_edge(&self, network: &StreetNetwork, edge: &Edge) -> bool {
+        let mut a = (network.node_idx.node(edge.from), network.coords[edge.from]);
+        for piece in network.pieces(edge) {
+            let b = (piece.node, network.location(piece.node).unwrap_or_default());
+            if self.blocks(network, piece.way, a, b) {
+                return true;
+            }
+            a = b;
+        }
+        false
+    }
+
+    fn blocks_route(&self, network: &StreetNetwork, route: &Route) -> bool {
+        if route.nodes.len() == 1 {
+            return self.nodes.contains(&route.nodes[0]) || self.covers(route.geometry[0]);
+        }
+        route.segments.iter().enumerate().any(|(i, s)| {
+            let a = (route.nodes[i], route.geometry[i]);
+            let b = (route.nodes[i + 1], route.geometry[i + 1]);
+            self.blocks(network, s.way, a, b)
+        })
+    }
+}
+
+/// Best route from `a` to `b` that stays clear of everything in `avoid`
+///
+/// Fails like [`StreetNetwork::route`], with [`RouteError::NoRoute`] as
+/// well when every route is blocked.
+pub fn route(network: &StreetNetwork, a: i64, b: i64, avoid: &Avoid) -> Result<Route, RouteError> {
+    let route = network.route(a, b)?;
+    if !avoid.blocks_route(network, &route) {
+        return Ok(route);
+    }
+
+    let source = network.node_idx.get(a);
+    let targets = network.arrivals(b);
+    if avoid.nodes.contains(&a) || avoid.covers(network.coords[source]) {
+        return Err(RouteError::NoRoute(a, b));
+    }
+    let blocked = network.edges.iter().map(|e| avoid.blocks_edge(network, e)).collect::<Vec<bool>>();
+    let cost = |i: usize, e: &Edge| if blocked[i] { None } else { Some(e.weight as f64) };
+    let tree = search::dijkstra(network, &[(source, 0.0)], f64::INFINITY, cost, |n| targets.contains(&n));
+
+    let target = targets.iter()
+    .filter(|t| tree.is_reached(**t))
+    .min_by(|x, y| tree.cost[**x].partial_cmp(&tree.cost[**y]).unwrap())
+    .ok_or(RouteError::NoRoute(a, b))?;
+    Ok(network.route_over(source, &tree.edges(network, *target), tree.cost[*target] as usize))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::RoutingProfile;
+
+    /// Direct toll road 1-2-3 and a detour 1-4-5-3, with side streets
+    /// leaving 1 and 3
+    const DETOUR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.001" lon="0.000"/>
+      <node id="5" lat="0.001" lon="0.002"/>
+      <node id="6" lat="-0.001" lon="0.000"/>
+      <node id="7" lat="-0.001" lon="0.002"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/><tag k="toll" v="yes"/></way>
+      <way id="11"><nd ref="1"/><nd ref="4"/><nd ref="5"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+      <way id="12"><nd ref="6"/><nd ref="1"/><tag k="highway" v="residential"/></way>
+      <way id="13"><nd ref="3"/><nd ref="7"/><tag k="highway" v="residential"/></way>
+    </osm>"#;
+
+    #[test]
+    fn exclusions_force_the_detour() {
+        let map = Map::from_xml(DETOUR.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        let direct = network.route(1, 3).unwrap();
+        assert_eq!(direct.nodes, vec![1, 2, 3]);
+
+        let detour = vec![1, 4, 5, 3];
+        assert_eq!(network.route_avoiding(1, 3, &Avoid::new()).unwrap(), direct);
+        assert_eq!(network.route_avoiding(1, 3, &Avoid::new().way(11)).unwrap(), direct);
+        assert_eq!(network.route_avoiding(1, 3, &Avoid::new().way(10)).unwrap().nodes, detour);
+        assert_eq!(network.route_avoiding(1, 3, &Avoid::new().node(2)).unwrap().nodes, detour);
+        assert_eq!(network.route_avoiding(1, 3, &Avoid::new().tag("toll", "yes")).unwrap().nodes, detour);
+
+        let route = network.route_avoiding(1, 3, &Avoid::new().way(10)).unwrap();
+        assert_eq!(route.weight, route.nodes.windows(2).map(|w| network.edge(w[0], w[1]).unwrap().weight).sum::<usize>());
+        assert!(route.distance > direct.distance);
+
+        // Both streets closed
+        assert_eq!(network.route_avoiding(1, 3, &Avoid::new().way(10).node(4)), Err(RouteError::NoRoute(1, 3)));
+        assert_eq!(network.route_avoiding(1, 3, &Avoid::new().node(1)), Err(RouteError::NoRoute(1, 3)));
+        assert_eq!(network.route_avoiding(1, 99, &Avoid::new()), Err(RouteError::UnknownNode(99)));
+
+        // Node 2 is inside the simplified edge 1-3
+        let mut simple = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        simple.simplify();
+        assert_eq!(simple.route_avoiding(1, 3, &Avoid::new().node(2)).unwrap().nodes, detour);
+    }
+
+    #[test]
+    fn polygons_close_whatever_they_touch() {
+        let map = Map::from_xml(DETOUR.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        let square = |lat: f64, lon: f64, size: f64| vec![
+            (lat, lon), (lat, lon + size), (lat + size, lon + size), (lat + size, lon), (lat, lon),
+        ];
+
+        // Around node 2
+        let avoid = Avoid::new().polygon(square(-0.0002, 0.0008, 0.0004));
+        assert_eq!(network.route_avoiding(1, 3, &avoid).unwrap().nodes, vec![1, 4, 5, 3]);
+
+        // Across the segment 1-2 without covering a node
+        let avoid = Avoid::new().polygon(square(-0.0001, 0.0004, 0.0002));
+        assert_eq!(network.route_avoiding(1, 3, &avoid).unwrap().nodes, vec![1, 4, 5, 3]);
+
+        // A hole leaves the direct road open
+        let mut avoid = Avoid::new().polygon(square(-0.0005, -0.0005, 0.003));
+        avoid.polygons[0].interiors.push(square(-0.0001, -0.0001, 0.0022));
+        assert_eq!(network.route_avoiding(1, 3, &avoid).unwrap().nodes, vec![1, 2, 3]);
+        avoid.polygons[0].interiors.clear();
+        assert_eq!(network.route_avoiding(1, 3, &avoid), Err(RouteError::NoRoute(1, 3)));
+    }
+}
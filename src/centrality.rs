@@ -0,0 +1,253 @@
+/* centrality.rs */
+//! Centrality of the nodes of a street network.
+//!
+//! Scores are computed from the shortest paths out of every node, in
+//! parallel over the sources, and keyed by OSM node id. A junction split
+//! by turn restrictions is scored once, nodes removed by
+//! [`StreetNetwork::simplify`] are not scored.
+
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::geo;
+use crate::isochrone;
+use crate::network::StreetNetwork;
+use crate::profile::Metric;
+use crate::search;
+
+/// Original graph node of every OSM node of the network
+fn sources(network: &StreetNetwork) -> Vec<usize> {
+    (0..network.node_idx.len())
+    .filter(|n| network.node_idx.find(network.node_idx.node(*n)) == Some(*n))
+    .collect()
+}
+
+/// Sum the scores of each graph node by OSM id
+fn by_id(network: &StreetNetwork, scores: &[f64]) -> HashMap<i64, f64> {
+    let mut found = HashMap::new();
+    for (node, score) in scores.iter().enumerate() {
+        *found.entry(network.node_idx.node(node)).or_insert(0.0) += score;
+    }
+    found
+}
+
+/// Brandes' accumulation of the dependencies of the paths out of `sources`
+fn brandes(network: &StreetNetwork, sources: &[usize], metric: Metric) -> Vec<f64> {
+    let size = network.node_idx.len();
+    let cost = isochrone::edge_cost(metric);
+
+    sources.par_iter()
+    .fold(|| vec![0.0; size], |mut scores, source| {
+        let paths = search::all_paths(network, *source, &cost);
+
+        // Each OSM node is a target once, at its cheapest graph node
+        let mut target = vec![0.0; size];
+        let mut seen = vec![false; size];
+        for node in &paths.order {
+            let first = network.node_idx.get(network.node_idx.node(*node));
+            if !seen[first] && first != *source {
+                seen[first] = true;
+                target[*node] = 1.0;
+            }
+        }
+
+        let mut dependency = vec![0.0; size];
+        for node in paths.order.iter().rev() {
+            for previous in &paths.previous[*node] {
+                dependency[*previous] += paths.count[*previous] / paths.count[*node] * (target[*node] + dependency[*node]);
+            }
+            if network.node_idx.node(*node) != network.node_idx.node(*source) {
+                scores[*node] += dependency[*node];
+            }
+        }
+        scores
+    })
+    .reduce(|| vec![0.0; size], |a, b| a.iter().zip(&b).map(|(x, y)| x + y).collect())
+}
+
+/// Scale raw betweenness scores, see [`betweenness`]
+fn scaled(network: &StreetNetwork, raw: Vec<f64>, factor: f64, normalized: bool) -> HashMap<i64, f64> {
+    let count = sources(network).len() as f64;
+    let norm = if normalized && count > 2.0 { (count - 1.0) * (count - 2.0) } else { 1.0 };
+    by_id(network, &raw).into_iter().map(|(id, score)| (id, score * factor / norm)).collect()
+}
+
+/// Betweenness centrality of every node
+///
+/// The score of a node is the number of shortest paths between two other
+/// nodes going through it, with shares when there are several shortest
+/// paths. Normalized scores are divided by the number of ordered pairs of
+/// other nodes, (n - 1)(n - 2).
+pub fn betweenness(network: &StreetNetwork, metric: Metric, normalized: bool) -> HashMap<i64, f64> {
+    let raw = brandes(network, &sources(network), metric);
+    scaled(network, raw, 1.0, normalized)
+}
+
+/// Betweenness centrality estimated from the paths out of `samples`
+/// nodes drawn at random
+///
+/// The same `seed` draws the same nodes. Scores are scaled up to estimate
+/// the ones of [`betweenness`], which this falls back to when `samples`
+/// is not smaller than the number of nodes.
+pub fn sampled_betweenness(network: &StreetNetwork, metric: Metric, samples: usize, seed: u64, normalized: bool) -> HashMap<i64, f64> {
+    let mut nodes = sources(network);
+    if samples >= nodes.len() {
+        return betweenness(network, metric, normalized);
+    }
+
+    // Partial Fisher-Yates shuffle with SplitMix64
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    for i in 0..samples {
+        let j = i + (next() % (nodes.len() - i) as u64) as usize;
+        nodes.swap(i, j);
+    }
+
+    let factor = nodes.len() as f64 / samples as f64;
+    let raw = brandes(network, &nodes[..samples], metric);
+    scaled(network, raw, factor, normalized)
+}
+
+/// Cost from `source` to every reached OSM node, as (original graph node,
+/// cost) pairs without the source itself
+fn reached(network: &StreetNetwork, source: usize, metric: Metric) -> Vec<(usize, f64)> {
+    let tree = search::dijkstra(network, &[(source, 0.0)], f64::INFINITY, isochrone::edge_cost(metric), |_| false);
+    let mut best: HashMap<usize, f64> = HashMap::new();
+    for (node, cost) in tree.cost.iter().enumerate().filter(|(_, c)| c.is_finite()) {
+        let first = network.node_idx.get(network.node_idx.node(node));
+        if first != source {
+            let entry = best.entry(first).or_insert(*cost);
+            *entry = entry.min(*cost);
+        }
+    }
+    best.into_iter().collect()
+}
+
+/// Closeness centrality of every node
+///
+/// The inverse of the mean cost of the shortest paths to the nodes it
+/// reaches, scaled by the share of the other nodes it reaches so that
+/// nodes of small components do not score high.
+pub fn closeness(network: &StreetNetwork, metric: Metric) -> HashMap<i64, f64> {
+    let nodes = sources(network);
+    let others = nodes.len().saturating_sub(1) as f64;
+
+    nodes.par_iter()
+    .map(|source| {
+        let costs = reached(network, *source, metric);
+        let total = costs.iter().map(|(_, c)| c).sum::<f64>();
+        let count = costs.len() as f64;
+        let score = if total > 0.0 { (count / others) * (count / total) } else { 0.0 };
+        (network.node_idx.node(*source), score)
+    })
+    .collect()
+}
+
+/// Straightness centrality of every node
+///
+/// The mean ratio between the straight distance and the length of the
+/// shortest path to every other node, 1 when all streets leave the node
+/// in a straight line. Unreached nodes count as 0.
+pub fn straightness(network: &StreetNetwork) -> HashMap<i64, f64> {
+    let nodes = sources(network);
+    let others = nodes.len().saturating_sub(1) as f64;
+
+    nodes.par_iter()
+    .map(|source| {
+        let (lat, lon) = network.coords[*source];
+        let total = reached(network, *source, Metric::Distance).iter()
+        .filter(|(_, length)| *length > 0.0)
+        .map(|(node, length)| geo::distance(lat, lon, network.coords[*node].0, network.coords[*node].1) / length)
+        .sum::<f64>();
+        (network.node_idx.node(*source), if others > 0.0 { total / others } else { 0.0 })
+    })
+    .collect()
+}
+
+/// GeoJSON FeatureCollection with a Point feature for every scored node
+///
+/// Each feature has the node ``id`` and one property for each of the
+/// (name, scores) pairs.
+pub fn to_geojson(network: &StreetNetwork, scores: &[(&str, &HashMap<i64, f64>)]) -> Value {
+    let mut ids = scores.iter().flat_map(|(_, s)| s.keys().copied()).collect::<Vec<i64>>();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let features = ids.into_iter()
+    .filter_map(|id| {
+        let (lat, lon) = network.location(id)?;
+        let mut properties = json!({ "id": id });
+        for (name, values) in scores {
+            properties[*name] = json!(values.get(&id));
+        }
+        Some(json!({
+            "type": "Feature",
+            "properties": properties,
+            "geometry": { "type": "Point", "coordinates": [lon, lat] },
+        }))
+    })
+    .collect::<Vec<Value>>();
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::RoutingProfile;
+
+    /// Straight street 1-2-3-4 with a side street 2-5
+    const STREETS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.000" lon="0.003"/>
+      <node id="5" lat="0.001" lon="0.001"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+      <way id="11"><nd ref="2"/><nd ref="5"/><tag k="highway" v="residential"/></way>
+    </osm>"#;
+
+    #[test]
+    fn scores_follow_the_street_layout() {
+        let map = Map::from_xml(STREETS.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let raw = network.betweenness(Metric::Distance, false);
+        let expected = [(1, 0.0), (2, 10.0), (3, 6.0), (4, 0.0), (5, 0.0)];
+        for (id, score) in expected.iter() {
+            assert!((raw[id] - score).abs() < 1e-9, "{}: {}", id, raw[id]);
+        }
+        let normalized = network.betweenness(Metric::Time, true);
+        assert!((normalized[&2] - 10.0 / 12.0).abs() < 1e-9);
+        assert_eq!(network.sampled_betweenness(Metric::Distance, 5, 7, false), raw);
+        let sampled = network.sampled_betweenness(Metric::Distance, 2, 7, false);
+        assert_eq!(sampled, network.sampled_betweenness(Metric::Distance, 2, 7, false));
+        assert!(sampled.values().all(|s| s.is_finite() && *s >= 0.0));
+
+        let closeness = network.closeness(Metric::Distance);
+        let best = closeness.iter().max_by(|a, b| a.1.partial_cmp(b.1).unwrap()).unwrap();
+        assert_eq!(*best.0, 2);
+        assert!(closeness[&4] < closeness[&3]);
+
+        let straightness = network.straightness();
+        assert!((straightness[&2] - 1.0).abs() < 1e-6);
+        assert!(straightness[&5] < straightness[&1] && straightness[&1] < 1.0);
+
+        let geojson = to_geojson(&network, &[("betweenness", &raw), ("closeness", &closeness)]);
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 5);
+        assert_eq!(features[1]["properties"]["betweenness"], 10.0);
+        assert_eq!(features[1]["geometry"]["coordinates"][0], 0.001);
+    }
+}
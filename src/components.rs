@@ -0,0 +1,288 @@
+/* components.rs */
+//! Connected components of a street network.
+//!
+//! Extracts often hold islands no route leaves or enters, such as parking
+//! aisles or roads clipped at the edge of the bounding box. A strongly
+//! connected component is a set of nodes that can all be reached from each
+//! other following oneway streets, a weakly connected component ignores
+//! the direction of travel.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::network::StreetNetwork;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Following the direction of travel
+    Strong,
+    /// Whatever the direction of travel
+    Weak,
+}
+
+impl Connectivity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Connectivity::Strong => "strong",
+            Connectivity::Weak => "weak",
+        }
+    }
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Connectivity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strong" => Ok(Connectivity::Strong),
+            "weak" => Ok(Connectivity::Weak),
+            _ => Err(format!("Unknown connectivity {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Sizes of the components of a network
+pub struct ComponentStats {
+    pub connectivity: Connectivity,
+    /// Number of nodes of each component, largest first
+    pub sizes: Vec<usize>,
+    /// Number of nodes of the network
+    pub nodes: usize,
+}
+
+impl ComponentStats {
+    /// Number of components
+    pub fn count(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// Share of the nodes in the largest component, 0 for an empty network
+    pub fn largest_share(&self) -> f64 {
+        match self.sizes.first() {
+            Some(size) => *size as f64 / self.nodes as f64,
+            None => 0.0,
+        }
+    }
+
+    /// Number of components made of a single node
+    pub fn isolated(&self) -> usize {
+        self.sizes.iter().filter(|s| **s == 1).count()
+    }
+}
+
+/// Strongly connected components with Tarjan's algorithm, in the order
+/// they are completed
+fn strong(network: &StreetNetwork) -> Vec<usize> {
+    let size = network.node_idx.len();
+    let mut index = vec![usize::MAX; size];
+    let mut low = vec![0; size];
+    let mut on_stack = vec![false; size];
+    let mut stack = vec![];
+    let mut component = vec![usize::MAX; size];
+    let (mut visited, mut found) = (0, 0);
+
+    for root in 0..size {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        // (node, next outgoing edge to look at)
+        let mut calls = vec![(root, 0)];
+        index[root] = visited;
+        low[root] = visited;
+        visited += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some((node, next)) = calls.last_mut() {
+            let node = *node;
+            let out = network.out_edges(node);
+            if *next < out.len() {
+                let to = network.edges[out[*next]].to;
+                *next += 1;
+                if index[to] == usize::MAX {
+                    index[to] = visited;
+                    low[to] = visited;
+                    visited += 1;
+                    stack.push(to);
+                    on_stack[to] = true;
+                    calls.push((to, 0));
+                } else if on_stack[to] {
+                    low[node] = low[node].min(index[to]);
+                }
+                continue;
+            }
+
+            calls.pop();
+            if let Some((parent, _)) = calls.last() {
+                low[*parent] = low[*parent].min(low[node]);
+            }
+            if low[node] == index[node] {
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component[member] = found;
+                    if member == node {
+                        break;
+                    }
+                }
+                found += 1;
+            }
+        }
+    }
+    component
+}
+
+/// Weakly connected components with a union-find, numbered by their
+/// root
+fn weak(network: &StreetNetwork) -> Vec<usize> {
+    fn root(parent: &mut [usize], mut node: usize) -> usize {
+        while parent[node] != node {
+            parent[node] = parent[parent[node]];
+            node = parent[node];
+        }
+        node
+    }
+
+    let mut parent = (0..network.node_idx.len()).collect::<Vec<usize>>();
+    let copies = network.copies.iter()
+    .flat_map(|(id, copies)| copies.iter().map(move |c| (network.node_idx.get(*id), *c)));
+    let links = network.edges.iter().map(|e| (e.from, e.to)).chain(copies).collect::<Vec<(usize, usize)>>();
+    for (a, b) in links {
+        let (a, b) = (root(&mut parent, a), root(&mut parent, b));
+        if a != b {
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+    (0..parent.len()).map(|n| root(&mut parent, n)).collect()
+}
+
+/// Component of each graph node, 0 for the largest one
+///
+/// Components are numbered by decreasing number of OSM nodes, then by
+/// their smallest OSM id. Copies of a junction split by turn restrictions
+/// always are in the same weak component, but may be in different strong
+/// ones.
+pub fn components(network: &StreetNetwork, connectivity: Connectivity) -> Vec<usize> {
+    let raw = match connectivity {
+        Connectivity::Strong => strong(network),
+        Connectivity::Weak => weak(network),
+    };
+
+    // OSM nodes of each raw component
+    let mut members: HashMap<usize, Vec<i64>> = HashMap::new();
+    for (node, c) in raw.iter().enumerate() {
+        members.entry(*c).or_default().push(network.node_idx.node(node));
+    }
+    let mut order = members.into_iter()
+    .map(|(c, mut ids)| {
+        ids.sort_unstable();
+        ids.dedup();
+        (c, ids.len(), ids[0])
+    })
+    .collect::<Vec<(usize, usize, i64)>>();
+    order.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)));
+
+    let renumbered = order.iter().enumerate()
+    .map(|(i, (c, _, _))| (*c, i))
+    .collect::<HashMap<usize, usize>>();
+    raw.iter().map(|c| renumbered[c]).collect()
+}
+
+/// Component of every node by OSM id, 0 for the largest one
+///
+/// A junction split by turn restrictions gets the component of its
+/// original graph node, nodes removed by
+/// [`StreetNetwork::simplify`] the one of the edge they are on.
+pub fn component_ids(network: &StreetNetwork, connectivity: Connectivity) -> HashMap<i64, usize> {
+    let ids = components(network, connectivity);
+    let mut found = HashMap::new();
+    for edge in &network.edges {
+        for piece in edge.pieces.iter().filter(|p| network.shape_nodes.contains_key(&p.node)) {
+            found.insert(piece.node, ids[edge.from]);
+        }
+    }
+    for (node, c) in ids.iter().enumerate() {
+        let id = network.node_idx.node(node);
+        if network.node_idx.find(id) == Some(node) {
+            found.insert(id, *c);
+        }
+    }
+    found
+}
+
+/// Number of nodes of each component
+pub fn stats(network: &StreetNetwork, connectivity: Connectivity) -> ComponentStats {
+    let ids = component_ids(network, connectivity);
+    let mut sizes = vec![0; ids.values().max().map_or(0, |m| m + 1)];
+    for c in ids.values() {
+        sizes[*c] += 1;
+    }
+    // Components of junction copies alone have no node of their own
+    sizes.retain(|s| *s > 0);
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+
+    ComponentStats { connectivity, sizes, nodes: ids.len() }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::RoutingProfile;
+
+    /// Two way street 1-2-3 with a oneway spur 3-4, a oneway loop 5-6-7
+    /// joined to 1 by the oneway 1-5, and an island 8-9
+    const ISLANDS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.000" lon="0.003"/>
+      <node id="5" lat="0.001" lon="0.000"/>
+      <node id="6" lat="0.002" lon="0.000"/>
+      <node id="7" lat="0.002" lon="0.001"/>
+      <node id="8" lat="0.010" lon="0.010"/>
+      <node id="9" lat="0.010" lon="0.011"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+      <way id="11"><nd ref="3"/><nd ref="4"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+      <way id="12"><nd ref="1"/><nd ref="5"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+      <way id="13"><nd ref="5"/><nd ref="6"/><nd ref="7"/><nd ref="5"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+      <way id="14"><nd ref="8"/><nd ref="9"/><tag k="highway" v="residential"/></way>
+    </osm>"#;
+
+    #[test]
+    fn islands_and_oneway_traps() {
+        let map = Map::from_xml(ISLANDS.as_bytes()).unwrap();
+        let mut network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let weak = network.component_ids(Connectivity::Weak);
+        assert_eq!(weak.len(), 9);
+        assert!((1..=7).all(|n| weak[&n] == 0));
+        assert_eq!((weak[&8], weak[&9]), (1, 1));
+
+        let strong = network.component_ids(Connectivity::Strong);
+        assert_eq!((strong[&1], strong[&2], strong[&3]), (0, 0, 0));
+        assert!([5, 6, 7].iter().all(|n| strong[n] == 1));
+        assert_eq!(strong[&8], 2);
+        assert_ne!(strong[&4], strong[&3]);
+
+        let stats = network.component_stats(Connectivity::Strong);
+        assert_eq!(stats.sizes, vec![3, 3, 2, 1]);
+        assert_eq!((stats.count(), stats.isolated(), stats.nodes), (4, 1, 9));
+        assert!((stats.largest_share() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(network.component_stats(Connectivity::Weak).sizes, vec![7, 2]);
+
+        assert_eq!(network.keep_largest_component(Connectivity::Strong), 6);
+        assert_eq!(network.node_count(), 3);
+        assert_eq!(network.route(1, 3).unwrap().nodes, vec![1, 2, 3]);
+        assert!(network.route(1, 5).is_err());
+        assert_eq!(network.intersections().iter().map(|i| i.id).collect::<Vec<i64>>(), vec![1, 3]);
+    }
+}
@@ -0,0 +1,110 @@
+/* geo.rs */
+//! Geodesic helpers on a spherical earth.
+//!
+//! Every angle is in degrees and every distance is in meters.
+
+/// Mean earth radius in meters
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Length of one degree of latitude in meters
+pub const METERS_PER_DEGREE: f64 = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+
+/// Great circle distance between two points (haversine formula)
+pub fn distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let dlat = (lat2 - lat1).to_radians();
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+/// Initial bearing from the first point toward the second one
+///
+/// The result is in `[0, 360)`, 0 is north and 90 is east.
+pub fn bearing(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let dlon = (lon2 - lon1).to_radians();
+    let y = dlon.sin() * phi2.cos();
+    let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * dlon.cos();
+    (y.atan2(x).to_degrees() + 360.0) % 360.0
+}
+
+/// Point reached after travelling `distance` from the point along `bearing`
+///
+/// Returns the (lat, lon) pair of the destination.
+pub fn destination(lat: f64, lon: f64, bearing: f64, distance: f64) -> (f64, f64) {
+    let delta = distance / EARTH_RADIUS;
+    let theta = bearing.to_radians();
+    let phi1 = lat.to_radians();
+    let lambda1 = lon.to_radians();
+
+    let phi2 = (phi1.sin() * delta.cos() + phi1.cos() * delta.sin() * theta.cos()).asin();
+    let lambda2 = lambda1
+        + (theta.sin() * delta.sin() * phi1.cos()).atan2(delta.cos() - phi1.sin() * phi2.sin());
+
+    // Normalize longitude into [-180, 180)
+    let lon2 = (lambda2.to_degrees() + 540.0) % 360.0 - 180.0;
+    (phi2.to_degrees(), lon2)
+}
+
+/// Whether the point lies inside the closed ring of (lat, lon) pairs
+///
+/// Even-odd rule on the plane of latitudes and longitudes, fine for rings
+/// that do not cross the antimeridian.
+pub fn ring_contains(ring: &[(f64, f64)], lat: f64, lon: f64) -> bool {
+    let mut inside = false;
+    for w in ring.windows(2) {
+        let ((lat_a, lon_a), (lat_b, lon_b)) = (w[0], w[1]);
+        if (lat_a > lat) != (lat_b > lat)
+            && lon < (lon_b - lon_a) * (lat - lat_a) / (lat_b - lat_a) + lon_a
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Whether the segments a-b and c-d of (lat, lon) pairs cross, on the same
+/// plane as [`ring_contains`]
+pub fn segments_cross(a: (f64, f64), b: (f64, f64), c: (f64, f64), d: (f64, f64)) -> bool {
+    let side = |p: (f64, f64), q: (f64, f64), r: (f64, f64)| {
+        ((q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0)).signum()
+    };
+    side(a, b, c) * side(a, b, d) < 0.0 && side(c, d, a) * side(c, d, b) < 0.0
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn longitude_shrinks_with_latitude() {
+        // One degree of longitude on the equator
+        assert!((distance(0.0, 0.0, 0.0, 1.0) - 111_195.0).abs() < 1.0);
+        // ... and half of it at 60 degrees north
+        assert!((distance(60.0, 0.0, 60.0, 1.0) - 55_597.0).abs() < 100.0);
+        assert_eq!(distance(-6.2, 106.8, -6.2, 106.8), 0.0);
+    }
+
+    #[test]
+    fn bearing_of_cardinal_directions() {
+        assert!((bearing(0.0, 0.0, 1.0, 0.0) - 0.0).abs() < 1e-9);
+        assert!((bearing(0.0, 0.0, 0.0, 1.0) - 90.0).abs() < 1e-9);
+        assert!((bearing(0.0, 0.0, -1.0, 0.0) - 180.0).abs() < 1e-9);
+        assert!((bearing(0.0, 0.0, 0.0, -1.0) - 270.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn destination_is_inverse_of_distance_and_bearing() {
+        let (lat1, lon1) = (-6.2, 106.8);
+        let (lat2, lon2) = (-6.9, 107.6);
+
+        let d = distance(lat1, lon1, lat2, lon2);
+        let b = bearing(lat1, lon1, lat2, lon2);
+        let (lat, lon) = destination(lat1, lon1, b, d);
+
+        assert!((lat - lat2).abs() < 1e-9);
+        assert!((lon - lon2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,319 @@
+/* instructions.rs */
+//! Turn-by-turn instructions of a route.
+//!
+//! A new step starts wherever the route changes street or turns at an
+//! intersection. Roundabouts are summarized in a single step giving the
+//! exit to take, exits are counted from the ways touching each node of
+//! the roundabout in `StreetNetwork::nodeways_idx`.
+
+use std::fmt;
+
+use crate::geo;
+use crate::map::Way;
+use crate::network::StreetNetwork;
+use crate::route::Route;
+
+/// Below this angle in degrees, the route goes straight on
+const STRAIGHT: f64 = 20.0;
+const SLIGHT: f64 = 60.0;
+const TURN: f64 = 120.0;
+const SHARP: f64 = 170.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Maneuver {
+    Depart,
+    Continue,
+    SlightLeft,
+    Left,
+    SharpLeft,
+    SlightRight,
+    Right,
+    SharpRight,
+    UTurn,
+    /// Take the exit-th exit of the roundabout
+    Roundabout { exit: usize },
+    Arrive,
+}
+
+impl Maneuver {
+    /// Maneuver for a change of direction of `angle` degrees, positive to the right
+    pub fn from_angle(angle: f64) -> Maneuver {
+        let magnitude = angle.abs();
+        let right = angle > 0.0;
+        match magnitude {
+            m if m < STRAIGHT => Maneuver::Continue,
+            m if m < SLIGHT => if right { Maneuver::SlightRight } else { Maneuver::SlightLeft },
+            m if m < TURN => if right { Maneuver::Right } else { Maneuver::Left },
+            m if m < SHARP => if right { Maneuver::SharpRight } else { Maneuver::SharpLeft },
+            _ => Maneuver::UTurn,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Maneuver::Depart => "depart",
+            Maneuver::Continue => "continue",
+            Maneuver::SlightLeft => "slight left",
+            Maneuver::Left => "left",
+            Maneuver::SharpLeft => "sharp left",
+            Maneuver::SlightRight => "slight right",
+            Maneuver::Right => "right",
+            Maneuver::SharpRight => "sharp right",
+            Maneuver::UTurn => "uturn",
+            Maneuver::Roundabout { .. } => "roundabout",
+            Maneuver::Arrive => "arrive",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Single navigation step
+pub struct Step {
+    pub maneuver: Maneuver,
+    /// Node where the maneuver takes place
+    pub node: i64,
+    /// (lat, lon) of the node
+    pub location: (f64, f64),
+    /// Name (or ref) of the street followed after the maneuver, empty when unnamed
+    pub name: String,
+    /// Bearing in degrees when leaving the node, 0 is north and 90 is east
+    pub bearing: f64,
+    /// Length in meters until the next step
+    pub distance: f64,
+    /// Travel time in seconds until the next step
+    pub duration: f64,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let onto = |f: &mut fmt::Formatter| {
+            if self.name.is_empty() { Ok(()) } else { write!(f, " onto {}", self.name) }
+        };
+        match self.maneuver {
+            Maneuver::Depart => {
+                write!(f, "Head {}", compass(self.bearing))?;
+                if !self.name.is_empty() {
+                    write!(f, " on {}", self.name)?;
+                }
+                Ok(())
+            },
+            Maneuver::Continue => {
+                write!(f, "Continue")?;
+                onto(f)
+            },
+            Maneuver::UTurn => {
+                write!(f, "Make a U-turn")?;
+                onto(f)
+            },
+            Maneuver::Roundabout { exit } => {
+                write!(f, "At the roundabout, take exit {}", exit)?;
+                onto(f)
+            },
+            Maneuver::Arrive => write!(f, "Arrive at destination"),
+            turn => {
+                write!(f, "Turn {}", turn.as_str())?;
+                onto(f)
+            },
+        }
+    }
+}
+
+/// Cardinal direction of a bearing
+fn compass(bearing: f64) -> &'static str {
+    const NAMES: [&str; 8] = ["north", "northeast", "east", "southeast", "south", "southwest", "west", "northwest"];
+    NAMES[((bearing + 22.5) / 45.0) as usize % 8]
+}
+
+/// Signed change of direction from bearing `a` to bearing `b`, in (-180, 180]
+fn turn_angle(a: f64, b: f64) -> f64 {
+    let angle = (b - a + 360.0) % 360.0;
+    if angle > 180.0 { angle - 360.0 } else { angle }
+}
+
+pub(crate) fn is_roundabout(way: Option<&Way>) -> bool {
+    way.and_then(|w| w.tags.get("junction"))
+    .map(|j| j == "roundabout" || j == "circular")
+    .unwrap_or(false)
+}
+
+fn street_name(way: Option<&Way>) -> String {
+    way.and_then(|w| w.tags.get("name").or_else(|| w.tags.get("ref")))
+    .cloned()
+    .unwrap_or_default()
+}
+
+/// Navigation steps of `route`, from the departure to the arrival
+pub fn steps(network: &StreetNetwork, route: &Route) -> Vec<Step> {
+    let way = |segment: usize| network.ways.get(&route.segments[segment].way);
+    let bearing = |segment: usize| {
+        let ((lat1, lon1), (lat2, lon2)) = (route.geometry[segment], route.geometry[segment + 1]);
+        geo::bearing(lat1, lon1, lat2, lon2)
+    };
+    let ways_at = |node: i64| network.nodeways_idx.get(&node).map(|w| w.as_slice()).unwrap_or(&[]);
+
+    let step = |maneuver, segment: usize, name: String, bearing: f64| Step {
+        maneuver,
+        node: route.nodes[segment],
+        location: route.geometry[segment],
+        name,
+        bearing,
+        distance: 0.0,
+        duration: 0.0,
+    };
+
+    if route.segments.is_empty() {
+        return vec![
+            step(Maneuver::Depart, 0, String::new(), 0.0),
+            step(Maneuver::Arrive, 0, String::new(), 0.0),
+        ];
+    }
+
+    // Steps with the index of the segment they start at
+    let mut steps = vec![(0, step(Maneuver::Depart, 0, street_name(way(0)), bearing(0)))];
+    // Exits passed since entering the current roundabout
+    let mut exits: Option<usize> = if is_roundabout(way(0)) { Some(0) } else { None };
+
+    for i in 1..route.segments.len() {
+        let (previous, current) = (way(i - 1), way(i));
+        let node = route.nodes[i];
+
+        match (is_roundabout(previous), is_roundabout(current)) {
+            (false, true) => {
+                exits = Some(0);
+                steps.push((i, step(Maneuver::Roundabout { exit: 0 }, i, String::new(), bearing(i))));
+            },
+            (true, true) => {
+                // Every way touching the roundabout that is not part of it is an exit
+                if ways_at(node).iter().any(|w| !is_roundabout(Some(w))) {
+                    exits = exits.map(|e| e + 1);
+                }
+            },
+            (true, false) => {
+                let maneuver = Maneuver::Roundabout { exit: exits.take().unwrap_or(0) + 1 };
+                let name = street_name(current);
+                match steps.last_mut() {
+                    Some((_, last)) if matches!(last.maneuver, Maneuver::Roundabout { .. }) => {
+                        last.maneuver = maneuver;
+                        last.name = name;
+                    },
+                    // The route starts on the roundabout
+                    _ => steps.push((i, step(maneuver, i, name, bearing(i)))),
+                }
+            },
+            (false, false) => {
+                let angle = turn_angle(bearing(i - 1), bearing(i));
+                let name = street_name(current);
+                let renamed = name != street_name(previous);
+                let intersection = ways_at(node).len() > 1;
+
+                if renamed || (intersection && angle.abs() >= STRAIGHT) {
+                    steps.push((i, step(Maneuver::from_angle(angle), i, name, bearing(i))));
+                }
+            },
+        }
+    }
+
+    let last = route.segments.len();
+    steps.push((last, step(Maneuver::Arrive, last, String::new(), bearing(last - 1))));
+
+    // Each step covers the segments up to the next step
+    let ends = steps.iter().skip(1).map(|(start, _)| *start).collect::<Vec<usize>>();
+    steps.into_iter().zip(ends.into_iter().chain(std::iter::once(last)))
+    .map(|((start, mut step), end)| {
+        step.distance = route.segments[start..end].iter().map(|s| s.distance).sum();
+        step.duration = route.segments[start..end].iter().map(|s| s.duration).sum();
+        step
+    })
+    .collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+
+    const STREETS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.001" lon="0.001"/>
+      <node id="4" lat="0.000" lon="0.002"/>
+      <node id="21" lat="0.0010" lon="0.0027"/>
+      <node id="22" lat="0.0007" lon="0.0030"/>
+      <node id="23" lat="0.0010" lon="0.0033"/>
+      <node id="24" lat="0.0013" lon="0.0030"/>
+      <node id="32" lat="0.000" lon="0.003"/>
+      <node id="33" lat="0.001" lon="0.004"/>
+      <node id="35" lat="0.002" lon="0.003"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="residential"/><tag k="name" v="Jalan A"/></way>
+      <way id="11"><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/><tag k="name" v="Jalan B"/></way>
+      <way id="12"><nd ref="2"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+      <way id="13"><nd ref="3"/><nd ref="21"/><tag k="highway" v="residential"/><tag k="name" v="Jalan C"/></way>
+      <way id="20">
+        <nd ref="21"/><nd ref="22"/><nd ref="23"/><nd ref="24"/><nd ref="21"/>
+        <tag k="highway" v="residential"/><tag k="junction" v="roundabout"/>
+      </way>
+      <way id="30"><nd ref="22"/><nd ref="32"/><tag k="highway" v="residential"/></way>
+      <way id="31"><nd ref="23"/><nd ref="33"/><tag k="highway" v="residential"/><tag k="name" v="Jalan Timur"/></way>
+      <way id="34"><nd ref="24"/><nd ref="35"/><tag k="highway" v="residential"/></way>
+    </osm>"#;
+
+    #[test]
+    fn turns_and_roundabout() {
+        let map = Map::from_xml(STREETS.as_bytes()).unwrap();
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+        let route = network.route(1, 33).unwrap();
+        assert_eq!(route.nodes, vec![1, 2, 3, 21, 22, 23, 33]);
+
+        let steps = network.instructions(&route);
+        let summary = steps.iter()
+        .map(|s| (s.maneuver, s.node, s.name.as_str()))
+        .collect::<Vec<(Maneuver, i64, &str)>>();
+        assert_eq!(summary, vec![
+            (Maneuver::Depart, 1, "Jalan A"),
+            (Maneuver::Left, 2, "Jalan B"),
+            (Maneuver::Right, 3, "Jalan C"),
+            (Maneuver::Roundabout { exit: 2 }, 21, "Jalan Timur"),
+            (Maneuver::Arrive, 33, ""),
+        ]);
+
+        assert!((steps[0].bearing - 90.0).abs() < 1e-6);
+        assert!((steps[1].bearing - 0.0).abs() < 1e-6);
+        assert!((steps[0].distance - 111.2).abs() < 0.1);
+        assert_eq!(steps[4].distance, 0.0);
+        let total = steps.iter().map(|s| s.distance).sum::<f64>();
+        assert!((total - route.distance).abs() < 1e-6);
+
+        let text = steps.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        assert_eq!(text[0], "Head east on Jalan A");
+        assert_eq!(text[1], "Turn left onto Jalan B");
+        assert_eq!(text[3], "At the roundabout, take exit 2 onto Jalan Timur");
+    }
+
+    #[test]
+    fn straight_through_intersection() {
+        let map = Map::from_xml(STREETS.as_bytes()).unwrap();
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+
+        let steps = network.instructions(&network.route(1, 4).unwrap());
+        let maneuvers = steps.iter().map(|s| s.maneuver).collect::<Vec<Maneuver>>();
+        // The street loses its name at 2 while going straight on
+        assert_eq!(maneuvers, vec![Maneuver::Depart, Maneuver::Continue, Maneuver::Arrive]);
+
+        let steps = network.instructions(&network.route(1, 1).unwrap());
+        let maneuvers = steps.iter().map(|s| s.maneuver).collect::<Vec<Maneuver>>();
+        assert_eq!(maneuvers, vec![Maneuver::Depart, Maneuver::Arrive]);
+    }
+
+    #[test]
+    fn maneuver_from_angle() {
+        assert_eq!(Maneuver::from_angle(5.0), Maneuver::Continue);
+        assert_eq!(Maneuver::from_angle(-30.0), Maneuver::SlightLeft);
+        assert_eq!(Maneuver::from_angle(90.0), Maneuver::Right);
+        assert_eq!(Maneuver::from_angle(-150.0), Maneuver::SharpLeft);
+        assert_eq!(Maneuver::from_angle(180.0), Maneuver::UTurn);
+        assert_eq!(turn_angle(350.0, 10.0), 20.0);
+        assert_eq!(turn_angle(10.0, 350.0), -20.0);
+    }
+}
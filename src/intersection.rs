@@ -0,0 +1,209 @@
+/* intersection.rs */
+//! Junctions of a street network.
+//!
+//! The streets meeting at a node are counted from the ways of the network
+//! in `StreetNetwork::nodeways_idx`, whatever their direction of travel.
+//! A node where exactly two streets meet is a bend or a joint between two
+//! ways, every other node is an intersection or a dead end.
+
+use std::fmt;
+
+use crate::geo;
+use crate::instructions;
+use crate::network::StreetNetwork;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntersectionKind {
+    /// A single street ends at the node
+    DeadEnd,
+    /// Three streets, T or Y shaped
+    Tee,
+    /// Four streets
+    Cross,
+    /// Five streets or more
+    MultiWay,
+    /// Node of a roundabout where another street joins it
+    Roundabout,
+}
+
+impl IntersectionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DeadEnd => "dead_end",
+            Self::Tee => "tee",
+            Self::Cross => "cross",
+            Self::MultiWay => "multi_way",
+            Self::Roundabout => "roundabout",
+        }
+    }
+}
+
+impl fmt::Display for IntersectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Street leaving an intersection
+pub struct Street {
+    /// Way the street belongs to
+    pub way: i64,
+    /// Name, or reference when unnamed
+    pub name: Option<String>,
+    /// Bearing in degrees from the intersection along the street
+    pub bearing: f64,
+    /// OSM id of the next node along the street
+    pub next: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Intersection {
+    /// OSM node id
+    pub id: i64,
+    pub lat: f64,
+    pub lon: f64,
+    pub kind: IntersectionKind,
+    /// One entry per street meeting at the node, by increasing bearing
+    pub streets: Vec<Street>,
+}
+
+impl Intersection {
+    /// Distinct names of the streets, by increasing bearing
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for name in self.streets.iter().filter_map(|s| s.name.clone()) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+/// Every intersection and dead end of the network, by node id
+pub fn intersections(network: &StreetNetwork) -> Vec<Intersection> {
+    let mut ids = network.nodeways_idx.keys().copied().collect::<Vec<i64>>();
+    ids.sort_unstable();
+    ids.into_iter().filter_map(|id| intersection(network, id)).collect()
+}
+
+/// Intersection at the node `id`, None when it is not one
+pub fn intersection(network: &StreetNetwork, id: i64) -> Option<Intersection> {
+    let ways = network.nodeways_idx.get(&id)?;
+    let (lat, lon) = network.location(id)?;
+
+    let mut streets: Vec<Street> = vec![];
+    for way in ways {
+        let closed = way.nodes.len() > 2 && way.nodes.first() == way.nodes.last();
+        let last = way.nodes.len() - 1;
+        for (i, _) in way.nodes.iter().enumerate().filter(|(_, n)| **n == id) {
+            let previous = match i {
+                0 if closed => Some(way.nodes[last - 1]),
+                0 => None,
+                _ => Some(way.nodes[i - 1]),
+            };
+            let next = match i {
+                i if i == last && closed => Some(way.nodes[1]),
+                i if i == last => None,
+                _ => Some(way.nodes[i + 1]),
+            };
+
+            for neighbour in previous.into_iter().chain(next) {
+                if neighbour == id || streets.iter().any(|s| s.next == neighbour) {
+                    continue;
+                }
+                let (n_lat, n_lon) = match network.location(neighbour) {
+                    Some(location) => location,
+                    None => continue,
+                };
+                streets.push(Street {
+                    way: way.id,
+                    name: way.tags.get("name").or_else(|| way.tags.get("ref")).cloned(),
+                    bearing: geo::bearing(lat, lon, n_lat, n_lon),
+                    next: neighbour,
+                });
+            }
+        }
+    }
+
+    let roundabout = ways.iter().any(|w| instructions::is_roundabout(Some(w)));
+    let kind = match streets.len() {
+        0 | 2 => return None,
+        1 => IntersectionKind::DeadEnd,
+        _ if roundabout => IntersectionKind::Roundabout,
+        3 => IntersectionKind::Tee,
+        4 => IntersectionKind::Cross,
+        _ => IntersectionKind::MultiWay,
+    };
+    streets.sort_by(|a, b| a.bearing.partial_cmp(&b.bearing).unwrap());
+
+    Some(Intersection { id, lat, lon, kind, streets })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::RoutingProfile;
+
+    #[test]
+    fn junctions_are_classified() {
+        // Main street 1-2-3-4 crossed at 2 by 5-2-6, side street 3-7,
+        // bend at 8 and a roundabout 10-11-12 joined at 10 by 4-10
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.000" lon="0.000"/>
+          <node id="2" lat="0.000" lon="0.001"/>
+          <node id="3" lat="0.000" lon="0.002"/>
+          <node id="4" lat="0.000" lon="0.003"/>
+          <node id="5" lat="0.001" lon="0.001"/>
+          <node id="6" lat="-0.001" lon="0.001"/>
+          <node id="7" lat="0.001" lon="0.002"/>
+          <node id="8" lat="0.002" lon="0.003"/>
+          <node id="10" lat="0.000" lon="0.004"/>
+          <node id="11" lat="0.0005" lon="0.0045"/>
+          <node id="12" lat="-0.0005" lon="0.0045"/>
+          <way id="20"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><tag k="highway" v="residential"/><tag k="name" v="Main Street"/></way>
+          <way id="21"><nd ref="5"/><nd ref="2"/><nd ref="6"/><tag k="highway" v="residential"/><tag k="name" v="Cross Street"/></way>
+          <way id="22"><nd ref="3"/><nd ref="7"/><nd ref="8"/><tag k="highway" v="residential"/><tag k="ref" v="D7"/></way>
+          <way id="23"><nd ref="4"/><nd ref="10"/><tag k="highway" v="residential"/><tag k="name" v="Main Street"/></way>
+          <way id="24">
+            <nd ref="10"/><nd ref="11"/><nd ref="12"/><nd ref="10"/>
+            <tag k="highway" v="residential"/><tag k="junction" v="roundabout"/>
+          </way>
+        </osm>"#;
+        let map = Map::from_xml(xml.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let found = network.intersections();
+        let kinds = found.iter().map(|i| (i.id, i.kind)).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![
+            (1, IntersectionKind::DeadEnd),
+            (2, IntersectionKind::Cross),
+            (3, IntersectionKind::Tee),
+            (5, IntersectionKind::DeadEnd),
+            (6, IntersectionKind::DeadEnd),
+            (8, IntersectionKind::DeadEnd),
+            (10, IntersectionKind::Roundabout),
+        ]);
+
+        let cross = &found[1];
+        assert_eq!((cross.lat, cross.lon), (0.0, 0.001));
+        assert_eq!(cross.names(), vec!["Cross Street", "Main Street"]);
+        let bearings = cross.streets.iter().map(|s| s.bearing.round()).collect::<Vec<f64>>();
+        assert_eq!(bearings, vec![0.0, 90.0, 180.0, 270.0]);
+        assert_eq!(cross.streets[1].next, 3);
+
+        assert_eq!(found[2].names(), vec!["D7", "Main Street"]);
+        assert_eq!(found[6].streets.len(), 3);
+        assert_eq!(network.intersection(4), None);
+        assert_eq!(network.intersection(99), None);
+
+        // Collapsed nodes are not intersections either
+        let mut simple = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        simple.simplify();
+        assert_eq!(simple.intersections(), found);
+    }
+}
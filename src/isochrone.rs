@@ -0,0 +1,343 @@
+/* isochrone.rs */
+//! Reachability within a cost budget and isochrone polygons.
+//!
+//! Polygons are alpha shapes: the reached points (nodes, plus the point
+//! where the budget runs out along each edge leaving the reached area) are
+//! triangulated, triangles wider than `alpha` are dropped and the outline
+//! of the remaining triangles gives the rings.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use spade::{DelaunayTriangulation, Point2, Triangulation};
+
+use crate::geo;
+use crate::network::{Edge, StreetNetwork};
+use crate::profile::Metric;
+use crate::route::RouteError;
+use crate::search;
+
+#[derive(Clone, Debug, PartialEq)]
+/// Polygon of (lat, lon) pairs, rings are closed
+pub struct Polygon {
+    /// Counter-clockwise outer ring
+    pub exterior: Vec<(f64, f64)>,
+    /// Clockwise holes
+    pub interiors: Vec<Vec<(f64, f64)>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Area reachable within `cutoff`
+pub struct Isochrone {
+    /// Budget in meters or seconds depending on the metric
+    pub cutoff: f64,
+    pub polygons: Vec<Polygon>,
+}
+
+/// Cost of an edge in meters or seconds
+pub(crate) fn edge_cost(metric: Metric) -> impl Fn(usize, &Edge) -> Option<f64> {
+    move |_, edge| Some(match metric {
+        Metric::Distance => edge.distance,
+        Metric::Time => edge.duration,
+    })
+}
+
+/// Every node reachable from `start` within `budget`, with its cost
+///
+/// The budget is in meters for [`Metric::Distance`] and in seconds for
+/// [`Metric::Time`]. Nodes are sorted by increasing cost.
+pub fn reachable(network: &StreetNetwork, start: i64, budget: f64, metric: Metric) -> Result<Vec<(i64, f64)>, RouteError> {
+    let source = network.node_idx.find(start).ok_or(RouteError::UnknownNode(start))?;
+    let tree = search::dijkstra(network, &[(source, 0.0)], budget, edge_cost(metric), |_| false);
+
+    // Split junctions have several graph nodes, keep the cheapest
+    let mut costs: HashMap<i64, f64> = HashMap::new();
+    for (id, cost) in tree.cost.iter().enumerate().filter(|(_, c)| c.is_finite()) {
+        let best = costs.entry(network.node_idx.node(id)).or_insert(*cost);
+        *best = best.min(*cost);
+    }
+
+    let mut nodes = costs.into_iter().collect::<Vec<(i64, f64)>>();
+    nodes.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(a.0.cmp(&b.0)));
+    Ok(nodes)
+}
+
+/// Isochrone polygons around `start`, one for each of `cutoffs`
+///
+/// `alpha` is the largest circumradius in meters of the triangles kept in
+/// the shape, smaller values follow the streets more closely. A cutoff
+/// reaching less than three distinct points has no polygon.
+pub fn isochrones(network: &StreetNetwork, start: i64, cutoffs: &[f64], metric: Metric, alpha: f64) -> Result<Vec<Isochrone>, RouteError> {
+    let source = network.node_idx.find(start).ok_or(RouteError::UnknownNode(start))?;
+    let limit = cutoffs.iter().cloned().fold(0.0, f64::max);
+    let cost = edge_cost(metric);
+    let tree = search::dijkstra(network, &[(source, 0.0)], limit, &cost, |_| false);
+
+    let (lat0, lon0) = network.coords[source];
+    let projection = Projection::new(lat0, lon0);
+
+    let isochrones = cutoffs.iter()
+    .map(|cutoff| {
+        let mut points = vec![];
+        for (id, reached) in tree.cost.iter().enumerate() {
+            if *reached > *cutoff {
+                continue;
+            }
+            points.push(projection.project(network.coords[id]));
+
+            // Nodes of the simplified edges leaving the node, up to where
+            // the budget runs out
+            for i in network.out_edges(id) {
+                let mut a = projection.project(network.coords[id]);
+                let mut spent = *reached;
+                for piece in network.pieces(&network.edges[*i]) {
+                    let length = match metric {
+                        Metric::Distance => piece.distance,
+                        Metric::Time => piece.duration,
+                    };
+                    let b = projection.project(network.location(piece.node).unwrap_or_default());
+                    if spent + length > *cutoff {
+                        let t = (cutoff - spent) / length;
+                        points.push([a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]);
+                        break;
+                    }
+                    points.push(b);
+                    spent += length;
+                    a = b;
+                }
+            }
+        }
+
+        let polygons = alpha_shape(&points, alpha).into_iter()
+        .map(|(exterior, interiors)| Polygon {
+            exterior: exterior.iter().map(|p| projection.unproject(*p)).collect(),
+            interiors: interiors.iter()
+            .map(|ring| ring.iter().map(|p| projection.unproject(*p)).collect())
+            .collect(),
+        })
+        .collect();
+
+        Isochrone { cutoff: *cutoff, polygons }
+    })
+    .collect();
+
+    Ok(isochrones)
+}
+
+/// GeoJSON FeatureCollection with one MultiPolygon feature per isochrone
+pub fn to_geojson(isochrones: &[Isochrone]) -> Value {
+    let ring = |r: &Vec<(f64, f64)>| r.iter().map(|(lat, lon)| json!([lon, lat])).collect::<Vec<Value>>();
+
+    let features = isochrones.iter()
+    .map(|iso| {
+        let polygons = iso.polygons.iter()
+        .map(|p| std::iter::once(ring(&p.exterior)).chain(p.interiors.iter().map(ring)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+        json!({
+            "type": "Feature",
+            "properties": { "cutoff": iso.cutoff },
+            "geometry": { "type": "MultiPolygon", "coordinates": polygons },
+        })
+    })
+    .collect::<Vec<Value>>();
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// Local planar coordinates in meters around a point
+pub(crate) struct Projection {
+    lat0: f64,
+    lon0: f64,
+    cos_lat: f64,
+}
+
+impl Projection {
+    pub(crate) fn new(lat0: f64, lon0: f64) -> Self {
+        Projection { lat0, lon0, cos_lat: lat0.to_radians().cos() }
+    }
+
+    pub(crate) fn project(&self, (lat, lon): (f64, f64)) -> [f64; 2] {
+        [
+            (lon - self.lon0) * self.cos_lat * geo::METERS_PER_DEGREE,
+            (lat - self.lat0) * geo::METERS_PER_DEGREE,
+        ]
+    }
+
+    pub(crate) fn unproject(&self, p: [f64; 2]) -> (f64, f64) {
+        (
+            p[1] / geo::METERS_PER_DEGREE + self.lat0,
+            p[0] / (self.cos_lat * geo::METERS_PER_DEGREE) + self.lon0,
+        )
+    }
+}
+
+type Ring = Vec<[f64; 2]>;
+
+fn signed_area(ring: &[[f64; 2]]) -> f64 {
+    ring.windows(2).map(|w| w[0][0] * w[1][1] - w[1][0] * w[0][1]).sum::<f64>() / 2.0
+}
+
+fn contains(ring: &[[f64; 2]], p: [f64; 2]) -> bool {
+    let mut inside = false;
+    for w in ring.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        if (a[1] > p[1]) != (b[1] > p[1]) && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0] {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Outline of the Delaunay triangles of `points` with a circumradius up to `alpha`
+///
+/// Returns (exterior, holes) pairs of closed rings.
+fn alpha_shape(points: &[[f64; 2]], alpha: f64) -> Vec<(Ring, Vec<Ring>)> {
+    let vertices = points.iter().map(|p| Point2::new(p[0], p[1])).collect();
+    let triangulation: DelaunayTriangulation<Point2<f64>> = match DelaunayTriangulation::bulk_load(vertices) {
+        Ok(t) => t,
+        Err(_) => return vec![],
+    };
+
+    // Directed edges of the kept triangles, counter-clockwise
+    let mut edges: Vec<(usize, usize)> = vec![];
+    let mut position: HashMap<usize, [f64; 2]> = HashMap::new();
+    for face in triangulation.inner_faces() {
+        if face.circumcircle().1 > alpha * alpha {
+            continue;
+        }
+        let vertices = face.vertices();
+        for v in &vertices {
+            let p = v.position();
+            position.insert(v.fix().index(), [p.x, p.y]);
+        }
+        let ids = vertices.iter().map(|v| v.fix().index()).collect::<Vec<usize>>();
+        edges.extend_from_slice(&[(ids[0], ids[1]), (ids[1], ids[2]), (ids[2], ids[0])]);
+    }
+
+    // The outline is made of the edges whose twin is not kept
+    let inner = edges.iter().copied().collect::<std::collections::HashSet<(usize, usize)>>();
+    let mut outline: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (a, b) in &edges {
+        if !inner.contains(&(*b, *a)) {
+            outline.entry(*a).or_default().push(*b);
+        }
+    }
+
+    let mut rings: Vec<Ring> = vec![];
+    let mut starts = outline.keys().copied().collect::<Vec<usize>>();
+    starts.sort_unstable();
+    for start in starts {
+        while let Some(next) = outline.get_mut(&start).and_then(|n| n.pop()) {
+            let mut ring = vec![start, next];
+            let mut current = next;
+            while current != start {
+                match outline.get_mut(&current).and_then(|n| n.pop()) {
+                    Some(next) => {
+                        ring.push(next);
+                        current = next;
+                    },
+                    None => break,
+                }
+            }
+            if current == start && ring.len() >= 4 {
+                rings.push(ring.iter().map(|id| position[id]).collect());
+            }
+        }
+    }
+
+    // Counter-clockwise rings are exteriors, the others are holes
+    let (exteriors, holes): (Vec<Ring>, Vec<Ring>) = rings.into_iter().partition(|r| signed_area(r) > 0.0);
+    let mut shapes = exteriors.into_iter().map(|r| (r, vec![])).collect::<Vec<(Ring, Vec<Ring>)>>();
+    for hole in holes {
+        let owner = shapes.iter_mut()
+        .filter(|(exterior, _)| hole.iter().any(|p| !exterior.contains(p) && contains(exterior, *p)))
+        .min_by(|a, b| signed_area(&a.0).partial_cmp(&signed_area(&b.0)).unwrap());
+        if let Some((_, interiors)) = owner {
+            interiors.push(hole);
+        }
+    }
+    shapes
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::RoutingProfile;
+
+    /// Square grid of 5 x 5 nodes 0.001 degree apart
+    fn grid() -> Map {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?><osm version="0.6">"#);
+        for row in 0..5 {
+            for col in 0..5 {
+                xml += &format!(r#"<node id="{}" lat="{}" lon="{}"/>"#, row * 5 + col + 1, row as f64 * 0.001, col as f64 * 0.001);
+            }
+        }
+        for row in 0..5 {
+            let nodes = (0..5).map(|col| format!(r#"<nd ref="{}"/>"#, row * 5 + col + 1)).collect::<String>();
+            xml += &format!(r#"<way id="{}">{}<tag k="highway" v="residential"/></way>"#, 100 + row, nodes);
+        }
+        for col in 0..5 {
+            let nodes = (0..5).map(|row| format!(r#"<nd ref="{}"/>"#, row * 5 + col + 1)).collect::<String>();
+            xml += &format!(r#"<way id="{}">{}<tag k="highway" v="residential"/></way>"#, 200 + col, nodes);
+        }
+        xml += "</osm>";
+        Map::from_xml(xml.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn reachable_within_budget() {
+        let map = grid();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::foot());
+
+        // Center node, one block is 111 meters
+        let nodes = network.reachable(13, 120.0, Metric::Distance).unwrap();
+        assert_eq!(nodes[0], (13, 0.0));
+        let mut ids = nodes.iter().map(|(id, _)| *id).collect::<Vec<i64>>();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![8, 12, 13, 14, 18]);
+
+        // 5 km/h, a block takes 80 seconds
+        let nodes = network.reachable(13, 170.0, Metric::Time).unwrap();
+        assert_eq!(nodes.len(), 13);
+        assert!(nodes.windows(2).all(|w| w[0].1 <= w[1].1));
+
+        assert_eq!(network.reachable(99, 10.0, Metric::Time), Err(RouteError::UnknownNode(99)));
+    }
+
+    #[test]
+    fn isochrone_polygons_grow_with_cutoff() {
+        let map = grid();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::foot());
+
+        let isochrones = network.isochrones(13, &[100.0, 200.0, 1000.0], Metric::Time, 150.0).unwrap();
+        assert_eq!(isochrones.len(), 3);
+
+        let area = |iso: &Isochrone| iso.polygons.iter()
+        .map(|p| {
+            let (lat0, lon0) = p.exterior[0];
+            let ring = p.exterior.iter().map(|c| Projection::new(lat0, lon0).project(*c)).collect::<Vec<[f64; 2]>>();
+            signed_area(&ring)
+        })
+        .sum::<f64>();
+
+        for iso in &isochrones {
+            assert_eq!(iso.polygons.len(), 1);
+            let exterior = &iso.polygons[0].exterior;
+            assert_eq!(exterior.first(), exterior.last());
+        }
+        assert!(area(&isochrones[0]) > 0.0);
+        assert!(area(&isochrones[0]) < area(&isochrones[1]));
+        assert!(area(&isochrones[1]) < area(&isochrones[2]));
+        // The whole grid, 444 meters wide
+        assert!((area(&isochrones[2]) - 444.8 * 444.8).abs() < 100.0);
+
+        let geojson = to_geojson(&isochrones);
+        assert_eq!(geojson["features"].as_array().unwrap().len(), 3);
+        assert_eq!(geojson["features"][1]["properties"]["cutoff"], 200.0);
+        assert_eq!(geojson["features"][0]["geometry"]["type"], "MultiPolygon");
+        let first = &geojson["features"][2]["geometry"]["coordinates"][0][0][0];
+        assert!(first[0].as_f64().unwrap() >= 0.0 && first[1].as_f64().unwrap() <= 0.004 + 1e-9);
+    }
+}
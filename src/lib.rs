@@ -1,10 +1,33 @@
-/**
+/*
 * Module Python
 */
 
+pub mod alternatives;
+pub mod area;
+pub mod avoid;
+pub mod centrality;
+pub mod components;
+pub mod geo;
+pub mod instructions;
+pub mod intersection;
+pub mod isochrone;
 pub mod map;
+pub mod matching;
+pub mod matrix;
+pub mod maxspeed;
 pub mod structure;
 #[cfg(feature = "extension-module")]
 pub mod python;
 pub mod network;
+pub mod pbf;
+pub mod profile;
 pub mod queries;
+pub mod restriction;
+pub mod route;
+pub mod simplify;
+pub mod snap;
+pub mod search;
+pub mod spatial;
+pub mod stats;
+pub mod traffic;
+pub mod via;
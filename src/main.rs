@@ -5,6 +5,7 @@ use std::path::Path;
 
 use openstreet::network::StreetNetwork;
 use openstreet::map::Map;
+use openstreet::profile::RoutingProfile;
 
 // NOTE: For now this can't be used because we prefer the pickle version.
 fn main() {
@@ -19,20 +20,29 @@ fn main() {
         None => panic!("Filepath not given, please add OSM map file as argument"),
     };
 
+    // Optional routing profile: car, bicycle or foot
+    let profile = env::args().nth(3).map(|name| match RoutingProfile::by_name(&name) {
+        Some(p) => p,
+        None => panic!("Unknown profile {}, expected car, bicycle or foot", name),
+    });
+
 
     println!("Creating Map!");
-    let map = Map::new(mapfilepath);
+    let map = Map::new(mapfilepath).expect("Map read failed");
 
     println!("Into StreetNetwork!");
-    let gra = StreetNetwork::new(&map, vec![
-        "primary"      , "secondary"      , "tertiary",
-        "primary_link" , "secondary_link" , "tertiary_link",
-        "residential"  , "service"
-    ]);
+    let gra = match profile {
+        Some(profile) => StreetNetwork::from_profile(&map, profile),
+        None => StreetNetwork::new(&map, vec![
+            "primary"      , "secondary"      , "tertiary",
+            "primary_link" , "secondary_link" , "tertiary_link",
+            "residential"  , "service"
+        ]),
+    };
 
     let path: &Path = Path::new(&outpath);
     let bytes = serde_json::to_string(&gra).unwrap();
-    fs::write(path, bytes);
+    fs::write(path, bytes).expect("File write failed");
 
     // let gra5: StreetNetwork = serde_json::from_str(std::str::from_utf8(&bytes2).unwrap()).unwrap();
 
@@ -42,6 +52,6 @@ fn main() {
 
     let bytes2 = fs::read(path).expect("File read failed");
     // let mut gra2: StreetNetwork = serde_json::from_str(std::str::from_utf8(&bytes2).unwrap()).unwrap();
-    let mut gra2 = StreetNetwork::deserialize(bytes2);
+    let gra2 = StreetNetwork::deserialize(bytes2);
     println!("{:?}", gra2.shortest_path(4137262376, 4137262384));
 }
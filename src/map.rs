@@ -1,13 +1,18 @@
 /* Map */
 
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::collections::HashMap;
+use std::io::BufRead;
 
 use osm_xml as osm;
 use fnv::FnvHashMap;
 use serde::{Serialize, Deserialize};
 
+use crate::area;
+use crate::geo;
+use crate::pbf;
 use crate::queries::Builder as QueryBuilder;
+use crate::spatial::SpatialIndex;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// OpenStreet Map object
@@ -37,6 +42,23 @@ impl From<&osm::Node> for Node {
     }
 }
 
+impl Node {
+    /// Great circle distance to `other` in meters
+    pub fn distance_to(&self, other: &Node) -> f64 {
+        geo::distance(self.lat, self.lon, other.lat, other.lon)
+    }
+
+    /// Initial bearing toward `other` in degrees, 0 is north and 90 is east
+    pub fn bearing_to(&self, other: &Node) -> f64 {
+        geo::bearing(self.lat, self.lon, other.lat, other.lon)
+    }
+
+    /// (lat, lon) reached after travelling `distance` meters along `bearing`
+    pub fn destination(&self, bearing: f64, distance: f64) -> (f64, f64) {
+        geo::destination(self.lat, self.lon, bearing, distance)
+    }
+}
+
 // Taken from https://github.com/orva/osm-xml/blob/6e0d7f6d932f353ecb5d32a54a129240cbca7e99/src/polygon.rs
 
 struct Rule {
@@ -49,8 +71,8 @@ impl Rule {
     fn has_matching_value(&self, tval: &str) -> bool {
         match self.polygon {
             RuleType::All => true,
-            RuleType::Whitelist => self.values.iter().any(|val| *val != "" && *val == tval),
-            RuleType::Blacklist => !self.values.iter().any(|val| *val == tval),
+            RuleType::Whitelist => self.values.iter().any(|val| !val.is_empty() && *val == tval),
+            RuleType::Blacklist => !self.values.contains(&tval),
         }
     }
 }
@@ -205,20 +227,32 @@ pub struct Way {
 
 impl Way {
     pub fn nodes(&self) -> Vec<i64> {
-        self.nodes.iter()
-        .map(|n| *n)
-        .collect::<Vec<i64>>()
+        self.nodes.to_vec()
+    }
+
+    /// Length of the way in meters
+    ///
+    /// Nodes that are missing from `map` (e.g. clipped by the extract
+    /// boundary) are skipped.
+    pub fn length(&self, map: &Map) -> f64 {
+        let nodes = self.nodes.iter()
+        .filter_map(|id| map.node(*id))
+        .collect::<Vec<&Node>>();
+
+        nodes.windows(2)
+        .map(|w| w[0].distance_to(w[1]))
+        .sum()
     }
 
     pub fn is_polygon(&self) -> bool {
-        if self.nodes.len() > 0 && self.nodes.first() == self.nodes.last() {
+        if !self.nodes.is_empty() && self.nodes.first() == self.nodes.last() {
             return true;
         }
 
         RULES.iter()
         .any(|rule| {
             let mut r = false;
-            if let Some(tagval) = self.tags.get(rule.key).map(|v| v.clone()) {
+            if let Some(tagval) = self.tags.get(rule.key).cloned() {
                 r = rule.has_matching_value(&tagval);
                 // println!("Rule key {} tagval {} => {}", rule.key, tagval, r);
             }
@@ -249,6 +283,95 @@ impl From<&osm::Way> for Way {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Type of element referenced by a relation member
+pub enum MemberType {
+    Node,
+    Way,
+    Relation,
+}
+
+impl MemberType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Node => "node",
+            Self::Way => "way",
+            Self::Relation => "relation",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// Reference to an element that is part of a relation
+pub struct Member {
+    pub member_type: MemberType,
+    /// Id of the referenced element
+    pub id: osm::Id,
+    /// Role of the element inside the relation, e.g. "outer" or "via"
+    pub role: String,
+}
+
+impl From<&osm::Member> for Member {
+    fn from(member: &osm::Member) -> Self {
+        let (member_type, reference, role) = match member {
+            osm::Member::Node(r, role) => (MemberType::Node, r, role),
+            osm::Member::Way(r, role) => (MemberType::Way, r, role),
+            osm::Member::Relation(r, role) => (MemberType::Relation, r, role),
+        };
+        let id = match reference {
+            osm::UnresolvedReference::Node(id)
+            | osm::UnresolvedReference::Way(id)
+            | osm::UnresolvedReference::Relation(id) => *id,
+        };
+
+        Member {
+            member_type,
+            id,
+            role: role.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// OpenStreet Relation object
+pub struct Relation {
+    pub id: osm::Id,
+    pub tags: HashMap<String, String>,
+    pub members: Vec<Member>,
+}
+
+impl Relation {
+    /// Value of the ``type`` tag, e.g. "multipolygon", "route" or "restriction"
+    pub fn relation_type(&self) -> Option<&str> {
+        self.tags.get("type").map(|v| v.as_str())
+    }
+
+    /// Members having role ``role``
+    pub fn members_with_role(&self, role: &str) -> Vec<&Member> {
+        self.members.iter()
+        .filter(|m| m.role == role)
+        .collect()
+    }
+}
+
+impl From<&osm::Relation> for Relation {
+    fn from(relation: &osm::Relation) -> Self {
+        let tagdict = HashMap::new();
+
+        Relation {
+            id: relation.id,
+            tags: relation.tags.iter()
+            .fold(tagdict, |mut d, t| {
+                d.insert(t.key.clone(), t.val.clone());
+                d
+            }),
+            members: relation.members.iter()
+            .map(|m| m.into())
+            .collect(),
+        }
+    }
+}
+
 #[derive(Clone, Deserialize)]
 /// OpenStreet Bounds object
 pub struct Bounds {
@@ -288,12 +411,59 @@ impl TaggableElement for Way {
     }
 }
 
+impl TaggableElement for Relation {
+    fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    fn get_tag_value(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(|v| v.as_str())
+    }
+}
+
+
+/// Error raised while reading an OSM file, whatever its format
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The content is not valid OSM XML
+    Xml(String),
+    /// The content is not valid OSM PBF or uses a feature we can not read
+    Pbf(pbf::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Read failed: {}", e),
+            Self::Xml(msg) => write!(f, "{}", msg),
+            Self::Pbf(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<pbf::Error> for Error {
+    fn from(e: pbf::Error) -> Self {
+        match e {
+            pbf::Error::Io(e) => Error::Io(e),
+            e => Error::Pbf(e),
+        }
+    }
+}
 
 #[derive(Clone)]
 /// Map provide parsing and storage for OSM format
 ///
-/// Map contains three main information: nodes, ways, and bounds.
-/// For ways and nodes, both must be accessed using query style
+/// Map contains four main information: nodes, ways, relations and bounds.
+/// Nodes, ways and relations must be accessed using query style
 /// or fluent interface.
 ///
 /// .. code-block:: python
@@ -310,13 +480,41 @@ pub struct Map {
     // inner: osm::OSM,
     nodes: FnvHashMap<i64, Node>,
     ways: FnvHashMap<i64, Way>,
+    relations: FnvHashMap<i64, Relation>,
     bounds: Option<Bounds>,
+    index: std::sync::Arc<SpatialIndex>,
 }
 
 impl Map {
-    pub fn new(path: String) -> Map {
-        let f = std::fs::File::open(path).unwrap();
-        let doc = osm::OSM::parse(f).unwrap();
+    /// Parse the OSM file at `path`
+    ///
+    /// Both OSM XML and OSM PBF are supported. The format is picked from
+    /// the file extension (``.osm``, ``.xml`` or ``.pbf``), otherwise
+    /// it is detected from the file content.
+    ///
+    /// Fails when the file can not be read or its content is invalid.
+    pub fn new(path: String) -> Result<Map, Error> {
+        let f = std::fs::File::open(&path)?;
+        let mut reader = std::io::BufReader::new(f);
+
+        let is_pbf = if path.ends_with(".pbf") {
+            true
+        } else if path.ends_with(".osm") || path.ends_with(".xml") {
+            false
+        } else {
+            pbf::sniff(reader.fill_buf()?)
+        };
+
+        if is_pbf {
+            Ok(Map::from_pbf(reader)?)
+        } else {
+            Map::from_xml(reader)
+        }
+    }
+
+    /// Parse OSM XML content
+    pub fn from_xml<R: std::io::Read>(reader: R) -> Result<Map, Error> {
+        let doc = osm::OSM::parse(reader).map_err(|e| Error::Xml(e.to_string()))?;
 
         // TODO: Remove runtime overhead by clone the xml parser
         let mut nodes: FnvHashMap<i64, Node> = FnvHashMap::default();
@@ -329,6 +527,11 @@ impl Map {
             ways.insert(*id, way.into());
         }
 
+        let mut relations: FnvHashMap<i64, Relation> = FnvHashMap::default();
+        for (id, relation) in &doc.relations {
+            relations.insert(*id, relation.into());
+        }
+
         let bounds = doc.bounds.map(|bounds| {
             Bounds {
                 minlat: bounds.minlat,
@@ -338,11 +541,31 @@ impl Map {
             }
         });
 
+        Ok(Map::from_parts(nodes, ways, relations, bounds))
+    }
+
+    /// Parse OSM PBF content
+    pub fn from_pbf<R: std::io::Read>(reader: R) -> pbf::Result<Map> {
+        let doc = pbf::parse(reader)?;
+
+        Ok(Map::from_parts(doc.nodes, doc.ways, doc.relations, doc.bounds))
+    }
+
+    fn from_parts(
+        nodes: FnvHashMap<i64, Node>,
+        ways: FnvHashMap<i64, Way>,
+        relations: FnvHashMap<i64, Relation>,
+        bounds: Option<Bounds>,
+    ) -> Map {
+        let index = SpatialIndex::new(&nodes, &ways);
+
         Map {
             // inner: doc,
-            ways: ways,
-            nodes: nodes,
-            bounds: bounds,
+            ways,
+            nodes,
+            relations,
+            bounds,
+            index: std::sync::Arc::new(index),
         }
     }
 
@@ -352,7 +575,7 @@ impl Map {
     /// Call :py:func:`WayQueryBuilder.get` when done to retrieve the result.
     /// See :py:class:`Map` documentation for example.
     pub fn ways(&self) -> QueryBuilder<Way> {
-        QueryBuilder::<Way>::new(self.ways.clone())
+        QueryBuilder::<Way>::with_index(self.ways.clone(), self.index.clone())
     }
 
     /// Return query builder to filter ways collection
@@ -361,7 +584,49 @@ impl Map {
     /// Call :py:func:`NodeQueryBuilder.get` when done to retrieve the result.
     /// See :py:class:`Map` documentation for example.
     pub fn nodes(&self) -> QueryBuilder<Node> {
-        QueryBuilder::<Node>::new(self.nodes.clone())
+        QueryBuilder::<Node>::with_index(self.nodes.clone(), self.index.clone())
+    }
+
+    /// Return query builder to filter relations collection
+    ///
+    /// Refer to RelationQueryBuilder methods for available filters.
+    /// Call :py:func:`RelationQueryBuilder.get` when done to retrieve the result.
+    /// See :py:class:`Map` documentation for example.
+    pub fn relations(&self) -> QueryBuilder<Relation> {
+        QueryBuilder::<Relation>::new(self.relations.clone())
+    }
+
+    /// Returns Node with given ``id`` if any
+    pub fn node(&self, id: osm::Id) -> Option<&Node> {
+        self.nodes.get(&id)
+    }
+
+    /// Returns Way with given ``id`` if any
+    pub fn way(&self, id: osm::Id) -> Option<&Way> {
+        self.ways.get(&id)
+    }
+
+    /// Returns Relation with given ``id`` if any
+    pub fn relation(&self, id: osm::Id) -> Option<&Relation> {
+        self.relations.get(&id)
+    }
+
+    /// Assemble polygon geometries of every area in the map
+    ///
+    /// Areas are the closed ways that pass [`area::is_area_way`] and the
+    /// relations that pass [`area::is_area_relation`]. Broken geometries
+    /// are returned as errors so that one bad relation does not hide the
+    /// others.
+    pub fn areas(&self) -> Vec<Result<area::Area, area::AreaError>> {
+        let ways = self.ways.values()
+        .filter(|w| area::is_area_way(w))
+        .map(|w| area::from_way(self, w));
+
+        let relations = self.relations.values()
+        .filter(|r| area::is_area_relation(r))
+        .map(|r| area::from_relation(self, r));
+
+        ways.chain(relations).collect()
     }
 
     /// Return bounds of map
@@ -374,6 +639,7 @@ impl Map {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::queries::QueryBuilder as _;
 
     macro_rules! map(
         { $($key:expr => $value:expr),+ } => {
@@ -441,6 +707,75 @@ mod test {
         assert!(way.is_polygon());
     }
 
+    #[test]
+    fn load_pbf_by_sniffing_content() {
+        let path = std::env::temp_dir().join(format!("openstreet-sniff-{}", std::process::id()));
+        std::fs::write(&path, crate::pbf::test::sample()).unwrap();
+        let map = Map::new(path.to_str().unwrap().to_owned());
+        std::fs::remove_file(&path).unwrap();
+        let map = map.unwrap();
+
+        let streets = map.ways().by_tag_eq("highway", "residential").get();
+        assert_eq!(streets.len(), 1);
+        assert_eq!(streets[0].nodes, vec![10, 11, 12]);
+        assert_eq!(map.nodes().by_tag_eq("amenity", "cafe").get().len(), 2);
+        assert!(map.bounds().is_some());
+    }
+
+    #[test]
+    fn malformed_xml_is_an_error() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="-6.20" lon="106.80">
+        </osm>"#;
+        assert!(matches!(Map::from_xml(xml.as_bytes()), Err(Error::Xml(_))));
+
+        let path = std::env::temp_dir().join(format!("openstreet-malformed-{}.osm", std::process::id()));
+        std::fs::write(&path, xml).unwrap();
+        let map = Map::new(path.to_str().unwrap().to_owned());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(map, Err(Error::Xml(_))));
+        assert!(matches!(Map::new("/nonexistent/map.osm".to_owned()), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn relations_keep_members_and_roles() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="-6.20" lon="106.80"/>
+          <node id="2" lat="-6.21" lon="106.81"/>
+          <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="primary"/></way>
+          <relation id="100">
+            <member type="way" ref="10" role=""/>
+            <member type="node" ref="2" role="stop"/>
+            <tag k="type" v="route"/>
+            <tag k="route" v="bus"/>
+          </relation>
+          <relation id="101">
+            <member type="relation" ref="100" role=""/>
+            <tag k="type" v="route_master"/>
+          </relation>
+        </osm>"#;
+        let map = Map::from_xml(xml.as_bytes()).unwrap();
+
+        let routes = map.relations().by_tag_eq("type", "route").get();
+        assert_eq!(routes.len(), 1);
+
+        let route = &routes[0];
+        assert_eq!(route.id, 100);
+        assert_eq!(route.members.len(), 2);
+        assert_eq!(route.members[0].member_type, MemberType::Way);
+        assert_eq!(route.members[0].id, 10);
+        let stops = route.members_with_role("stop");
+        assert_eq!(stops.len(), 1);
+        assert_eq!(stops[0].member_type, MemberType::Node);
+        assert_eq!(stops[0].id, 2);
+
+        let master = map.relations().by_id(101);
+        assert_eq!(master.relation_type(), Some("route_master"));
+        assert_eq!(master.members[0].member_type, MemberType::Relation);
+    }
+
     // #[test]
     // fn whitelist_val_included_is_polygon() {
     //     let way = Way {
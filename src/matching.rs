@@ -0,0 +1,395 @@
+/* matching.rs */
+//! Map matching of GPS traces with a hidden Markov model.
+//!
+//! The states of each point are the street segments around it, see
+//! [`snap::candidates`]. A state is more likely the closer its segment is
+//! to the point, and a transition the closer the route length between two
+//! states is to the straight distance between the points, after Newson and
+//! Krumm, "Hidden Markov Map Matching Through Noise and Sparseness" (2009).
+//! The Viterbi algorithm gives the matched states, the forward-backward
+//! algorithm their probability.
+
+use crate::geo;
+use crate::network::StreetNetwork;
+use crate::route::Route;
+use crate::snap::{self, Snap};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Position of a GPS trace
+pub struct GpsPoint {
+    pub lat: f64,
+    pub lon: f64,
+    /// Timestamp in seconds
+    pub time: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Parameters of the matching
+///
+/// ```ignore
+/// let options = MatchOptions::new().with_sigma(10.0).with_radius(80.0);
+/// let matched = network.match_trace(&trace, &options)?;
+/// ```
+pub struct MatchOptions {
+    /// Standard deviation in meters of the GPS positions
+    pub sigma: f64,
+    /// Scale in meters of the difference between route and straight
+    /// distances of consecutive points
+    pub beta: f64,
+    /// Largest distance in meters between a point and its street
+    pub radius: f64,
+    /// Largest number of street segments considered for each point
+    pub max_candidates: usize,
+    /// Largest speed in km/h between consecutive points, up to twice the
+    /// radius of slack
+    pub max_speed: f64,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions { sigma: 5.0, beta: 5.0, radius: 50.0, max_candidates: 8, max_speed: 200.0 }
+    }
+}
+
+impl MatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_sigma(mut self, sigma: f64) -> Self {
+        self.sigma = sigma;
+        self
+    }
+
+    pub fn with_beta(mut self, beta: f64) -> Self {
+        self.beta = beta;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn with_max_candidates(mut self, count: usize) -> Self {
+        self.max_candidates = count;
+        self
+    }
+
+    pub fn with_max_speed(mut self, kmh: f64) -> Self {
+        self.max_speed = kmh;
+        self
+    }
+
+    /// Error naming the first option that is not a positive number, only
+    /// the largest speed may be infinite
+    pub fn check(&self) -> Result<(), String> {
+        let lengths = [("sigma", self.sigma), ("beta", self.beta), ("radius", self.radius)];
+        for (name, value) in lengths.iter() {
+            if !value.is_finite() || *value <= 0.0 {
+                return Err(format!("{} must be a positive number, not {}", name, value));
+            }
+        }
+        if self.max_speed.is_nan() || self.max_speed <= 0.0 {
+            return Err(format!("max_speed must be a positive number, not {}", self.max_speed));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Point of a trace matched to the network
+pub struct MatchedPoint {
+    /// Position on the matched street, None when no street is within
+    /// reach of the point
+    pub snap: Option<Snap>,
+    /// Probability of the matched street given the whole trace, 0 for
+    /// unmatched points
+    pub confidence: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Result of [`match_trace`]
+pub struct Matching {
+    /// One entry for each point of the trace
+    pub points: Vec<MatchedPoint>,
+    /// Ids of the ways driven along, without consecutive repeats
+    pub ways: Vec<i64>,
+    /// OSM ids of the nodes driven through
+    pub nodes: Vec<i64>,
+    /// Length in meters of the matched route
+    pub distance: f64,
+}
+
+/// States of a point and transitions from the states of the previous one
+struct Step {
+    point: usize,
+    candidates: Vec<Snap>,
+    emissions: Vec<f64>,
+    /// Log probability from each previous state to each state
+    transitions: Vec<Vec<f64>>,
+    /// Route from each previous state to each state, when there is one
+    routes: Vec<Vec<Option<Route>>>,
+}
+
+fn log_sum_exp(values: impl Iterator<Item=f64>) -> f64 {
+    let values = values.collect::<Vec<f64>>();
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + values.iter().map(|v| (v - max).exp()).sum::<f64>().ln()
+}
+
+/// Transition log probabilities and routes between every state of `from`
+/// and every state of `to`
+fn transitions(
+    network: &StreetNetwork,
+    calculator: &mut fast_paths::PathCalculator,
+    a: &GpsPoint,
+    b: &GpsPoint,
+    from: &[Snap],
+    to: &[Snap],
+    options: &MatchOptions,
+) -> (Vec<Vec<f64>>, Vec<Vec<Option<Route>>>) {
+    let straight = geo::distance(a.lat, a.lon, b.lat, b.lon);
+    let elapsed = b.time - a.time;
+
+    let mut probabilities = vec![];
+    let mut routes = vec![];
+    for x in from {
+        let (row, row_routes) = to.iter()
+        .map(|y| {
+            let route = match snap::route_with(network, calculator, x, y) {
+                Ok(route) => route,
+                Err(_) => return (f64::NEG_INFINITY, None),
+            };
+            let travelled = (route.distance - 2.0 * options.radius).max(0.0);
+            if elapsed > 0.0 && travelled / elapsed > options.max_speed / 3.6 {
+                return (f64::NEG_INFINITY, None);
+            }
+            (-options.beta.ln() - (straight - route.distance).abs() / options.beta, Some(route))
+        })
+        .unzip();
+        probabilities.push(row);
+        routes.push(row_routes);
+    }
+    (probabilities, routes)
+}
+
+/// Matched state and its probability for each step of a chain of steps
+/// joined by possible transitions
+fn solve(steps: &[Step]) -> Vec<(usize, f64)> {
+    // Viterbi
+    let mut best = steps[0].emissions.clone();
+    let mut back: Vec<Vec<usize>> = vec![vec![]];
+    for step in &steps[1..] {
+        let mut next = vec![f64::NEG_INFINITY; step.candidates.len()];
+        let mut from = vec![0; step.candidates.len()];
+        for (j, emission) in step.emissions.iter().enumerate() {
+            for (i, score) in best.iter().enumerate() {
+                let candidate = score + step.transitions[i][j] + emission;
+                if candidate > next[j] {
+                    next[j] = candidate;
+                    from[j] = i;
+                }
+            }
+        }
+        best = next;
+        back.push(from);
+    }
+    let mut state = (0..best.len()).max_by(|a, b| best[*a].total_cmp(&best[*b])).unwrap();
+    let mut states = vec![state];
+    for from in back[1..].iter().rev() {
+        state = from[state];
+        states.push(state);
+    }
+    states.reverse();
+
+    // Forward-backward
+    let mut forward = vec![steps[0].emissions.clone()];
+    for step in &steps[1..] {
+        let previous = forward.last().unwrap();
+        let alpha = step.emissions.iter().enumerate()
+        .map(|(j, e)| e + log_sum_exp(previous.iter().enumerate().map(|(i, a)| a + step.transitions[i][j])))
+        .collect();
+        forward.push(alpha);
+    }
+    let mut backward = vec![vec![0.0; steps[steps.len() - 1].candidates.len()]];
+    for (t, step) in steps.iter().enumerate().skip(1).rev() {
+        let next = backward.last().unwrap();
+        let beta = (0..steps[t - 1].candidates.len())
+        .map(|i| log_sum_exp((0..step.candidates.len()).map(|j| step.transitions[i][j] + step.emissions[j] + next[j])))
+        .collect();
+        backward.push(beta);
+    }
+    backward.reverse();
+    let total = log_sum_exp(forward[forward.len() - 1].iter().cloned());
+
+    states.iter().enumerate()
+    .map(|(t, s)| (*s, (forward[t][*s] + backward[t][*s] - total).exp()))
+    .collect()
+}
+
+/// Match a GPS trace to the streets of the network
+///
+/// Points without any street within the radius, or with a coordinate or
+/// timestamp that is not finite, are left unmatched. Where no route joins
+/// the candidates of consecutive points, the trace is matched as two
+/// separate parts and the route has a gap. Fails when the options do not
+/// pass [`MatchOptions::check`].
+pub fn match_trace(network: &StreetNetwork, trace: &[GpsPoint], options: &MatchOptions) -> Result<Matching, String> {
+    options.check()?;
+    let mut points = vec![MatchedPoint { snap: None, confidence: 0.0 }; trace.len()];
+    let mut calculator = fast_paths::create_calculator(network.prepared());
+    let mut chains: Vec<Vec<Step>> = vec![];
+    let mut chain: Vec<Step> = vec![];
+    // States of the last step reached by a route from the chain start
+    let mut reached: Vec<bool> = vec![];
+
+    for (i, point) in trace.iter().enumerate() {
+        if !point.lat.is_finite() || !point.lon.is_finite() || !point.time.is_finite() {
+            continue;
+        }
+        let mut candidates = network.candidates(point.lat, point.lon, options.radius, None);
+        candidates.truncate(options.max_candidates);
+        if candidates.is_empty() {
+            continue;
+        }
+        let emissions = candidates.iter()
+        .map(|c| -0.5 * (c.distance / options.sigma).powi(2) - (options.sigma * (2.0 * std::f64::consts::PI).sqrt()).ln())
+        .collect();
+
+        let (transitions, routes) = match chain.last() {
+            Some(previous) => {
+                let (a, from) = (&trace[previous.point], &previous.candidates);
+                transitions(network, &mut calculator, a, point, from, &candidates, options)
+            },
+            None => (vec![], vec![]),
+        };
+        let next = (0..candidates.len())
+        .map(|j| transitions.iter().zip(&reached).any(|(t, r)| *r && t[j] > f64::NEG_INFINITY))
+        .collect::<Vec<bool>>();
+        if chain.is_empty() || !next.contains(&true) {
+            if !chain.is_empty() {
+                chains.push(std::mem::take(&mut chain));
+            }
+            reached = vec![true; candidates.len()];
+            chain.push(Step { point: i, candidates, emissions, transitions: vec![], routes: vec![] });
+            continue;
+        }
+        reached = next;
+        chain.push(Step { point: i, candidates, emissions, transitions, routes });
+    }
+    if !chain.is_empty() {
+        chains.push(chain);
+    }
+
+    let (mut ways, mut nodes, mut distance) = (vec![], vec![], 0.0);
+    for chain in chains {
+        let matched = solve(&chain);
+        for (step, (state, confidence)) in chain.iter().zip(&matched) {
+            points[step.point] = MatchedPoint { snap: Some(step.candidates[*state].clone()), confidence: *confidence };
+        }
+
+        // Routes between the matched states were found with the transitions
+        for (t, step) in chain.iter().enumerate().skip(1) {
+            if let Some(route) = &step.routes[matched[t - 1].0][matched[t].0] {
+                nodes.extend(route.nodes.iter().filter(|n| **n != snap::START && **n != snap::END));
+                ways.extend(route.ways.iter());
+                distance += route.distance;
+            }
+        }
+    }
+    ways.dedup();
+    nodes.dedup();
+
+    Ok(Matching { points, ways, nodes, distance })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::RoutingProfile;
+
+    /// Main street 1-2-3-4 and a parallel street 5-6-7-8 about 45 meters
+    /// north of it, joined at both ends
+    const PARALLEL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.000" lon="0.003"/>
+      <node id="5" lat="0.0004" lon="0.000"/>
+      <node id="6" lat="0.0004" lon="0.001"/>
+      <node id="7" lat="0.0004" lon="0.002"/>
+      <node id="8" lat="0.0004" lon="0.003"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><tag k="highway" v="residential"/><tag k="name" v="Main Street"/></way>
+      <way id="11"><nd ref="5"/><nd ref="6"/><nd ref="7"/><nd ref="8"/><tag k="highway" v="residential"/></way>
+      <way id="12"><nd ref="1"/><nd ref="5"/><tag k="highway" v="residential"/></way>
+      <way id="13"><nd ref="4"/><nd ref="8"/><tag k="highway" v="residential"/></way>
+    </osm>"#;
+
+    #[test]
+    fn noisy_trace_stays_on_the_street() {
+        let map = Map::from_xml(PARALLEL.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        // The third point is closer to the parallel street, the sixth one
+        // far from everything
+        let positions = [
+            (0.0001, 0.0002), (0.0001, 0.0007), (0.00028, 0.0012),
+            (0.0001, 0.0017), (0.0001, 0.0022), (0.01, 0.0025), (0.0001, 0.0027),
+        ];
+        let trace = positions.iter().enumerate()
+        .map(|(i, (lat, lon))| GpsPoint { lat: *lat, lon: *lon, time: 10.0 * i as f64 })
+        .collect::<Vec<GpsPoint>>();
+        let matched = network.match_trace(&trace, &MatchOptions::new()).unwrap();
+
+        assert_eq!(matched.points.len(), trace.len());
+        assert_eq!(matched.points[5], MatchedPoint { snap: None, confidence: 0.0 });
+        for (i, point) in matched.points.iter().enumerate().filter(|(i, _)| *i != 5) {
+            let snapped = point.snap.as_ref().unwrap();
+            assert_eq!(snapped.way, 10, "point {}", i);
+            assert!(snapped.location.0.abs() < 1e-9);
+            assert!(point.confidence > 0.9 && point.confidence <= 1.0 + 1e-9, "point {}", i);
+        }
+        assert_eq!(matched.ways, vec![10]);
+        assert_eq!(matched.nodes, vec![2, 3]);
+        assert!((matched.distance - geo::distance(0.0, 0.0002, 0.0, 0.0027)).abs() < 1e-6);
+
+        // Without the trace, the noisy point alone goes to the closest street
+        let alone = network.match_trace(&trace[2..3], &MatchOptions::new()).unwrap();
+        assert_eq!(alone.points[0].snap.as_ref().unwrap().way, 11);
+        assert!(network.match_trace(&[], &MatchOptions::new()).unwrap().points.is_empty());
+    }
+
+    #[test]
+    fn bad_options_and_points_do_not_panic() {
+        let map = Map::from_xml(PARALLEL.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        let trace = [
+            GpsPoint { lat: 0.0, lon: 0.0005, time: 0.0 },
+            GpsPoint { lat: f64::NAN, lon: 0.001, time: 5.0 },
+            GpsPoint { lat: 0.0, lon: 0.0015, time: 10.0 },
+        ];
+
+        for options in [
+            MatchOptions::new().with_sigma(0.0),
+            MatchOptions::new().with_beta(-5.0),
+            MatchOptions::new().with_radius(f64::NAN),
+            MatchOptions::new().with_sigma(f64::INFINITY),
+            MatchOptions::new().with_max_speed(f64::NAN),
+        ].iter() {
+            assert!(network.match_trace(&trace, options).is_err(), "{:?}", options);
+        }
+
+        // The point that is not a number is left out of the route
+        let matched = network.match_trace(&trace, &MatchOptions::new()).unwrap();
+        assert_eq!(matched.points[1], MatchedPoint { snap: None, confidence: 0.0 });
+        assert_eq!(matched.points[2].snap.as_ref().unwrap().way, 10);
+        assert_eq!(matched.nodes, vec![2]);
+    }
+}
@@ -0,0 +1,107 @@
+/* matrix.rs */
+//! Many-to-many cost matrices.
+//!
+//! Every cell is a query on the prepared contraction hierarchy, rows are
+//! computed in parallel, each worker thread reusing its own calculator.
+
+use rayon::prelude::*;
+
+use crate::network::StreetNetwork;
+
+#[derive(Clone, Debug, PartialEq)]
+/// Costs of the best routes from each source to each target
+///
+/// Cells are None when the target can not be reached or when either node
+/// is not part of the network.
+pub struct Matrix {
+    pub sources: Vec<i64>,
+    pub targets: Vec<i64>,
+    /// Length in meters, one row per source
+    pub distances: Vec<Vec<Option<f64>>>,
+    /// Travel time in seconds, one row per source
+    pub durations: Vec<Vec<Option<f64>>>,
+}
+
+/// Costs from every node of `sources` to every node of `targets`
+///
+/// Routes minimize the metric of the network profile, so distances of a
+/// time profile are the lengths of the fastest routes.
+pub fn compute(network: &StreetNetwork, sources: &[i64], targets: &[i64]) -> Matrix {
+    let graph = network.prepared();
+    let arrivals = targets.iter()
+    .map(|t| network.arrivals(*t))
+    .collect::<Vec<Vec<usize>>>();
+
+    let rows = sources.par_iter()
+    .map_init(
+        || fast_paths::create_calculator(graph),
+        |calculator, source| {
+            let source = network.node_idx.find(*source);
+            arrivals.iter()
+            .map(|targets| {
+                let source = source?;
+                let path = targets.iter()
+                .filter_map(|t| calculator.calc_path(graph, source, *t))
+                .min_by_key(|p| p.get_weight())?;
+                Some(network.path_cost(path.get_nodes()))
+            })
+            .collect::<Vec<Option<(f64, f64)>>>()
+        },
+    )
+    .collect::<Vec<Vec<Option<(f64, f64)>>>>();
+
+    Matrix {
+        sources: sources.to_vec(),
+        targets: targets.to_vec(),
+        distances: rows.iter().map(|r| r.iter().map(|c| c.map(|(d, _)| d)).collect()).collect(),
+        durations: rows.iter().map(|r| r.iter().map(|c| c.map(|(_, t)| t)).collect()).collect(),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::map::Map;
+    use crate::network::StreetNetwork;
+    use crate::profile::RoutingProfile;
+
+    #[test]
+    fn matrix_matches_single_routes() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.000" lon="0.000"/>
+          <node id="2" lat="0.000" lon="0.001"/>
+          <node id="3" lat="0.000" lon="0.002"/>
+          <node id="4" lat="0.001" lon="0.000"/>
+          <node id="5" lat="0.001" lon="0.001"/>
+          <node id="6" lat="0.001" lon="0.002"/>
+          <node id="7" lat="0.010" lon="0.010"/>
+          <node id="8" lat="0.010" lon="0.011"/>
+          <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+          <way id="11"><nd ref="4"/><nd ref="5"/><nd ref="6"/><tag k="highway" v="primary"/></way>
+          <way id="12"><nd ref="1"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+          <way id="13"><nd ref="3"/><nd ref="6"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+          <way id="14"><nd ref="7"/><nd ref="8"/><tag k="highway" v="residential"/></way>
+        </osm>"#;
+        let map = Map::from_xml(xml.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let (sources, targets) = (vec![1, 3, 6, 99], vec![6, 3, 1, 7]);
+        let matrix = network.matrix(&sources, &targets);
+        assert_eq!(matrix.distances.len(), 4);
+        assert!(matrix.distances.iter().all(|row| row.len() == 4));
+
+        for (i, a) in sources.iter().enumerate() {
+            for (j, b) in targets.iter().enumerate() {
+                let expected = network.route(*a, *b).ok();
+                assert_eq!(matrix.distances[i][j], expected.as_ref().map(|r| r.distance), "{} -> {}", a, b);
+                assert_eq!(matrix.durations[i][j], expected.as_ref().map(|r| r.duration), "{} -> {}", a, b);
+            }
+        }
+
+        // Oneway 3 -> 6 makes the matrix asymmetric
+        assert!(matrix.distances[1][0].unwrap() < matrix.distances[2][1].unwrap());
+        assert_eq!(matrix.distances[0][3], None);
+        assert_eq!(matrix.distances[3][0], None);
+    }
+}
@@ -0,0 +1,152 @@
+/* maxspeed.rs */
+//! Parsing of the ``maxspeed`` tag.
+//!
+//! Every speed is in km/h. Implicit limits written with a country code
+//! (``DE:urban``, ``FR:rural``, ``UK:nsl_single``) are resolved from a small
+//! table of national defaults, unknown countries get common European values.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Speed limit of a way
+pub enum MaxSpeed {
+    /// Limit in km/h
+    Kmh(f64),
+    /// No speed limit at all, e.g. on some German motorways
+    Unlimited,
+}
+
+/// Walking pace used for ``maxspeed=walk``
+pub const WALK: f64 = 6.0;
+
+const MPH: f64 = 1.609_344;
+const KNOT: f64 = 1.852;
+
+/// Parse a ``maxspeed`` value
+///
+/// Accepts plain numbers in km/h, numbers with a ``mph``, ``km/h`` or
+/// ``knots`` unit, ``walk``, ``none`` and country coded zones. When several
+/// values are given (``50;30``) the first readable one is used. Returns None
+/// for values that can not be understood, like ``signals`` or ``variable``.
+pub fn parse(value: &str) -> Option<MaxSpeed> {
+    value.split(';').find_map(|v| parse_single(v.trim()))
+}
+
+fn parse_single(value: &str) -> Option<MaxSpeed> {
+    match value {
+        "none" | "unlimited" => return Some(MaxSpeed::Unlimited),
+        "walk" => return Some(MaxSpeed::Kmh(WALK)),
+        _ => (),
+    }
+
+    if let Some((country, zone)) = value.split_once(':') {
+        return implicit(country, zone);
+    }
+
+    let number_end = value.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+    .unwrap_or(value.len());
+    let number = value[..number_end].parse::<f64>().ok()?;
+    let factor = match value[number_end..].trim() {
+        "" | "km/h" | "kmh" | "kph" => 1.0,
+        "mph" => MPH,
+        "knots" => KNOT,
+        _ => return None,
+    };
+
+    if number > 0.0 {
+        Some(MaxSpeed::Kmh(number * factor))
+    } else {
+        None
+    }
+}
+
+/// National default of a zone, e.g. ``urban`` in ``DE``
+fn implicit(country: &str, zone: &str) -> Option<MaxSpeed> {
+    use MaxSpeed::*;
+
+    // Zones carrying their own limit: zone30, zone:30
+    if let Some(limit) = zone.strip_prefix("zone") {
+        return limit.trim_start_matches(':').parse::<f64>().ok()
+        .filter(|kmh| kmh.is_finite() && *kmh > 0.0)
+        .map(Kmh);
+    }
+
+    let speed = match (country, zone) {
+        (_, "walk") => Kmh(WALK),
+        (_, "living_street") => match country {
+            "FR" | "BE" | "CH" => Kmh(20.0),
+            _ => Kmh(WALK),
+        },
+        (_, "bicycle_road") | (_, "cyclestreet") => Kmh(30.0),
+        ("RU", "urban") | ("UA", "urban") | ("BY", "urban") | ("KZ", "urban") => Kmh(60.0),
+        (_, "urban") => Kmh(50.0),
+
+        ("GB", "nsl_single") | ("UK", "nsl_single") => Kmh(60.0 * MPH),
+        ("GB", "nsl_dual") | ("UK", "nsl_dual") => Kmh(70.0 * MPH),
+        ("GB", "motorway") | ("UK", "motorway") => Kmh(70.0 * MPH),
+        ("GB", "rural") | ("UK", "rural") => Kmh(60.0 * MPH),
+
+        ("DE", "motorway") | ("DE", "trunk") => Unlimited,
+        ("DE", "rural") | ("AT", "rural") => Kmh(100.0),
+        ("FR", "rural") | ("NL", "rural") | ("CH", "rural") | ("DK", "rural") | ("NO", "rural") => Kmh(80.0),
+        ("BE", "rural") | ("SE", "rural") => Kmh(70.0),
+        (_, "rural") => Kmh(90.0),
+
+        ("FR", "trunk") | ("IT", "trunk") => Kmh(110.0),
+        ("PL", "trunk") => Kmh(120.0),
+        (_, "trunk") => Kmh(100.0),
+
+        ("PL", "motorway") => Kmh(140.0),
+        ("AT", "motorway") | ("FR", "motorway") | ("IT", "motorway")
+        | ("CZ", "motorway") | ("DK", "motorway") => Kmh(130.0),
+        ("RU", "motorway") | ("SE", "motorway") | ("NO", "motorway") => Kmh(110.0),
+        ("NL", "motorway") => Kmh(100.0),
+        (_, "motorway") => Kmh(120.0),
+
+        _ => return None,
+    };
+    Some(speed)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kmh(value: &str) -> f64 {
+        match parse(value) {
+            Some(MaxSpeed::Kmh(kmh)) => kmh,
+            other => panic!("{} parsed as {:?}", value, other),
+        }
+    }
+
+    #[test]
+    fn explicit_values() {
+        assert_eq!(kmh("50"), 50.0);
+        assert_eq!(kmh("50 km/h"), 50.0);
+        assert!((kmh("30 mph") - 48.28).abs() < 0.01);
+        assert!((kmh("30mph") - 48.28).abs() < 0.01);
+        assert!((kmh("5 knots") - 9.26).abs() < 0.01);
+        assert_eq!(kmh("60;40"), 60.0);
+        assert_eq!(kmh("walk"), WALK);
+        assert_eq!(parse("none"), Some(MaxSpeed::Unlimited));
+    }
+
+    #[test]
+    fn implicit_values() {
+        assert_eq!(kmh("DE:urban"), 50.0);
+        assert_eq!(kmh("DE:rural"), 100.0);
+        assert_eq!(parse("DE:motorway"), Some(MaxSpeed::Unlimited));
+        assert_eq!(kmh("FR:rural"), 80.0);
+        assert_eq!(kmh("RU:urban"), 60.0);
+        assert_eq!(kmh("DE:zone30"), 30.0);
+        assert_eq!(kmh("DE:zone:20"), 20.0);
+        assert!((kmh("UK:nsl_single") - 96.56).abs() < 0.01);
+        assert_eq!(kmh("XX:urban"), 50.0);
+    }
+
+    #[test]
+    fn unreadable_values() {
+        for value in &["signals", "variable", "", "0", "fast", "DE:unknown", "50 furlongs", "DE:zone:0", "DE:zone-30", "DE:zone:inf", "DE:zoneNaN"] {
+            assert_eq!(parse(value), None, "{}", value);
+        }
+    }
+}
@@ -1,65 +1,202 @@
 /* network.rs */
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
 use serde::{Serialize, Deserialize};
 
+use crate::alternatives;
+use crate::avoid::{self, Avoid};
+use crate::centrality;
+use crate::components::{self, ComponentStats, Connectivity};
+use crate::instructions::{self, Step};
+use crate::intersection::{self, Intersection};
+use crate::isochrone::{self, Isochrone};
+use crate::matrix::{self, Matrix};
+use crate::geo;
 use crate::queries::QueryBuilder;
 use crate::map;
-use crate::map::{Way, Node};
+use crate::map::Way;
+use crate::matching::{self, GpsPoint, MatchOptions, Matching};
+use crate::profile::{Metric, Oneway, RoutingProfile, TravelMode};
+use crate::restriction::{NodeRestriction, RestrictionKind, TurnRestriction};
+use crate::route::{Leg, Route, RouteError, Segment};
+use crate::simplify;
+use crate::snap::{self, SegmentIndex, Snap};
+use crate::stats::{self, NetworkStats};
 use crate::structure::NodeMap;
+use crate::traffic::{self, TrafficUpdate};
+use crate::via;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// Directed street segment between two consecutive nodes of a way
+pub struct Edge {
+    /// Graph node id of the start, see [`StreetNetwork::node_idx`]
+    pub from: usize,
+    /// Graph node id of the end
+    pub to: usize,
+    /// OSM way the segment belongs to
+    pub way: i64,
+    /// Length in meters
+    pub distance: f64,
+    /// Travel time in seconds
+    pub duration: f64,
+    /// Weight in the graph, see [`RoutingProfile::weight`]
+    pub weight: usize,
+    /// Edges between consecutive nodes merged into this one by
+    /// [`StreetNetwork::simplify`], empty when nothing was merged
+    #[serde(default)]
+    pub pieces: Vec<Piece>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// Part of a simplified edge between two consecutive nodes of a way
+pub struct Piece {
+    /// OSM id of the node the piece ends at
+    pub node: i64,
+    /// OSM way the piece belongs to
+    pub way: i64,
+    /// Length in meters
+    pub distance: f64,
+    /// Travel time in seconds
+    pub duration: f64,
+    /// Weight in the graph, see [`RoutingProfile::weight`]
+    pub weight: usize,
+}
 
 /// Graph for  OpenStreet's streets
 #[derive(Serialize, Deserialize)]
 pub struct StreetNetwork {
     pub inner: fast_paths::InputGraph,
     pub node_idx: NodeMap<i64>,
-    // pub intersection_nodes: Vec<Node>,
     pub nodeways_idx: std::collections::HashMap<i64, Vec<Way>>,
+    /// Profile the network is built with
+    pub profile: RoutingProfile,
+    /// Every edge of `inner` with its length and travel time
+    pub edges: Vec<Edge>,
+    /// Outgoing edges (index in `edges`) of each graph node
+    adjacency: Vec<Vec<usize>>,
+    /// Extra graph nodes of the junctions split by turn restrictions
+    pub copies: HashMap<i64, Vec<usize>>,
+    /// (lat, lon) of each graph node
+    pub coords: Vec<(f64, f64)>,
+    /// Ways the network is built from
+    pub ways: HashMap<i64, Way>,
+    /// (lat, lon) of the nodes removed by [`simplify`](Self::simplify)
+    #[serde(default)]
+    pub shape_nodes: HashMap<i64, (f64, f64)>,
+    /// Node ordering of the last contraction, reused to prepare the graph
+    /// again after weight updates
+    #[serde(default)]
+    pub(crate) order: Vec<usize>,
+    /// Contraction hierarchy of `inner`, prepared on the first query
+    #[serde(skip)]
+    prepared: OnceLock<fast_paths::FastGraph>,
+    /// R-tree over the edges, built on the first snap
+    #[serde(skip)]
+    segments: OnceLock<SegmentIndex>,
 }
 
 impl StreetNetwork {
     pub fn new(map: &map::Map, street_types: Vec<&str>) -> Self {
-        // println!("Creating StreetNetwork!");
-        // println!("- Constraints: {:?}", street_types);
+        Self::with_mode(map, street_types, TravelMode::Car)
+    }
 
-        let mut graph = fast_paths::InputGraph::new();
-        let mut qstreets = map.ways();
-        if street_types.len() > 0 {
-            qstreets = qstreets.by_tag_in("highway", street_types);
-        }
+    /// Build the network for `mode`, honouring oneway streets
+    ///
+    /// Edges are weighted by length and only the `street_types` highways
+    /// are kept, or every highway of the built-in profile of `mode` when
+    /// the list is empty.
+    pub fn with_mode(map: &map::Map, street_types: Vec<&str>, mode: TravelMode) -> Self {
+        let profile = RoutingProfile::for_mode(mode)
+        .restricted_to(&street_types)
+        .with_metric(Metric::Distance);
+        Self::from_profile(map, profile)
+    }
+
+    /// Build the network of the ways accessible with `profile`
+    ///
+    /// Edges follow the allowed travel direction of each way, ways with
+    /// reversible traffic are left out. Edge weights are computed by
+    /// [`RoutingProfile::weight`] from the length and the speed limit of
+    /// the way in each direction.
+    ///
+    /// Turn restrictions applying to the profile travel mode are honoured
+    /// by splitting the restricted junctions into several graph nodes.
+    pub fn from_profile(map: &map::Map, profile: RoutingProfile) -> Self {
+        let qstreets = map.ways().by_tag_in("highway", profile.highways());
 
         let mut node_idx: NodeMap<i64> = NodeMap::new();
         let mut node_ways_idx: std::collections::HashMap<i64, Vec<Way>> = std::collections::HashMap::new();
 
-        let mut qnodes = map.nodes();
+        let qnodes = map.nodes();
+        let mut edges: Vec<Edge> = vec![];
+        let mut ways: HashMap<i64, Way> = HashMap::new();
 
-        let mut c = 0;
         for (_, way) in qstreets.iter() {
+            let (forward, backward) = match profile.speeds(&way.tags) {
+                Some(speeds) => speeds,
+                None => continue,
+            };
+            let oneway = profile.oneway(&way.tags);
+            if oneway == Oneway::Reversible {
+                continue;
+            }
+
             let size = way.nodes.len();
             for i in 0..(size-1) {
-                c += 2;
                 let a = node_idx.get_or_insert(way.nodes[i]);
                 let b = node_idx.get_or_insert(way.nodes[i+1]);
 
                 let node_a = qnodes.by_id(way.nodes[i]);
                 let node_b = qnodes.by_id(way.nodes[i+1]);
-                let w = ((node_a.lat - node_b.lat).powi(2) + (node_a.lon - node_b.lon as f64).powi(2)).sqrt() * 111_120.0;
+                let w = node_a.distance_to(&node_b);
 
-                graph.add_edge_bidir(a, b, w as usize);
+                let edge = |from, to, speed| Edge {
+                    from,
+                    to,
+                    way: way.id,
+                    distance: w,
+                    duration: w / (speed / 3.6),
+                    weight: profile.weight(w, speed),
+                    pieces: vec![],
+                };
+                if oneway != Oneway::Backward {
+                    edges.push(edge(a, b, forward));
+                }
+                if oneway != Oneway::Forward {
+                    edges.push(edge(b, a, backward));
+                }
                 // println!("Add edge {}/{} <-({})-> {}/{}", a, way.nodes[i], w, b, way.nodes[i+1])
             }
 
             for node_id in &way.nodes {
-                if !node_ways_idx.contains_key(&node_id) {
+                if !node_ways_idx.contains_key(node_id) {
                     let ways = Vec::new();
                     node_ways_idx.insert(*node_id, ways);
                 }
-                if let Some(ways) = node_ways_idx.get_mut(&node_id) {
-                    ways.push(way.clone().into())
+                if let Some(ways) = node_ways_idx.get_mut(node_id) {
+                    ways.push(way.clone())
                 }
             }
+            ways.insert(way.id, way);
             // println!("Way {}", way.id);
         }
-        node_idx.guarantee_node_ordering(&mut graph);
-        graph.freeze();
+
+        let restrictions = TurnRestriction::from_map(map, profile.mode).iter()
+        .flat_map(|r| r.node_restrictions(map))
+        .collect::<Vec<NodeRestriction>>();
+        let (edges, copies) = split_restricted_nodes(&mut node_idx, edges, &restrictions);
+
+        let coords = (0..node_idx.len())
+        .map(|id| qnodes.by_id(node_idx.node(id)))
+        .map(|n| (n.lat, n.lon))
+        .collect();
+
+        let mut adjacency = vec![vec![]; node_idx.len()];
+        for (i, edge) in edges.iter().enumerate() {
+            adjacency[edge.from].push(i);
+        }
+        let graph = input_graph(&node_idx, &edges);
         // println!("There are {} edges added, {} num nodes", c, graph.get_num_nodes());
 
         // println!("Return StreetNetwork");
@@ -67,26 +204,487 @@ impl StreetNetwork {
             inner: graph,
             node_idx,
             nodeways_idx: node_ways_idx,
+            profile,
+            edges,
+            adjacency,
+            copies,
+            coords,
+            ways,
+            shape_nodes: HashMap::new(),
+            order: vec![],
+            prepared: OnceLock::new(),
+            segments: OnceLock::new(),
+        }
+    }
+
+    /// Every intersection and dead end of the network, by node id,
+    /// see [`intersection::intersections`]
+    pub fn intersections(&self) -> Vec<Intersection> {
+        intersection::intersections(self)
+    }
+
+    /// Intersection at the node `id`, None when two streets or none meet there
+    pub fn intersection(&self, id: i64) -> Option<Intersection> {
+        intersection::intersection(self, id)
+    }
+
+    /// Returns the prepared graph, preparing it on the first call
+    ///
+    /// Preparation is the expensive part of routing, the result is kept
+    /// until the topology or any edge weight changes.
+    pub fn prepared(&self) -> &fast_paths::FastGraph {
+        self.prepared.get_or_init(|| {
+            if self.order.len() == self.inner.get_num_nodes() {
+                if let Ok(graph) = fast_paths::prepare_with_order(&self.inner, &self.order) {
+                    return graph;
+                }
+            }
+            fast_paths::prepare(&self.inner)
+        })
+    }
+
+    /// Mutable access to the input graph
+    ///
+    /// The prepared graph is thrown away and will be prepared again
+    /// on the next query. Distances and durations reported by routes
+    /// still come from [`edges`](Self::edges).
+    pub fn graph_mut(&mut self) -> &mut fast_paths::InputGraph {
+        self.prepared = OnceLock::new();
+        self.order.clear();
+        &mut self.inner
+    }
+
+    /// Apply the new speeds of `update` to the edges it selects
+    ///
+    /// Durations and weights of the edges follow the new speeds, with a
+    /// distance metric the weights are left as they are. The input graph
+    /// is rebuilt from [`edges`](Self::edges) once for the whole batch and
+    /// the next query prepares it again with the node ordering of the
+    /// previous preparation, which is much cheaper than the first one.
+    ///
+    /// Returns the number of updated edges.
+    pub fn update(&mut self, update: &TrafficUpdate) -> usize {
+        let changes = traffic::changes(self, update);
+        for (i, k, kmh) in &changes {
+            let edge = &mut self.edges[*i];
+            if edge.pieces.is_empty() {
+                edge.duration = edge.distance / (kmh / 3.6);
+                edge.weight = self.profile.weight(edge.distance, *kmh);
+                continue;
+            }
+            let piece = &mut edge.pieces[*k];
+            piece.duration = piece.distance / (kmh / 3.6);
+            piece.weight = self.profile.weight(piece.distance, *kmh);
+            edge.duration = edge.pieces.iter().map(|p| p.duration).sum();
+            edge.weight = edge.pieces.iter().map(|p| p.weight).sum();
+        }
+        let mut updated = changes.iter().map(|(i, _, _)| *i).collect::<Vec<usize>>();
+        updated.dedup();
+        if updated.is_empty() {
+            return 0;
+        }
+
+        if let Some(prepared) = self.prepared.take() {
+            self.order = fast_paths::get_node_ordering(&prepared);
+        }
+        self.inner = input_graph(&self.node_idx, &self.edges);
+        updated.len()
+    }
+
+    /// Set the speed from node `a` to node `b`, see [`update`](Self::update)
+    pub fn set_edge_speed(&mut self, a: i64, b: i64, kmh: f64) -> usize {
+        self.update(&TrafficUpdate::new().edge(a, b, kmh))
+    }
+
+    /// Set the speed along both directions of a way, see [`update`](Self::update)
+    pub fn set_way_speed(&mut self, way: i64, kmh: f64) -> usize {
+        self.update(&TrafficUpdate::new().way(way, kmh))
+    }
+
+    /// Collapse the nodes where the network only bends or passes from a
+    /// way to the next, see [`simplify::simplify`]
+    ///
+    /// Edges then run between intersections, dead ends and the junctions
+    /// split by turn restrictions, and keep the removed nodes as
+    /// [`pieces`](Edge::pieces). Routes still list every node they pass
+    /// through, but removed nodes can no longer be routed from or to.
+    pub fn simplify(&mut self) {
+        let simplified = simplify::simplify(self);
+        self.replace_graph(simplified);
+    }
+
+    /// Drop every node outside of the largest component, see
+    /// [`components::components`]
+    ///
+    /// Routes between the remaining nodes are unchanged. Returns the number
+    /// of removed nodes.
+    pub fn keep_largest_component(&mut self, connectivity: Connectivity) -> usize {
+        let ids = components::components(self, connectivity);
+        let removed = ids.iter().map(|c| *c != 0).collect::<Vec<bool>>();
+        let before = self.node_count();
+
+        let edges = self.edges.iter()
+        .filter(|e| !removed[e.from] && !removed[e.to])
+        .cloned()
+        .collect::<Vec<Edge>>();
+        let shape_nodes = edges.iter()
+        .flat_map(|e| e.pieces.iter())
+        .filter_map(|p| Some((p.node, *self.shape_nodes.get(&p.node)?)))
+        .collect();
+        let graph = simplify::renumber(self, &removed, edges, shape_nodes);
+        self.shape_nodes.clear();
+        self.replace_graph(graph);
+        let (node_idx, shape_nodes) = (&self.node_idx, &self.shape_nodes);
+        self.nodeways_idx.retain(|id, _| node_idx.find(*id).is_some() || shape_nodes.contains_key(id));
+
+        before - self.node_count()
+    }
+
+    /// Number of OSM nodes of the network, including the ones removed by
+    /// [`simplify`](Self::simplify)
+    pub fn node_count(&self) -> usize {
+        self.node_idx.len() - self.copies.values().map(|c| c.len()).sum::<usize>() + self.shape_nodes.len()
+    }
+
+    /// Component of each node of the network, see [`components::component_ids`]
+    pub fn component_ids(&self, connectivity: Connectivity) -> HashMap<i64, usize> {
+        components::component_ids(self, connectivity)
+    }
+
+    /// Sizes of the components of the network, see [`components::stats`]
+    pub fn component_stats(&self, connectivity: Connectivity) -> ComponentStats {
+        components::stats(self, connectivity)
+    }
+
+    /// Summary statistics of the network with densities over the convex
+    /// hull of its nodes, see [`stats::summary`]
+    pub fn stats(&self) -> NetworkStats {
+        stats::summary(self, None)
+    }
+
+    /// Summary statistics of the network with densities over `bounds`,
+    /// such as [`map::Map::bounds`]
+    pub fn stats_within(&self, bounds: &map::Bounds) -> NetworkStats {
+        stats::summary(self, Some(bounds))
+    }
+
+    fn replace_graph(&mut self, graph: simplify::Simplified) {
+        let mut adjacency = vec![vec![]; graph.node_idx.len()];
+        for (i, edge) in graph.edges.iter().enumerate() {
+            adjacency[edge.from].push(i);
+        }
+
+        self.inner = input_graph(&graph.node_idx, &graph.edges);
+        self.node_idx = graph.node_idx;
+        self.edges = graph.edges;
+        self.adjacency = adjacency;
+        self.copies = graph.copies;
+        self.coords = graph.coords;
+        self.shape_nodes.extend(graph.shape_nodes);
+        self.order.clear();
+        self.prepared = OnceLock::new();
+        self.segments = OnceLock::new();
+    }
+
+    /// (lat, lon) of an OSM node of the network, removed or not by
+    /// [`simplify`](Self::simplify)
+    pub fn location(&self, node: i64) -> Option<(f64, f64)> {
+        match self.node_idx.find(node) {
+            Some(id) => Some(self.coords[id]),
+            None => self.shape_nodes.get(&node).copied(),
+        }
+    }
+
+    /// Pieces of `edge` between consecutive OSM nodes, a single piece when
+    /// it was not simplified
+    pub fn pieces(&self, edge: &Edge) -> Vec<Piece> {
+        if !edge.pieces.is_empty() {
+            return edge.pieces.clone();
+        }
+        vec![Piece {
+            node: self.node_idx.node(edge.to),
+            way: edge.way,
+            distance: edge.distance,
+            duration: edge.duration,
+            weight: edge.weight,
+        }]
+    }
+
+    /// Cheapest edge from graph node `a` to `b`, the one used by routing
+    fn edge_between(&self, a: usize, b: usize) -> Option<&Edge> {
+        self.adjacency.get(a)?.iter()
+        .map(|i| &self.edges[*i])
+        .filter(|e| e.to == b)
+        .min_by_key(|e| e.weight)
+    }
+
+    /// Edge from node `a` to node `b`, both are OSM node ids
+    pub fn edge(&self, a: i64, b: i64) -> Option<&Edge> {
+        self.adjacency.get(self.node_idx.find(a)?)?.iter()
+        .map(|i| &self.edges[*i])
+        .filter(|e| self.node_idx.node(e.to) == b)
+        .min_by_key(|e| e.weight)
+    }
+
+    /// Outgoing edges (index in `edges`) of the graph node `node`
+    pub(crate) fn out_edges(&self, node: usize) -> &[usize] {
+        self.adjacency.get(node).map(|e| e.as_slice()).unwrap_or(&[])
+    }
+
+    /// Graph nodes a route to the OSM node `node` may end at
+    pub(crate) fn arrivals(&self, node: i64) -> Vec<usize> {
+        let mut arrivals: Vec<usize> = self.node_idx.find(node).into_iter().collect();
+        if let Some(copies) = self.copies.get(&node) {
+            arrivals.extend(copies);
+        }
+        arrivals
+    }
+
+    /// Returns the best route from `a` to `b` for the network profile
+    ///
+    /// Fails with [`RouteError::UnknownNode`] if either node is not part of
+    /// the network and with [`RouteError::NoRoute`] if `b` can not be
+    /// reached from `a`.
+    pub fn route(&self, a: i64, b: i64) -> Result<Route, RouteError> {
+        let source = self.node_idx.find(a).ok_or(RouteError::UnknownNode(a))?;
+        let targets = self.arrivals(b);
+        if targets.is_empty() {
+            return Err(RouteError::UnknownNode(b));
         }
+
+        let graph = self.prepared();
+        let mut calculator = fast_paths::create_calculator(graph);
+        let path = targets.into_iter()
+        .filter_map(|target| calculator.calc_path(graph, source, target))
+        .min_by_key(|p| p.get_weight())
+        .ok_or(RouteError::NoRoute(a, b))?;
+
+        Ok(self.route_along(path.get_nodes(), path.get_weight()))
     }
 
-    pub fn intersections() -> Vec<Node> {
-        unimplemented!()
+    /// Length and travel time along the graph nodes `path`, the same as
+    /// the totals of [`route_along`](Self::route_along)
+    pub(crate) fn path_cost(&self, path: &[usize]) -> (f64, f64) {
+        path.windows(2)
+        .map(|w| match self.edge_between(w[0], w[1]) {
+            Some(edge) => (edge.distance, edge.duration),
+            None => {
+                let segment = self.bare_segment(w[0], w[1]);
+                (segment.distance, segment.duration)
+            },
+        })
+        .fold((0.0, 0.0), |(d, t), (distance, duration)| (d + distance, t + duration))
+    }
 
+    /// Route following the graph nodes `path`
+    ///
+    /// The cheapest edge is taken between consecutive nodes, searches that
+    /// may use another one of parallel edges go through
+    /// [`route_over`](Self::route_over).
+    pub(crate) fn route_along(&self, path: &[usize], weight: usize) -> Route {
+        let steps = path.windows(2)
+        .map(|w| (w[1], self.edge_between(w[0], w[1])))
+        .collect::<Vec<(usize, Option<&Edge>)>>();
+        self.build_route(path[0], &steps, weight)
     }
 
-    pub fn shortest_path(&mut self, a: i64, b: i64) -> Vec<i64> {
-        // prepare the graph for fast shortest path calculations. note that you have to do this again if you want to change the
-        // graph topology or any of the edge weights
-        let fast_graph = fast_paths::prepare(&self.inner);
+    /// Route from the graph node `start` following `edges` (index in
+    /// [`edges`](Self::edges))
+    pub(crate) fn route_over(&self, start: usize, edges: &[usize], weight: usize) -> Route {
+        let steps = edges.iter()
+        .map(|i| (self.edges[*i].to, Some(&self.edges[*i])))
+        .collect::<Vec<(usize, Option<&Edge>)>>();
+        self.build_route(start, &steps, weight)
+    }
 
-        // calculate the shortest path between nodes with ID 8 and 6
-        let a = self.node_idx.get_or_insert(a);
-        let b = self.node_idx.get_or_insert(b);
-        match fast_paths::calc_path(&fast_graph, a, b) {
-            Some(p) => self.node_idx.translate(&p),
-            None => vec![],
+    /// Route from the graph node `start` along (next graph node, edge) steps
+    fn build_route(&self, start: usize, steps: &[(usize, Option<&Edge>)], weight: usize) -> Route {
+        let mut nodes = vec![self.node_idx.node(start)];
+        let mut segments: Vec<Segment> = vec![];
+        let mut current = start;
+        for (next, edge) in steps {
+            let edge = match edge {
+                Some(edge) => edge,
+                None => {
+                    nodes.push(self.node_idx.node(*next));
+                    segments.push(self.bare_segment(current, *next));
+                    current = *next;
+                    continue;
+                },
+            };
+            for piece in self.pieces(edge) {
+                nodes.push(piece.node);
+                segments.push(Segment { way: piece.way, distance: piece.distance, duration: piece.duration });
+            }
+            current = *next;
         }
+
+        let geometry = nodes.iter().filter_map(|id| self.location(*id)).collect();
+        self.assemble(nodes, geometry, segments, weight)
+    }
+
+    /// Segment of an edge added through [`graph_mut`](Self::graph_mut)
+    ///
+    /// Such an edge has no way, its length is the straight distance between
+    /// its ends and its travel time comes from its weight, or from the
+    /// default speed of the travel mode with a distance metric.
+    fn bare_segment(&self, from: usize, to: usize) -> Segment {
+        let ((from_lat, from_lon), (to_lat, to_lon)) = (self.coords[from], self.coords[to]);
+        let distance = geo::distance(from_lat, from_lon, to_lat, to_lon);
+        let weight = self.inner.get_edges().iter()
+        .filter(|e| e.from == from && e.to == to)
+        .map(|e| e.weight)
+        .min();
+        let duration = match (self.profile.metric, weight) {
+            (Metric::Time, Some(weight)) => weight as f64 / 1000.0,
+            _ => distance / (self.profile.mode.default_speed() / 3.6),
+        };
+        Segment { way: 0, distance, duration }
+    }
+
+    /// Route made of `nodes` at `geometry` joined by `segments`
+    pub(crate) fn assemble(&self, nodes: Vec<i64>, geometry: Vec<(f64, f64)>, segments: Vec<Segment>, weight: usize) -> Route {
+        let mut ways = segments.iter().map(|s| s.way).filter(|w| *w != 0).collect::<Vec<i64>>();
+        ways.dedup();
+        let mut names = ways.iter()
+        .filter_map(|id| self.ways.get(id)?.tags.get("name").cloned())
+        .collect::<Vec<String>>();
+        names.dedup();
+
+        let distance = segments.iter().map(|s| s.distance).sum();
+        let duration = segments.iter().map(|s| s.duration).sum();
+        let leg = Leg {
+            from: nodes[0],
+            to: nodes[nodes.len() - 1],
+            start: 0,
+            end: nodes.len() - 1,
+            distance,
+            duration,
+            weight,
+        };
+
+        Route {
+            geometry,
+            nodes,
+            distance,
+            duration,
+            segments,
+            ways,
+            names,
+            weight,
+            legs: vec![leg],
+        }
+    }
+
+    /// Best route from `a` to `b` clear of everything in `avoid`,
+    /// see [`avoid::route`]
+    pub fn route_avoiding(&self, a: i64, b: i64, avoid: &Avoid) -> Result<Route, RouteError> {
+        avoid::route(self, a, b, avoid)
+    }
+
+    /// Route through each of `waypoints` in order, see [`via::route_via`]
+    pub fn route_via(&self, waypoints: &[i64], uturns: bool) -> Result<Route, RouteError> {
+        via::route_via(self, waypoints, uturns)
+    }
+
+    /// The `k` best loopless routes from `a` to `b`, see [`alternatives::k_shortest`]
+    pub fn k_shortest_paths(&self, a: i64, b: i64, k: usize) -> Result<Vec<Route>, RouteError> {
+        alternatives::k_shortest(self, a, b, k)
+    }
+
+    /// Up to `count` reasonably different routes from `a` to `b`,
+    /// see [`alternatives::alternatives`]
+    pub fn alternatives(&self, a: i64, b: i64, count: usize, max_stretch: f64, max_overlap: f64) -> Result<Vec<Route>, RouteError> {
+        alternatives::alternatives(self, a, b, count, max_stretch, max_overlap)
+    }
+
+    /// Distances and durations of the best routes from each of `sources`
+    /// to each of `targets`
+    ///
+    /// Rows are computed in parallel, see [`matrix::compute`].
+    pub fn matrix(&self, sources: &[i64], targets: &[i64]) -> Matrix {
+        matrix::compute(self, sources, targets)
+    }
+
+    /// Every node reachable from `start` within `budget` with its cost,
+    /// see [`isochrone::reachable`]
+    pub fn reachable(&self, start: i64, budget: f64, metric: Metric) -> Result<Vec<(i64, f64)>, RouteError> {
+        isochrone::reachable(self, start, budget, metric)
+    }
+
+    /// Isochrone polygons around `start` for each of `cutoffs`,
+    /// see [`isochrone::isochrones`]
+    pub fn isochrones(&self, start: i64, cutoffs: &[f64], metric: Metric, alpha: f64) -> Result<Vec<Isochrone>, RouteError> {
+        isochrone::isochrones(self, start, cutoffs, metric, alpha)
+    }
+
+    pub(crate) fn segment_index(&self) -> &SegmentIndex {
+        self.segments.get_or_init(|| SegmentIndex::new(self))
+    }
+
+    /// Closest point of a street within `max_distance` meters of the
+    /// coordinate, see [`snap::snap`]
+    pub fn snap(&self, lat: f64, lon: f64, max_distance: f64, heading: Option<f64>) -> Option<Snap> {
+        snap::snap(self, lat, lon, max_distance, heading)
+    }
+
+    /// Every street segment within `radius` meters of the coordinate,
+    /// see [`snap::candidates`]
+    pub fn candidates(&self, lat: f64, lon: f64, radius: f64, heading: Option<f64>) -> Vec<Snap> {
+        snap::candidates(self, lat, lon, radius, heading)
+    }
+
+    /// Best route between two (lat, lon) coordinates snapped to the
+    /// network, see [`snap::route`]
+    pub fn route_coords(&self, from: (f64, f64), to: (f64, f64), max_distance: f64) -> Result<Route, RouteError> {
+        snap::route(self, from, to, max_distance)
+    }
+
+    /// Betweenness centrality of every node, see [`centrality::betweenness`]
+    pub fn betweenness(&self, metric: Metric, normalized: bool) -> HashMap<i64, f64> {
+        centrality::betweenness(self, metric, normalized)
+    }
+
+    /// Betweenness centrality estimated from `samples` sources, see
+    /// [`centrality::sampled_betweenness`]
+    pub fn sampled_betweenness(&self, metric: Metric, samples: usize, seed: u64, normalized: bool) -> HashMap<i64, f64> {
+        centrality::sampled_betweenness(self, metric, samples, seed, normalized)
+    }
+
+    /// Closeness centrality of every node, see [`centrality::closeness`]
+    pub fn closeness(&self, metric: Metric) -> HashMap<i64, f64> {
+        centrality::closeness(self, metric)
+    }
+
+    /// Straightness centrality of every node, see [`centrality::straightness`]
+    pub fn straightness(&self) -> HashMap<i64, f64> {
+        centrality::straightness(self)
+    }
+
+    /// Streets driven along by a GPS trace, see [`matching::match_trace`]
+    pub fn match_trace(&self, trace: &[GpsPoint], options: &MatchOptions) -> Result<Matching, String> {
+        matching::match_trace(self, trace, options)
+    }
+
+    /// Network node closest to the point, None when the network is empty
+    /// or the point is not finite
+    pub fn nearest_node(&self, lat: f64, lon: f64) -> Option<i64> {
+        self.segment_index().nearest_node(self, lat, lon)
+        .map(|id| self.node_idx.node(id))
+    }
+
+    /// Turn-by-turn instructions of a route computed on this network
+    pub fn instructions(&self, route: &Route) -> Vec<Step> {
+        instructions::steps(self, route)
+    }
+
+    /// Returns Node Id list from `a` to `b`
+    ///
+    /// The returned list is empty if there is no possible route or
+    /// if either node is not part of the network, see [`route`](Self::route)
+    /// to tell the two apart.
+    pub fn shortest_path(&self, a: i64, b: i64) -> Vec<i64> {
+        self.route(a, b).map(|r| r.nodes).unwrap_or_default()
     }
 
     pub fn serialize(&self) -> Vec<u8> {
@@ -98,13 +696,104 @@ impl StreetNetwork {
     }
 }
 
+/// Frozen input graph with every edge of `edges`
+fn input_graph(node_idx: &NodeMap<i64>, edges: &[Edge]) -> fast_paths::InputGraph {
+    let mut graph = fast_paths::InputGraph::new();
+    for edge in edges {
+        graph.add_edge(edge.from, edge.to, edge.weight);
+    }
+    node_idx.guarantee_node_ordering(&mut graph);
+    graph.freeze();
+    graph
+}
+
+/// Copy the junction nodes of turn restrictions so that forbidden turns
+/// have no edge in the graph
+///
+/// A copy stands for "arrived at the node along a given sequence of nodes"
+/// and only has the outgoing edges allowed after that sequence. The edge
+/// entering a restricted junction is redirected to the copy, so the graph
+/// stays node based and is contracted as usual, routes ending at a split
+/// node have to try each of its copies. A sequence leaving a junction only
+/// starts another restriction on its last edge.
+///
+/// Returns the edges of the split graph and the copies of each OSM node.
+fn split_restricted_nodes(
+    node_idx: &mut NodeMap<i64>,
+    edges: Vec<Edge>,
+    restrictions: &[NodeRestriction],
+) -> (Vec<Edge>, HashMap<i64, Vec<usize>>) {
+    // Graph node copy of each sequence leading into a junction
+    let mut states: BTreeMap<Vec<usize>, usize> = BTreeMap::new();
+    let mut rules: BTreeMap<Vec<usize>, Vec<(RestrictionKind, Vec<usize>)>> = BTreeMap::new();
+    let mut copies: HashMap<i64, Vec<usize>> = HashMap::new();
+
+    for restriction in restrictions {
+        let nodes = restriction.nodes.iter()
+        .map(|n| node_idx.find(*n))
+        .collect::<Option<Vec<usize>>>();
+        let nodes = match nodes {
+            Some(nodes) => nodes,
+            None => continue,
+        };
+
+        for len in 2..=nodes.len() {
+            if !states.contains_key(&nodes[..len]) {
+                let node = node_idx.node(nodes[len - 1]);
+                let copy = node_idx.insert_copy(node);
+                copies.entry(node).or_default().push(copy);
+                states.insert(nodes[..len].to_vec(), copy);
+            }
+        }
+
+        let to = restriction.to.iter().filter_map(|n| node_idx.find(*n)).collect();
+        rules.entry(nodes).or_default().push((restriction.kind, to));
+    }
+
+    if states.is_empty() {
+        return (edges, copies);
+    }
+
+    let state = |sequence: &[usize]| states.get(sequence).copied();
+    let mut outgoing: HashMap<usize, Vec<&Edge>> = HashMap::new();
+    for edge in &edges {
+        outgoing.entry(edge.from).or_default().push(edge);
+    }
+
+    let mut split = edges.iter()
+    .map(|e| Edge { to: state(&[e.from, e.to]).unwrap_or(e.to), ..e.clone() })
+    .collect::<Vec<Edge>>();
+
+    for (sequence, copy) in &states {
+        let last = sequence[sequence.len() - 1];
+        for edge in outgoing.get(&last).into_iter().flatten() {
+            let next = edge.to;
+            let allowed = rules.get(sequence).into_iter().flatten()
+            .all(|(kind, to)| match kind {
+                RestrictionKind::No => !to.contains(&next),
+                RestrictionKind::Only => to.contains(&next),
+            });
+            if !allowed {
+                continue;
+            }
+
+            let mut longer = sequence.clone();
+            longer.push(next);
+            let to = state(&longer).or_else(|| state(&[last, next])).unwrap_or(next);
+            split.push(Edge { from: *copy, to, ..(*edge).clone() });
+        }
+    }
+
+    (split, copies)
+}
+
 #[test]
 fn test_fastpath() {
     println!("Creating Map!");
-    let map = crate::map::Map::new("resources/madina.osm".into());
+    let map = crate::map::Map::new("resources/madina.osm".into()).unwrap();
 
     println!("Into StreetNetwork!");
-    let mut gra = StreetNetwork::new(&map, vec![
+    let gra = StreetNetwork::new(&map, vec![
         "primary"      , "secondary"      , "tertiary",
         "primary_link" , "secondary_link" , "tertiary_link",
         "residential"  , "service"
@@ -115,3 +804,351 @@ fn test_fastpath() {
     let sp = gra.shortest_path(1, 12);
     println!("Shortest path: {:?}", sp);
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+
+    const GRID: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.001" lon="0.000"/>
+      <node id="5" lat="0.001" lon="0.001"/>
+      <node id="6" lat="0.001" lon="0.002"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+      <way id="11"><nd ref="4"/><nd ref="5"/><nd ref="6"/><tag k="highway" v="residential"/></way>
+      <way id="12"><nd ref="1"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+      <way id="13"><nd ref="3"/><nd ref="6"/><tag k="highway" v="residential"/></way>
+    </osm>"#;
+
+    #[test]
+    fn prepared_graph_is_shared_between_threads() {
+        let map = map::Map::from_xml(GRID.as_bytes()).unwrap();
+        let network = Arc::new(StreetNetwork::new(&map, vec!["residential"]));
+
+        let handles = (0..4).map(|_| {
+            let network = network.clone();
+            std::thread::spawn(move || network.shortest_path(1, 6))
+        })
+        .collect::<Vec<_>>();
+
+        for handle in handles {
+            let path = handle.join().unwrap();
+            assert_eq!(path.first(), Some(&1));
+            assert_eq!(path.last(), Some(&6));
+            assert_eq!(path.len(), 4);
+        }
+        assert!(network.prepared.get().is_some());
+    }
+
+    #[test]
+    fn edge_weight_is_geodesic_length() {
+        // 0.001 degree apart in both directions, at 60 degrees north a
+        // degree of longitude is half as long as a degree of latitude.
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="60.000" lon="10.000"/>
+          <node id="2" lat="60.000" lon="10.001"/>
+          <node id="3" lat="60.001" lon="10.000"/>
+          <way id="10"><nd ref="2"/><nd ref="1"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+        </osm>"#;
+        let map = map::Map::from_xml(xml.as_bytes()).unwrap();
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+
+        let weight = |a: i64, b: i64| {
+            let (a, b) = (network.node_idx.get(a), network.node_idx.get(b));
+            network.inner.get_edges().iter()
+            .find(|e| e.from == a && e.to == b)
+            .map(|e| e.weight)
+            .unwrap()
+        };
+        assert_eq!(weight(1, 2), 56);
+        assert_eq!(weight(1, 3), 111);
+        assert_eq!(map.way(10).unwrap().length(&map).round(), 167.0);
+    }
+
+    #[test]
+    fn unknown_node_has_no_path() {
+        let map = map::Map::from_xml(GRID.as_bytes()).unwrap();
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+
+        assert!(network.shortest_path(1, 999).is_empty());
+
+        // No way is accessible by the profile at all
+        let empty = StreetNetwork::from_profile(&map, RoutingProfile::new("none", TravelMode::Car));
+        assert!(empty.shortest_path(1, 2).is_empty());
+    }
+
+    #[test]
+    fn graph_change_drops_prepared_graph() {
+        let map = map::Map::from_xml(GRID.as_bytes()).unwrap();
+        let mut network = StreetNetwork::new(&map, vec!["residential"]);
+        assert_eq!(network.shortest_path(2, 5).len(), 4);
+
+        // Shortcut from 2 to 5
+        let (a, b) = (network.node_idx.get(2), network.node_idx.get(5));
+        let graph = network.graph_mut();
+        graph.thaw();
+        graph.add_edge_bidir(a, b, 1);
+        graph.freeze();
+
+        assert_eq!(network.shortest_path(2, 5), vec![2, 5]);
+
+        // The shortcut has no way but still has a segment
+        let route = network.route(2, 5).unwrap();
+        assert_eq!(route.segments.len(), route.nodes.len() - 1);
+        assert_eq!(route.segments[0].way, 0);
+        assert!(route.ways.is_empty());
+        assert!(route.distance > 0.0 && route.duration > 0.0);
+        let matrix = network.matrix(&[2], &[5]);
+        assert_eq!((matrix.distances[0][0], matrix.durations[0][0]), (Some(route.distance), Some(route.duration)));
+    }
+
+    /// Direct street 1-2 whose tags vary, with a longer two-way detour 1-3-2
+    fn detour(tags: &str) -> map::Map {
+        let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.000" lon="0.000"/>
+          <node id="2" lat="0.000" lon="0.002"/>
+          <node id="3" lat="0.001" lon="0.001"/>
+          <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="residential"/>{}</way>
+          <way id="11"><nd ref="1"/><nd ref="3"/><nd ref="2"/><tag k="highway" v="residential"/></way>
+        </osm>"#, tags);
+        map::Map::from_xml(xml.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn oneway_street_is_directed() {
+        let map = detour(r#"<tag k="oneway" v="yes"/>"#);
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+
+        assert_eq!(network.profile.mode, TravelMode::Car);
+        assert_eq!(network.shortest_path(1, 2), vec![1, 2]);
+        assert_eq!(network.shortest_path(2, 1), vec![2, 3, 1]);
+
+        let map = detour(r#"<tag k="oneway" v="-1"/>"#);
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+        assert_eq!(network.shortest_path(1, 2), vec![1, 3, 2]);
+        assert_eq!(network.shortest_path(2, 1), vec![2, 1]);
+    }
+
+    #[test]
+    fn roundabout_is_implied_oneway() {
+        let map = detour(r#"<tag k="junction" v="roundabout"/>"#);
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+
+        assert_eq!(network.shortest_path(1, 2), vec![1, 2]);
+        assert_eq!(network.shortest_path(2, 1), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn oneway_depends_on_travel_mode() {
+        let map = detour(r#"<tag k="oneway" v="yes"/><tag k="oneway:bicycle" v="no"/>"#);
+
+        let car = StreetNetwork::with_mode(&map, vec!["residential"], TravelMode::Car);
+        assert_eq!(car.shortest_path(2, 1), vec![2, 3, 1]);
+
+        let bicycle = StreetNetwork::with_mode(&map, vec!["residential"], TravelMode::Bicycle);
+        assert_eq!(bicycle.shortest_path(2, 1), vec![2, 1]);
+
+        let map = detour(r#"<tag k="oneway" v="yes"/>"#);
+        let foot = StreetNetwork::with_mode(&map, vec!["residential"], TravelMode::Foot);
+        assert_eq!(foot.shortest_path(2, 1), vec![2, 1]);
+    }
+
+    #[test]
+    fn reversible_street_is_not_routable() {
+        let map = detour(r#"<tag k="oneway" v="reversible"/>"#);
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+
+        assert_eq!(network.shortest_path(1, 2), vec![1, 3, 2]);
+        assert_eq!(network.shortest_path(2, 1), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn profile_decides_access_and_weights() {
+        // Short residential street 1-2 and a longer primary detour 1-3-2
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.000" lon="0.000"/>
+          <node id="2" lat="0.000" lon="0.002"/>
+          <node id="3" lat="0.001" lon="0.001"/>
+          <node id="4" lat="0.001" lon="0.002"/>
+          <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="residential"/></way>
+          <way id="11"><nd ref="1"/><nd ref="3"/><nd ref="2"/><tag k="highway" v="primary"/></way>
+          <way id="12"><nd ref="2"/><nd ref="4"/><tag k="highway" v="footway"/></way>
+        </osm>"#;
+        let map = map::Map::from_xml(xml.as_bytes()).unwrap();
+
+        let fastest = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        assert_eq!(fastest.shortest_path(1, 2), vec![1, 3, 2]);
+        assert!(fastest.shortest_path(1, 4).is_empty());
+
+        let shortest = StreetNetwork::from_profile(&map, RoutingProfile::car().with_metric(Metric::Distance));
+        assert_eq!(shortest.shortest_path(1, 2), vec![1, 2]);
+
+        let foot = StreetNetwork::from_profile(&map, RoutingProfile::foot());
+        assert_eq!(foot.shortest_path(1, 4), vec![1, 2, 4]);
+
+        // 222 meters at 30 km/h
+        let weight = fastest.inner.get_edges().iter()
+        .find(|e| e.from == fastest.node_idx.get(1) && e.to == fastest.node_idx.get(2))
+        .map(|e| e.weight);
+        assert_eq!(weight, Some(26_687));
+    }
+
+    #[test]
+    fn route_reports_distance_and_duration() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.000" lon="0.000"/>
+          <node id="2" lat="0.000" lon="0.001"/>
+          <node id="3" lat="0.000" lon="0.002"/>
+          <way id="10">
+            <nd ref="1"/><nd ref="2"/>
+            <tag k="highway" v="residential"/><tag k="maxspeed" v="20 mph"/>
+          </way>
+          <way id="11">
+            <nd ref="2"/><nd ref="3"/>
+            <tag k="highway" v="primary"/><tag k="maxspeed" v="60"/><tag k="maxspeed:backward" v="30"/>
+          </way>
+        </osm>"#;
+        let map = map::Map::from_xml(xml.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let edge = network.edge(2, 3).unwrap();
+        assert_eq!(edge.way, 11);
+        assert!((edge.distance - 111.2).abs() < 0.1);
+        assert!((edge.duration - 6.67).abs() < 0.01);
+        assert!((network.edge(3, 2).unwrap().duration - 13.34).abs() < 0.01);
+        assert_eq!(edge.weight, 6_672);
+
+        let route = network.route(1, 3).unwrap();
+        assert_eq!(route.nodes, vec![1, 2, 3]);
+        assert!((route.distance - 222.4).abs() < 0.1);
+        // 111.2 meters at 20 mph then at 60 km/h
+        assert!((route.duration - (12.44 + 6.67)).abs() < 0.01);
+        assert_eq!(route.weight, network.edge(1, 2).unwrap().weight + edge.weight);
+
+        let route = network.route(2, 2).unwrap();
+        assert_eq!((route.nodes, route.distance, route.duration), (vec![2], 0.0, 0.0));
+        assert_eq!(network.route(1, 999), Err(RouteError::UnknownNode(999)));
+    }
+
+    /// Cross junction at 2 with a loop 3-6-5, restriction relation 1 is
+    /// made of the given members and tags
+    fn junction(members: &str, tags: &str) -> map::Map {
+        let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.000" lon="0.000"/>
+          <node id="2" lat="0.000" lon="0.001"/>
+          <node id="3" lat="0.000" lon="0.002"/>
+          <node id="4" lat="-0.001" lon="0.001"/>
+          <node id="5" lat="0.001" lon="0.001"/>
+          <node id="6" lat="0.001" lon="0.0015"/>
+          <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="residential"/></way>
+          <way id="11"><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+          <way id="12"><nd ref="2"/><nd ref="5"/><tag k="highway" v="residential"/></way>
+          <way id="13"><nd ref="5"/><nd ref="6"/><tag k="highway" v="residential"/></way>
+          <way id="14"><nd ref="3"/><nd ref="6"/><tag k="highway" v="residential"/></way>
+          <way id="15"><nd ref="2"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+          <relation id="1">{}<tag k="type" v="restriction"/>{}</relation>
+        </osm>"#, members, tags);
+        map::Map::from_xml(xml.as_bytes()).unwrap()
+    }
+
+    const VIA_NODE: &str = r#"
+        <member type="way" ref="10" role="from"/>
+        <member type="node" ref="2" role="via"/>"#;
+
+    #[test]
+    fn no_turn_at_via_node() {
+        let members = format!(r#"{}<member type="way" ref="12" role="to"/>"#, VIA_NODE);
+        let map = junction(&members, r#"<tag k="restriction" v="no_left_turn"/>"#);
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        assert_eq!(network.shortest_path(1, 5), vec![1, 2, 3, 6, 5]);
+        assert_eq!(network.shortest_path(1, 2), vec![1, 2]);
+        assert_eq!(network.shortest_path(1, 4), vec![1, 2, 4]);
+        assert_eq!(network.shortest_path(4, 5), vec![4, 2, 5]);
+        assert_eq!(network.shortest_path(2, 5), vec![2, 5]);
+        assert_eq!(network.edge(1, 2).map(|e| e.way), Some(10));
+
+        // Pedestrians ignore it
+        let foot = StreetNetwork::from_profile(&map, RoutingProfile::foot());
+        assert_eq!(foot.shortest_path(1, 5), vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn only_turn_at_via_node() {
+        let members = format!(r#"{}<member type="way" ref="11" role="to"/>"#, VIA_NODE);
+        let map = junction(&members, r#"<tag k="restriction" v="only_straight_on"/>"#);
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        assert_eq!(network.shortest_path(1, 3), vec![1, 2, 3]);
+        // Turning back at 3 is the shortest legal way
+        assert_eq!(network.shortest_path(1, 4), vec![1, 2, 3, 2, 4]);
+        assert_eq!(network.shortest_path(5, 4), vec![5, 2, 4]);
+
+        let route = network.route(1, 2).unwrap();
+        assert_eq!(route.nodes, vec![1, 2]);
+        assert_eq!(route.weight, network.edge(1, 2).unwrap().weight);
+    }
+
+    #[test]
+    fn no_turn_after_via_way() {
+        let members = r#"
+            <member type="way" ref="10" role="from"/>
+            <member type="way" ref="12" role="via"/>
+            <member type="way" ref="13" role="to"/>"#;
+        let map = junction(members, r#"<tag k="restriction" v="no_right_turn"/>"#);
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        assert_eq!(network.shortest_path(1, 6), vec![1, 2, 3, 6]);
+        assert_eq!(network.shortest_path(1, 5), vec![1, 2, 5]);
+        assert_eq!(network.shortest_path(4, 6), vec![4, 2, 5, 6]);
+        assert_eq!(network.shortest_path(2, 6), vec![2, 5, 6]);
+
+        // The split graph survives a round trip
+        let network = StreetNetwork::deserialize(network.serialize());
+        assert_eq!(network.shortest_path(1, 6), vec![1, 2, 3, 6]);
+    }
+
+    #[test]
+    fn route_describes_the_streets() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.000" lon="0.000"/>
+          <node id="2" lat="0.000" lon="0.001"/>
+          <node id="3" lat="0.000" lon="0.002"/>
+          <node id="4" lat="0.001" lon="0.002"/>
+          <node id="5" lat="0.010" lon="0.010"/>
+          <node id="6" lat="0.010" lon="0.011"/>
+          <way id="10">
+            <nd ref="1"/><nd ref="2"/><nd ref="3"/>
+            <tag k="highway" v="residential"/><tag k="name" v="Jalan Merdeka"/>
+          </way>
+          <way id="11"><nd ref="3"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+          <way id="12"><nd ref="5"/><nd ref="6"/><tag k="highway" v="residential"/></way>
+        </osm>"#;
+        let map = map::Map::from_xml(xml.as_bytes()).unwrap();
+        let network = StreetNetwork::new(&map, vec!["residential"]);
+
+        let route = network.route(1, 4).unwrap();
+        assert_eq!(route.nodes, vec![1, 2, 3, 4]);
+        assert_eq!(route.geometry, vec![(0.0, 0.0), (0.0, 0.001), (0.0, 0.002), (0.001, 0.002)]);
+        assert_eq!(route.ways, vec![10, 11]);
+        assert_eq!(route.names, vec!["Jalan Merdeka".to_string()]);
+        assert_eq!(route.segments.len(), 3);
+        assert_eq!(route.segments.iter().map(|s| s.way).collect::<Vec<i64>>(), vec![10, 10, 11]);
+        assert_eq!(route.distance, route.segments.iter().map(|s| s.distance).sum::<f64>());
+
+        assert_eq!(network.route(1, 5), Err(RouteError::NoRoute(1, 5)));
+        assert_eq!(network.route(7, 5), Err(RouteError::UnknownNode(7)));
+        assert_eq!(network.route(1, 7).unwrap_err().to_string(), "Node 7 is not part of the network");
+    }
+}
@@ -0,0 +1,763 @@
+/* pbf.rs */
+//! Reader for the OSM PBF (protocol buffer binary) format.
+//!
+//! The format is a sequence of length prefixed blobs, each one holding either
+//! the file header or a block of primitives (nodes, dense nodes, ways, relations).
+//! See https://wiki.openstreetmap.org/wiki/PBF_Format for the full layout.
+//! Only the parts needed to build a `map::Map` are decoded here.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+use fnv::FnvHashMap;
+
+use crate::map::{Bounds, Member, MemberType, Node, Relation, Way};
+
+/// Error raised while reading a PBF file
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The content is not a valid protocol buffer message
+    Decode(&'static str),
+    /// The content is valid but uses a feature we can not read
+    Unsupported(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Read failed: {}", e),
+            Self::Decode(msg) => write!(f, "PBF decode failed: {}", msg),
+            Self::Unsupported(feature) => write!(f, "PBF feature not supported: {}", feature),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Largest BlobHeader allowed by the format
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+
+/// Largest Blob allowed by the format, compressed or not
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+
+/// Elements decoded from a PBF file
+#[derive(Default)]
+pub struct Document {
+    pub nodes: FnvHashMap<i64, Node>,
+    pub ways: FnvHashMap<i64, Way>,
+    pub relations: FnvHashMap<i64, Relation>,
+    pub bounds: Option<Bounds>,
+}
+
+/// Returns true when `head` looks like the beginning of a PBF file
+///
+/// A PBF file starts with the 4 bytes length of the first BlobHeader,
+/// whose first field is the blob type string "OSMHeader".
+pub fn sniff(head: &[u8]) -> bool {
+    const MAGIC: &[u8] = b"OSMHeader";
+    head.len() >= 6 + MAGIC.len()
+        && head[4] == 0x0A
+        && head[6..].starts_with(MAGIC)
+}
+
+/// Parse the whole PBF stream into a `Document`
+pub fn parse<R: Read>(mut reader: R) -> Result<Document> {
+    let mut doc = Document::default();
+
+    let mut len_buf = [0u8; 4];
+    loop {
+        // A clean end of stream can only happen at a blob boundary
+        match reader.read_exact(&mut len_buf) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+
+        // Sizes are checked before allocating, a corrupt file must not
+        // exhaust the memory
+        let header_len = u32::from_be_bytes(len_buf) as usize;
+        if header_len > MAX_HEADER_SIZE {
+            return Err(Error::Decode("BlobHeader is larger than 64 KiB"));
+        }
+        let mut header = vec![0u8; header_len];
+        reader.read_exact(&mut header)?;
+        let (blob_type, blob_len) = read_blob_header(&header)?;
+        if blob_len > MAX_BLOB_SIZE {
+            return Err(Error::Decode("Blob is larger than 32 MiB"));
+        }
+
+        let mut blob = vec![0u8; blob_len];
+        reader.read_exact(&mut blob)?;
+        let data = read_blob(&blob)?;
+
+        match blob_type.as_str() {
+            "OSMHeader" => doc.bounds = read_header_block(&data)?,
+            "OSMData" => read_primitive_block(&data, &mut doc)?,
+            // Unknown blob types must be skipped according to the spec
+            _ => (),
+        }
+    }
+
+    Ok(doc)
+}
+
+fn read_blob_header(buf: &[u8]) -> Result<(String, usize)> {
+    let mut blob_type = None;
+    let mut datasize = None;
+
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => blob_type = Some(value.as_string()?),
+            3 => datasize = Some(value.as_varint()? as usize),
+            _ => (),
+        }
+    }
+
+    match (blob_type, datasize) {
+        (Some(t), Some(s)) => Ok((t, s)),
+        _ => Err(Error::Decode("BlobHeader misses type or datasize")),
+    }
+}
+
+fn read_blob(buf: &[u8]) -> Result<Vec<u8>> {
+    let mut msg = Message::new(buf);
+    let mut raw_size = 0;
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => return Ok(value.as_bytes()?.to_vec()),
+            2 => raw_size = value.as_varint()? as usize,
+            3 => {
+                if raw_size > MAX_BLOB_SIZE {
+                    return Err(Error::Decode("Uncompressed blob is larger than 32 MiB"));
+                }
+                let mut data = Vec::with_capacity(raw_size);
+                flate2::read::ZlibDecoder::new(value.as_bytes()?)
+                .take(MAX_BLOB_SIZE as u64 + 1)
+                .read_to_end(&mut data)?;
+                if data.len() > MAX_BLOB_SIZE {
+                    return Err(Error::Decode("Uncompressed blob is larger than 32 MiB"));
+                }
+                return Ok(data);
+            },
+            4 => return Err(Error::Unsupported("lzma compressed blob".to_owned())),
+            5 => return Err(Error::Unsupported("bzip2 compressed blob".to_owned())),
+            6 => return Err(Error::Unsupported("lz4 compressed blob".to_owned())),
+            7 => return Err(Error::Unsupported("zstd compressed blob".to_owned())),
+            _ => (),
+        }
+    }
+    Err(Error::Decode("Blob has no data"))
+}
+
+fn read_header_block(buf: &[u8]) -> Result<Option<Bounds>> {
+    let mut bounds = None;
+
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => bounds = Some(read_header_bbox(value.as_bytes()?)?),
+            4 => {
+                let feature = value.as_string()?;
+                if feature != "OsmSchema-V0.6" && feature != "DenseNodes" {
+                    return Err(Error::Unsupported(feature));
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(bounds)
+}
+
+fn read_header_bbox(buf: &[u8]) -> Result<Bounds> {
+    // Values are stored in nanodegrees
+    let mut bbox = [0i64; 4];
+
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        if (1..=4).contains(&field) {
+            bbox[field as usize - 1] = zigzag(value.as_varint()?);
+        }
+    }
+
+    let [left, right, top, bottom] = bbox;
+    Ok(Bounds {
+        minlat: bottom as f64 * 1e-9,
+        minlon: left as f64 * 1e-9,
+        maxlat: top as f64 * 1e-9,
+        maxlon: right as f64 * 1e-9,
+    })
+}
+
+/// Coordinate and string context shared by every group of a block
+struct Block {
+    strings: Vec<String>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+}
+
+impl Block {
+    fn lat(&self, raw: i64) -> f64 {
+        (self.lat_offset + self.granularity * raw) as f64 * 1e-9
+    }
+
+    fn lon(&self, raw: i64) -> f64 {
+        (self.lon_offset + self.granularity * raw) as f64 * 1e-9
+    }
+
+    fn string(&self, idx: u64) -> Result<String> {
+        self.strings.get(idx as usize)
+        .cloned()
+        .ok_or(Error::Decode("String table index out of range"))
+    }
+
+    fn tags(&self, keys: &[u64], vals: &[u64]) -> Result<HashMap<String, String>> {
+        if keys.len() != vals.len() {
+            return Err(Error::Decode("Tag keys and values length differ"));
+        }
+        keys.iter().zip(vals.iter())
+        .map(|(k, v)| Ok((self.string(*k)?, self.string(*v)?)))
+        .collect()
+    }
+}
+
+fn read_primitive_block(buf: &[u8], doc: &mut Document) -> Result<()> {
+    let mut block = Block {
+        strings: vec![],
+        granularity: 100,
+        lat_offset: 0,
+        lon_offset: 0,
+    };
+    let mut groups = vec![];
+
+    // Groups are decoded last since they depend on fields that may come after them
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => block.strings = read_string_table(value.as_bytes()?)?,
+            2 => groups.push(value.as_bytes()?),
+            17 => block.granularity = value.as_varint()? as i64,
+            19 => block.lat_offset = value.as_varint()? as i64,
+            20 => block.lon_offset = value.as_varint()? as i64,
+            _ => (),
+        }
+    }
+
+    for group in groups {
+        read_primitive_group(group, &block, doc)?;
+    }
+    Ok(())
+}
+
+fn read_string_table(buf: &[u8]) -> Result<Vec<String>> {
+    let mut strings = vec![];
+
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        if field == 1 {
+            strings.push(value.as_string()?);
+        }
+    }
+    Ok(strings)
+}
+
+fn read_primitive_group(buf: &[u8], block: &Block, doc: &mut Document) -> Result<()> {
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => {
+                let node = read_node(value.as_bytes()?, block)?;
+                doc.nodes.insert(node.id, node);
+            },
+            2 => read_dense_nodes(value.as_bytes()?, block, doc)?,
+            3 => {
+                let way = read_way(value.as_bytes()?, block)?;
+                doc.ways.insert(way.id, way);
+            },
+            4 => {
+                let relation = read_relation(value.as_bytes()?, block)?;
+                doc.relations.insert(relation.id, relation);
+            },
+            // Changesets are not stored in Map
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn read_node(buf: &[u8], block: &Block) -> Result<Node> {
+    let mut id = 0;
+    let mut keys = vec![];
+    let mut vals = vec![];
+    let mut lat = 0;
+    let mut lon = 0;
+
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => id = zigzag(value.as_varint()?),
+            2 => value.extend_varints(&mut keys)?,
+            3 => value.extend_varints(&mut vals)?,
+            8 => lat = zigzag(value.as_varint()?),
+            9 => lon = zigzag(value.as_varint()?),
+            _ => (),
+        }
+    }
+
+    Ok(Node {
+        id,
+        lat: block.lat(lat),
+        lon: block.lon(lon),
+        tags: block.tags(&keys, &vals)?,
+    })
+}
+
+fn read_dense_nodes(buf: &[u8], block: &Block, doc: &mut Document) -> Result<()> {
+    let mut ids = vec![];
+    let mut lats = vec![];
+    let mut lons = vec![];
+    let mut keys_vals = vec![];
+
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => value.extend_varints(&mut ids)?,
+            8 => value.extend_varints(&mut lats)?,
+            9 => value.extend_varints(&mut lons)?,
+            10 => value.extend_varints(&mut keys_vals)?,
+            _ => (),
+        }
+    }
+
+    if ids.len() != lats.len() || ids.len() != lons.len() {
+        return Err(Error::Decode("DenseNodes arrays length differ"));
+    }
+
+    // Tags of every node are packed into one array, each node ends with a 0
+    let mut kv = keys_vals.into_iter();
+    let (mut id, mut lat, mut lon) = (0i64, 0i64, 0i64);
+    for i in 0..ids.len() {
+        id += zigzag(ids[i]);
+        lat += zigzag(lats[i]);
+        lon += zigzag(lons[i]);
+
+        let mut tags = HashMap::new();
+        while let Some(k) = kv.next() {
+            if k == 0 {
+                break;
+            }
+            let v = kv.next().ok_or(Error::Decode("DenseNodes tag has no value"))?;
+            tags.insert(block.string(k)?, block.string(v)?);
+        }
+
+        doc.nodes.insert(id, Node {
+            id,
+            lat: block.lat(lat),
+            lon: block.lon(lon),
+            tags,
+        });
+    }
+    Ok(())
+}
+
+fn read_way(buf: &[u8], block: &Block) -> Result<Way> {
+    let mut id = 0;
+    let mut keys = vec![];
+    let mut vals = vec![];
+    let mut refs = vec![];
+
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => id = value.as_varint()? as i64,
+            2 => value.extend_varints(&mut keys)?,
+            3 => value.extend_varints(&mut vals)?,
+            8 => value.extend_varints(&mut refs)?,
+            _ => (),
+        }
+    }
+
+    Ok(Way {
+        id,
+        tags: block.tags(&keys, &vals)?,
+        nodes: delta_decode(&refs),
+    })
+}
+
+fn read_relation(buf: &[u8], block: &Block) -> Result<Relation> {
+    let mut id = 0;
+    let mut keys = vec![];
+    let mut vals = vec![];
+    let mut roles = vec![];
+    let mut memids = vec![];
+    let mut types = vec![];
+
+    let mut msg = Message::new(buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => id = value.as_varint()? as i64,
+            2 => value.extend_varints(&mut keys)?,
+            3 => value.extend_varints(&mut vals)?,
+            8 => value.extend_varints(&mut roles)?,
+            9 => value.extend_varints(&mut memids)?,
+            10 => value.extend_varints(&mut types)?,
+            _ => (),
+        }
+    }
+
+    if roles.len() != memids.len() || roles.len() != types.len() {
+        return Err(Error::Decode("Relation member arrays length differ"));
+    }
+
+    let members = delta_decode(&memids).into_iter()
+    .zip(roles.iter().zip(types.iter()))
+    .map(|(id, (role, member_type))| {
+        let member_type = match member_type {
+            0 => MemberType::Node,
+            1 => MemberType::Way,
+            2 => MemberType::Relation,
+            _ => return Err(Error::Decode("Unknown relation member type")),
+        };
+        Ok(Member {
+            member_type,
+            id,
+            role: block.string(*role)?,
+        })
+    })
+    .collect::<Result<Vec<Member>>>()?;
+
+    Ok(Relation {
+        id,
+        tags: block.tags(&keys, &vals)?,
+        members,
+    })
+}
+
+fn zigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+fn delta_decode(values: &[u64]) -> Vec<i64> {
+    let mut acc = 0;
+    values.iter()
+    .map(|v| {
+        acc += zigzag(*v);
+        acc
+    })
+    .collect()
+}
+
+/// Value of a single protocol buffer field
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    // Fixed width values are not used by any field we read
+    Fixed,
+}
+
+impl<'a> Value<'a> {
+    fn as_varint(&self) -> Result<u64> {
+        match self {
+            Self::Varint(v) => Ok(*v),
+            _ => Err(Error::Decode("Expected a varint field")),
+        }
+    }
+
+    fn as_bytes(&self) -> Result<&'a [u8]> {
+        match self {
+            Self::Bytes(b) => Ok(b),
+            _ => Err(Error::Decode("Expected a length delimited field")),
+        }
+    }
+
+    fn as_string(&self) -> Result<String> {
+        String::from_utf8(self.as_bytes()?.to_vec())
+        .map_err(|_| Error::Decode("String is not valid UTF-8"))
+    }
+
+    /// Append a repeated varint field, either packed or not
+    fn extend_varints(&self, out: &mut Vec<u64>) -> Result<()> {
+        match self {
+            Self::Varint(v) => out.push(*v),
+            Self::Bytes(b) => {
+                let mut msg = Message::new(b);
+                while !msg.is_empty() {
+                    out.push(msg.read_varint()?);
+                }
+            },
+            _ => return Err(Error::Decode("Expected a repeated varint field")),
+        }
+        Ok(())
+    }
+}
+
+/// Cursor over the fields of an encoded protocol buffer message
+struct Message<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Message<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Message { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn read_varint(&mut self) -> Result<u64> {
+        let mut result = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.buf.get(self.pos).ok_or(Error::Decode("Truncated varint"))?;
+            self.pos += 1;
+            result |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(Error::Decode("Varint is too long"))
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len)
+        .filter(|end| *end <= self.buf.len())
+        .ok_or(Error::Decode("Truncated field"))?;
+        let slice = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u32, Value<'a>)>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+
+        let key = self.read_varint()?;
+        let field = (key >> 3) as u32;
+        let value = match key & 0x7 {
+            0 => Value::Varint(self.read_varint()?),
+            1 => {
+                self.read_slice(8)?;
+                Value::Fixed
+            },
+            2 => {
+                let len = self.read_varint()? as usize;
+                Value::Bytes(self.read_slice(len)?)
+            },
+            5 => {
+                self.read_slice(4)?;
+                Value::Fixed
+            },
+            _ => return Err(Error::Decode("Unknown wire type")),
+        };
+        Ok(Some((field, value)))
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    /// Minimal protocol buffer writer to build PBF fixtures in memory
+    #[derive(Default)]
+    pub struct Encoder {
+        pub buf: Vec<u8>,
+    }
+
+    impl Encoder {
+        fn varint(&mut self, mut v: u64) {
+            while v >= 0x80 {
+                self.buf.push((v as u8) | 0x80);
+                v >>= 7;
+            }
+            self.buf.push(v as u8);
+        }
+
+        pub fn uint(&mut self, field: u32, v: u64) -> &mut Self {
+            self.varint((field as u64) << 3);
+            self.varint(v);
+            self
+        }
+
+        pub fn sint(&mut self, field: u32, v: i64) -> &mut Self {
+            self.uint(field, ((v << 1) ^ (v >> 63)) as u64)
+        }
+
+        pub fn bytes(&mut self, field: u32, b: &[u8]) -> &mut Self {
+            self.varint(((field as u64) << 3) | 2);
+            self.varint(b.len() as u64);
+            self.buf.extend_from_slice(b);
+            self
+        }
+
+        pub fn packed(&mut self, field: u32, values: &[u64]) -> &mut Self {
+            let mut inner = Encoder::default();
+            for v in values {
+                inner.varint(*v);
+            }
+            self.bytes(field, &inner.buf)
+        }
+
+        pub fn packed_sint(&mut self, field: u32, values: &[i64]) -> &mut Self {
+            let zz = values.iter()
+            .map(|v| ((v << 1) ^ (v >> 63)) as u64)
+            .collect::<Vec<u64>>();
+            self.packed(field, &zz)
+        }
+    }
+
+    pub fn delta(values: &[i64]) -> Vec<i64> {
+        let mut prev = 0;
+        values.iter()
+        .map(|v| {
+            let d = v - prev;
+            prev = *v;
+            d
+        })
+        .collect()
+    }
+
+    /// Frame a block into a length prefixed BlobHeader + Blob pair
+    pub fn frame(out: &mut Vec<u8>, blob_type: &str, data: &[u8], compress: bool) {
+        let mut blob = Encoder::default();
+        if compress {
+            let mut z = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            z.write_all(data).unwrap();
+            blob.uint(2, data.len() as u64).bytes(3, &z.finish().unwrap());
+        } else {
+            blob.bytes(1, data);
+        }
+
+        let mut header = Encoder::default();
+        header.bytes(1, blob_type.as_bytes()).uint(3, blob.buf.len() as u64);
+
+        out.extend_from_slice(&(header.buf.len() as u32).to_be_bytes());
+        out.extend_from_slice(&header.buf);
+        out.extend_from_slice(&blob.buf);
+    }
+
+    /// A tiny two-block file: a header with bbox and one block with
+    /// a plain node, two dense nodes, a way and a relation.
+    pub fn sample() -> Vec<u8> {
+        let mut out = vec![];
+
+        let mut bbox = Encoder::default();
+        bbox.sint(1, 106_000_000_000).sint(2, 107_000_000_000)
+        .sint(3, -6_000_000_000).sint(4, -7_000_000_000);
+        let mut header = Encoder::default();
+        header.bytes(1, &bbox.buf)
+        .bytes(4, b"OsmSchema-V0.6")
+        .bytes(4, b"DenseNodes");
+        frame(&mut out, "OSMHeader", &header.buf, false);
+
+        let mut strings = Encoder::default();
+        for s in &["", "highway", "residential", "name", "Jalan Merdeka", "amenity", "cafe",
+            "type", "route", "stop"] {
+            strings.bytes(1, s.as_bytes());
+        }
+
+        let mut node = Encoder::default();
+        node.sint(1, 10).packed(2, &[5]).packed(3, &[6])
+        .sint(8, -65_000_000).sint(9, 1_065_000_000);
+
+        let mut dense = Encoder::default();
+        dense.packed_sint(1, &delta(&[11, 12]))
+        .packed_sint(8, &delta(&[-65_100_000, -65_200_000]))
+        .packed_sint(9, &delta(&[1_065_100_000, 1_065_200_000]))
+        .packed(10, &[0, 5, 6, 0]);
+
+        let mut way = Encoder::default();
+        way.uint(1, 100).packed(2, &[1, 3]).packed(3, &[2, 4])
+        .packed_sint(8, &delta(&[10, 11, 12]));
+
+        let mut relation = Encoder::default();
+        relation.uint(1, 200).packed(2, &[7]).packed(3, &[8])
+        .packed(8, &[0, 9]).packed_sint(9, &delta(&[100, 12])).packed(10, &[1, 0]);
+
+        let mut group = Encoder::default();
+        group.bytes(1, &node.buf).bytes(2, &dense.buf).bytes(3, &way.buf).bytes(4, &relation.buf);
+
+        let mut block = Encoder::default();
+        block.bytes(1, &strings.buf).bytes(2, &group.buf).uint(17, 100);
+        frame(&mut out, "OSMData", &block.buf, true);
+
+        out
+    }
+
+    #[test]
+    fn sniff_detects_pbf() {
+        assert!(sniff(&sample()));
+        assert!(!sniff(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?><osm></osm>"));
+    }
+
+    #[test]
+    fn parse_nodes_and_ways() {
+        let doc = parse(&sample()[..]).unwrap();
+
+        assert_eq!(doc.nodes.len(), 3);
+        let node = &doc.nodes[&10];
+        assert!((node.lat - -6.5).abs() < 1e-9);
+        assert!((node.lon - 106.5).abs() < 1e-9);
+        assert_eq!(node.tags.get("amenity").map(|s| s.as_str()), Some("cafe"));
+
+        let dense = &doc.nodes[&12];
+        assert!((dense.lat - -6.52).abs() < 1e-9);
+        assert!((dense.lon - 106.52).abs() < 1e-9);
+        assert_eq!(dense.tags.get("amenity").map(|s| s.as_str()), Some("cafe"));
+        assert!(doc.nodes[&11].tags.is_empty());
+
+        let way = &doc.ways[&100];
+        assert_eq!(way.nodes, vec![10, 11, 12]);
+        assert_eq!(way.tags.get("highway").map(|s| s.as_str()), Some("residential"));
+        assert_eq!(way.tags.get("name").map(|s| s.as_str()), Some("Jalan Merdeka"));
+
+        let relation = &doc.relations[&200];
+        assert_eq!(relation.relation_type(), Some("route"));
+        assert_eq!(relation.members, vec![
+            Member { member_type: MemberType::Way, id: 100, role: "".to_owned() },
+            Member { member_type: MemberType::Node, id: 12, role: "stop".to_owned() },
+        ]);
+
+        let bounds = doc.bounds.unwrap();
+        assert!((bounds.minlat - -7.0).abs() < 1e-9);
+        assert!((bounds.maxlon - 107.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let data = sample();
+        assert!(parse(&data[..data.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn oversized_blobs_are_rejected() {
+        let header = (MAX_HEADER_SIZE as u32 + 1).to_be_bytes();
+        assert!(matches!(parse(&header[..]), Err(Error::Decode(_))));
+
+        let mut blob_header = Encoder::default();
+        blob_header.bytes(1, b"OSMData").uint(3, u32::MAX as u64);
+        let mut data = (blob_header.buf.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(&blob_header.buf);
+        assert!(matches!(parse(&data[..]), Err(Error::Decode(_))));
+
+        let mut blob = Encoder::default();
+        blob.uint(2, u64::MAX).bytes(3, b"");
+        assert!(matches!(read_blob(&blob.buf), Err(Error::Decode(_))));
+    }
+}
@@ -0,0 +1,454 @@
+/* profile.rs */
+//! Routing profiles: which streets may be travelled, in which direction
+//! and how fast, depending on the mode of transport.
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::maxspeed::{self, MaxSpeed};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Mode of transport a network is built for
+pub enum TravelMode {
+    Car,
+    Bicycle,
+    Foot,
+}
+
+impl TravelMode {
+    /// Access tags from the most general to the most specific
+    fn access_keys(&self) -> &'static [&'static str] {
+        match self {
+            TravelMode::Car => &["access", "vehicle", "motor_vehicle", "motorcar"],
+            TravelMode::Bicycle => &["access", "vehicle", "bicycle"],
+            TravelMode::Foot => &["access", "foot"],
+        }
+    }
+
+    /// Speed in km/h used on highway types without a known speed
+    pub fn default_speed(&self) -> f64 {
+        match self {
+            TravelMode::Car => 30.0,
+            TravelMode::Bicycle => 16.0,
+            TravelMode::Foot => 5.0,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TravelMode::Car => "car",
+            TravelMode::Bicycle => "bicycle",
+            TravelMode::Foot => "foot",
+        }
+    }
+}
+
+impl std::str::FromStr for TravelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "car" => Ok(TravelMode::Car),
+            "bicycle" | "bike" => Ok(TravelMode::Bicycle),
+            "foot" => Ok(TravelMode::Foot),
+            _ => Err(format!("Unknown travel mode {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Quantity minimized by the route search
+pub enum Metric {
+    /// Length in meters
+    Distance,
+    /// Travel time
+    Time,
+}
+
+impl std::str::FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "distance" => Ok(Metric::Distance),
+            "time" => Ok(Metric::Time),
+            _ => Err(format!("Unknown metric {}", s)),
+        }
+    }
+}
+
+/// Access values forbidding the way to the general public
+const DENIED: [&str; 6] = ["no", "private", "agricultural", "forestry", "delivery", "use_sidepath"];
+
+const CAR_SPEEDS: [(&str, f64); 15] = [
+    ("motorway", 110.0), ("motorway_link", 60.0),
+    ("trunk", 90.0), ("trunk_link", 50.0),
+    ("primary", 70.0), ("primary_link", 40.0),
+    ("secondary", 60.0), ("secondary_link", 40.0),
+    ("tertiary", 50.0), ("tertiary_link", 30.0),
+    ("unclassified", 40.0), ("residential", 30.0),
+    ("living_street", 10.0), ("service", 15.0), ("road", 30.0),
+];
+
+const BICYCLE_SPEEDS: [(&str, f64); 14] = [
+    ("cycleway", 18.0),
+    ("primary", 16.0), ("primary_link", 16.0),
+    ("secondary", 16.0), ("secondary_link", 16.0),
+    ("tertiary", 16.0), ("tertiary_link", 16.0),
+    ("unclassified", 16.0), ("residential", 16.0), ("road", 16.0),
+    ("living_street", 12.0), ("service", 12.0), ("track", 12.0), ("path", 12.0),
+];
+
+const FOOT_SPEEDS: [(&str, f64); 20] = [
+    ("footway", 5.0), ("pedestrian", 5.0), ("path", 5.0), ("steps", 3.0),
+    ("living_street", 5.0), ("residential", 5.0), ("service", 5.0), ("track", 5.0),
+    ("unclassified", 5.0), ("road", 5.0), ("cycleway", 5.0),
+    ("trunk", 5.0), ("trunk_link", 5.0),
+    ("primary", 5.0), ("primary_link", 5.0),
+    ("secondary", 5.0), ("secondary_link", 5.0),
+    ("tertiary", 5.0), ("tertiary_link", 5.0), ("corridor", 5.0),
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// Rules deciding which ways a network is made of and how edges are weighted
+///
+/// Only ways whose ``highway`` type has a speed are routable, access tags
+/// of the travel mode may still forbid them.
+pub struct RoutingProfile {
+    pub name: String,
+    pub mode: TravelMode,
+    pub metric: Metric,
+    /// Speed in km/h for each routable ``highway`` type
+    pub speeds: HashMap<String, f64>,
+}
+
+impl RoutingProfile {
+    /// Empty custom profile, add highway types with [`with_speed`](Self::with_speed)
+    pub fn new(name: &str, mode: TravelMode) -> Self {
+        RoutingProfile {
+            name: name.to_string(),
+            mode,
+            metric: Metric::Time,
+            speeds: HashMap::new(),
+        }
+    }
+
+    fn with_speeds(name: &str, mode: TravelMode, speeds: &[(&str, f64)]) -> Self {
+        speeds.iter().fold(Self::new(name, mode), |p, (h, s)| p.with_speed(h, *s))
+    }
+
+    pub fn car() -> Self {
+        Self::with_speeds("car", TravelMode::Car, &CAR_SPEEDS)
+    }
+
+    pub fn bicycle() -> Self {
+        Self::with_speeds("bicycle", TravelMode::Bicycle, &BICYCLE_SPEEDS)
+    }
+
+    pub fn foot() -> Self {
+        Self::with_speeds("foot", TravelMode::Foot, &FOOT_SPEEDS)
+    }
+
+    /// Built-in profile of the travel mode
+    pub fn for_mode(mode: TravelMode) -> Self {
+        match mode {
+            TravelMode::Car => Self::car(),
+            TravelMode::Bicycle => Self::bicycle(),
+            TravelMode::Foot => Self::foot(),
+        }
+    }
+
+    /// Built-in profile by name, ``car``, ``bicycle`` (or ``bike``) and ``foot``
+    pub fn by_name(name: &str) -> Option<Self> {
+        name.parse::<TravelMode>().ok().map(Self::for_mode)
+    }
+
+    /// Set the speed in km/h of a highway type, making it routable
+    ///
+    /// Speeds that are not a positive number are ignored.
+    pub fn with_speed(mut self, highway: &str, kmh: f64) -> Self {
+        if kmh.is_finite() && kmh > 0.0 {
+            self.speeds.insert(highway.to_string(), kmh);
+        }
+        self
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Keep only the given highway types
+    ///
+    /// Types unknown to the profile get the default speed of the mode.
+    /// An empty list keeps the profile as is.
+    pub fn restricted_to(mut self, highways: &[&str]) -> Self {
+        if highways.is_empty() {
+            return self;
+        }
+        let default = self.mode.default_speed();
+        self.speeds = highways.iter()
+        .map(|h| (h.to_string(), self.speeds.get(*h).copied().unwrap_or(default)))
+        .collect();
+        self
+    }
+
+    /// Routable highway types
+    pub fn highways(&self) -> Vec<&str> {
+        self.speeds.keys().map(|h| h.as_str()).collect()
+    }
+
+    /// Whether a way with `tags` may be used by this profile
+    pub fn is_accessible(&self, tags: &HashMap<String, String>) -> bool {
+        let routable = tags.get("highway").is_some_and(|h| self.speeds.contains_key(h));
+        if !routable {
+            return false;
+        }
+        if self.mode != TravelMode::Car && tags.get("motorroad").map(|v| v.as_str()) == Some("yes") {
+            return false;
+        }
+
+        // The most specific access tag wins
+        self.mode.access_keys().iter().rev()
+        .find_map(|key| tags.get(*key))
+        .is_none_or(|v| !DENIED.contains(&v.as_str()))
+    }
+
+    /// Speed in km/h along a way with `tags`, None when it is not accessible
+    pub fn speed(&self, tags: &HashMap<String, String>) -> Option<f64> {
+        self.speeds(tags).map(|(forward, _)| forward)
+    }
+
+    /// Speeds in km/h along and against a way with `tags`
+    ///
+    /// The ``maxspeed`` tag (or ``maxspeed:forward`` and ``maxspeed:backward``)
+    /// sets the speed of cars and caps the speed of the other modes. Without
+    /// a readable limit, the implicit zone found in ``maxspeed:type``,
+    /// ``source:maxspeed`` or ``zone:maxspeed`` is used, then the speed of
+    /// the highway type. Returns None when the way is not accessible.
+    pub fn speeds(&self, tags: &HashMap<String, String>) -> Option<(f64, f64)> {
+        if !self.is_accessible(tags) {
+            return None;
+        }
+        let highway = tags.get("highway").and_then(|h| self.speeds.get(h)).copied()?;
+
+        let limit = |key: &str| tags.get(key).and_then(|v| maxspeed::parse(v));
+        let general = limit("maxspeed").or_else(|| {
+            ["maxspeed:type", "source:maxspeed", "zone:maxspeed"].iter().find_map(|k| limit(k))
+        });
+        let forward = limit("maxspeed:forward").or(general);
+        let backward = limit("maxspeed:backward").or(general);
+
+        Some((self.limited(highway, forward), self.limited(highway, backward)))
+    }
+
+    fn limited(&self, highway: f64, limit: Option<MaxSpeed>) -> f64 {
+        match (self.mode, limit) {
+            (_, None) | (_, Some(MaxSpeed::Unlimited)) => highway,
+            (TravelMode::Car, Some(MaxSpeed::Kmh(kmh))) => kmh,
+            (_, Some(MaxSpeed::Kmh(kmh))) => highway.min(kmh),
+        }
+    }
+
+    /// Edge weight of `meters` travelled at `kmh`
+    ///
+    /// The weight is in meters for [`Metric::Distance`] and in milliseconds
+    /// for [`Metric::Time`], never zero since the graph drops such edges.
+    pub fn weight(&self, meters: f64, kmh: f64) -> usize {
+        let weight = match self.metric {
+            Metric::Distance => meters,
+            Metric::Time => meters / (kmh / 3.6) * 1000.0,
+        };
+        (weight.round() as usize).max(1)
+    }
+
+    /// Allowed travel direction on a way with `tags`
+    pub fn oneway(&self, tags: &HashMap<String, String>) -> Oneway {
+        oneway(tags, self.mode)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Allowed travel direction along the order of the way nodes
+pub enum Oneway {
+    /// Both directions
+    No,
+    /// From the first node toward the last node only
+    Forward,
+    /// From the last node toward the first node only
+    Backward,
+    /// Direction changes over time, the way can not be routed
+    Reversible,
+}
+
+impl Oneway {
+    fn parse(value: &str) -> Option<Oneway> {
+        match value {
+            "yes" | "true" | "1" => Some(Oneway::Forward),
+            "-1" | "reverse" => Some(Oneway::Backward),
+            "reversible" | "alternating" => Some(Oneway::Reversible),
+            "no" | "false" | "0" => Some(Oneway::No),
+            _ => None,
+        }
+    }
+}
+
+/// Direction of travel allowed on a way with `tags` for `mode`
+///
+/// Mode specific tags (``oneway:bicycle``, ``oneway:foot``) win over the
+/// plain ``oneway`` tag. Without any tag, roundabouts and motorways are
+/// implied oneway. Pedestrians may walk against the flow unless
+/// ``oneway:foot`` says otherwise.
+pub fn oneway(tags: &HashMap<String, String>, mode: TravelMode) -> Oneway {
+    let tag = |key: &str| tags.get(key).map(|v| v.as_str());
+
+    match mode {
+        TravelMode::Foot => {
+            return tag("oneway:foot").and_then(Oneway::parse).unwrap_or(Oneway::No);
+        },
+        TravelMode::Bicycle => {
+            if let Some(oneway) = tag("oneway:bicycle").and_then(Oneway::parse) {
+                return oneway;
+            }
+            if let Some("opposite") | Some("opposite_lane") | Some("opposite_track") = tag("cycleway") {
+                return Oneway::No;
+            }
+        },
+        TravelMode::Car => {
+            let specific = tag("oneway:motor_vehicle").or_else(|| tag("oneway:motorcar"));
+            if let Some(oneway) = specific.and_then(Oneway::parse) {
+                return oneway;
+            }
+        },
+    }
+
+    if let Some(oneway) = tag("oneway").and_then(Oneway::parse) {
+        return oneway;
+    }
+
+    match (tag("junction"), tag("highway")) {
+        (Some("roundabout"), _) | (Some("circular"), _) => Oneway::Forward,
+        (_, Some("motorway")) => Oneway::Forward,
+        _ => Oneway::No,
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn explicit_oneway_values() {
+        for (value, expected) in &[
+            ("yes", Oneway::Forward),
+            ("true", Oneway::Forward),
+            ("1", Oneway::Forward),
+            ("-1", Oneway::Backward),
+            ("reversible", Oneway::Reversible),
+            ("no", Oneway::No),
+        ] {
+            assert_eq!(oneway(&tags(&[("oneway", value)]), TravelMode::Car), *expected);
+        }
+    }
+
+    #[test]
+    fn implied_oneway() {
+        let roundabout = tags(&[("highway", "primary"), ("junction", "roundabout")]);
+        assert_eq!(oneway(&roundabout, TravelMode::Car), Oneway::Forward);
+        assert_eq!(oneway(&tags(&[("highway", "motorway")]), TravelMode::Car), Oneway::Forward);
+
+        let explicit = tags(&[("highway", "motorway"), ("oneway", "no")]);
+        assert_eq!(oneway(&explicit, TravelMode::Car), Oneway::No);
+    }
+
+    #[test]
+    fn mode_exceptions() {
+        let contraflow = tags(&[("oneway", "yes"), ("oneway:bicycle", "no")]);
+        assert_eq!(oneway(&contraflow, TravelMode::Car), Oneway::Forward);
+        assert_eq!(oneway(&contraflow, TravelMode::Bicycle), Oneway::No);
+
+        let opposite = tags(&[("oneway", "yes"), ("cycleway", "opposite_lane")]);
+        assert_eq!(oneway(&opposite, TravelMode::Bicycle), Oneway::No);
+
+        let street = tags(&[("oneway", "yes")]);
+        assert_eq!(oneway(&street, TravelMode::Bicycle), Oneway::Forward);
+        assert_eq!(oneway(&street, TravelMode::Foot), Oneway::No);
+        assert_eq!(oneway(&tags(&[("oneway:foot", "yes")]), TravelMode::Foot), Oneway::Forward);
+    }
+
+    #[test]
+    fn access_follows_most_specific_tag() {
+        let car = RoutingProfile::car();
+        let foot = RoutingProfile::foot();
+
+        assert!(car.is_accessible(&tags(&[("highway", "residential")])));
+        assert!(!car.is_accessible(&tags(&[("highway", "footway")])));
+        assert!(!foot.is_accessible(&tags(&[("highway", "motorway")])));
+
+        let private = tags(&[("highway", "service"), ("access", "private")]);
+        assert!(!car.is_accessible(&private));
+
+        let bus_gate = tags(&[("highway", "residential"), ("motor_vehicle", "no"), ("foot", "yes")]);
+        assert!(!car.is_accessible(&bus_gate));
+        assert!(foot.is_accessible(&bus_gate));
+
+        let destination = tags(&[("highway", "residential"), ("access", "no"), ("motorcar", "destination")]);
+        assert!(car.is_accessible(&destination));
+    }
+
+    #[test]
+    fn custom_profile() {
+        let profile = RoutingProfile::new("horse", TravelMode::Foot)
+        .with_speed("bridleway", 8.0)
+        .with_speed("track", 0.0)
+        .with_speed("path", f64::NAN)
+        .with_speed("footway", -5.0)
+        .with_metric(Metric::Distance);
+
+        assert_eq!(profile.highways(), vec!["bridleway"]);
+        assert_eq!(profile.speed(&tags(&[("highway", "bridleway")])), Some(8.0));
+        assert_eq!(profile.speed(&tags(&[("highway", "residential")])), None);
+
+        let restricted = RoutingProfile::car().restricted_to(&["residential", "footway"]);
+        assert_eq!(restricted.speed(&tags(&[("highway", "residential")])), Some(30.0));
+        assert_eq!(restricted.speed(&tags(&[("highway", "footway")])), Some(30.0));
+        assert_eq!(restricted.speed(&tags(&[("highway", "primary")])), None);
+
+        assert_eq!(RoutingProfile::by_name("bike"), Some(RoutingProfile::bicycle()));
+        assert_eq!(RoutingProfile::by_name("plane"), None);
+    }
+
+    #[test]
+    fn maxspeed_overrides_highway_speed() {
+        let car = RoutingProfile::car();
+        let bicycle = RoutingProfile::bicycle();
+
+        let fast = tags(&[("highway", "residential"), ("maxspeed", "50")]);
+        assert_eq!(car.speeds(&fast), Some((50.0, 50.0)));
+        assert_eq!(bicycle.speeds(&fast), Some((16.0, 16.0)));
+
+        let walk = tags(&[("highway", "living_street"), ("maxspeed", "walk")]);
+        assert_eq!(car.speed(&walk), Some(crate::maxspeed::WALK));
+        assert_eq!(bicycle.speed(&walk), Some(crate::maxspeed::WALK));
+
+        let autobahn = tags(&[("highway", "motorway"), ("maxspeed", "none")]);
+        assert_eq!(car.speed(&autobahn), Some(110.0));
+
+        let zone = tags(&[("highway", "primary"), ("source:maxspeed", "DE:urban")]);
+        assert_eq!(car.speed(&zone), Some(50.0));
+
+        let unreadable = tags(&[("highway", "primary"), ("maxspeed", "signals")]);
+        assert_eq!(car.speed(&unreadable), Some(70.0));
+
+        let directional = tags(&[("highway", "primary"), ("maxspeed", "30 mph"), ("maxspeed:backward", "20")]);
+        let (forward, backward) = car.speeds(&directional).unwrap();
+        assert!((forward - 48.28).abs() < 0.01);
+        assert_eq!(backward, 20.0);
+    }
+}
@@ -2,16 +2,41 @@
 use std::collections::HashMap;
 
 use pyo3::prelude::*;
-use pyo3::types::{PyTuple, PyBytes};
+use pyo3::types::{PyDict, PyTuple, PyBytes};
 use pyo3::exceptions;
 use pyo3::class::iter::{PyIterProtocol, IterNextOutput};
 
 use osm_xml as osm;
 
+use crate::avoid;
+use crate::centrality;
+use crate::components;
+use crate::instructions;
+use crate::intersection;
+use crate::isochrone;
 use crate::map;
+use crate::matching;
 use crate::queries;
 use crate::queries::QueryBuilder;
 use crate::network;
+use crate::profile;
+use crate::route;
+use crate::snap;
+use crate::traffic;
+
+pyo3::create_exception!(openstreet, NoRouteError, exceptions::PyException);
+pyo3::create_exception!(openstreet, UnknownNodeError, exceptions::PyKeyError);
+
+impl From<route::RouteError> for PyErr {
+    fn from(err: route::RouteError) -> PyErr {
+        match err {
+            route::RouteError::UnknownNode(_) => UnknownNodeError::new_err(err.to_string()),
+            route::RouteError::NoRoute(_, _) => NoRouteError::new_err(err.to_string()),
+            route::RouteError::NotSnapped(_, _) => NoRouteError::new_err(err.to_string()),
+            route::RouteError::NoWaypoints => exceptions::PyValueError::new_err(err.to_string()),
+        }
+    }
+}
 
 #[pyclass]
 #[derive(Clone)]
@@ -45,6 +70,18 @@ impl Node {
     pub fn tags(&self) -> PyResult<HashMap<String, String>> {
         Ok(self.inner.tags.clone())
     }
+
+    #[text_signature = "(self, other)"]
+    /// Great circle distance to ``other`` in meters
+    pub fn distance_to(&self, other: &Node) -> f64 {
+        self.inner.distance_to(&other.inner)
+    }
+
+    #[text_signature = "(self, other)"]
+    /// Initial bearing toward ``other`` in degrees, 0 is north and 90 is east
+    pub fn bearing_to(&self, other: &Node) -> f64 {
+        self.inner.bearing_to(&other.inner)
+    }
 }
 
 impl From<map::Node> for Node {
@@ -92,6 +129,70 @@ impl From<map::Way> for Way {
     }
 }
 
+#[pyclass]
+#[derive(Clone)]
+/// Member of a Relation object
+struct Member {
+    inner: map::Member
+}
+
+#[pymethods]
+impl Member {
+    #[getter(type)]
+    /// Type of the referenced element, one of "node", "way" or "relation"
+    pub fn member_type(&self) -> PyResult<&'static str> {
+        Ok(self.inner.member_type.as_str())
+    }
+
+    #[getter(ref)]
+    /// A numeric Id of the referenced element
+    pub fn reference(&self) -> PyResult<i64> {
+        Ok(self.inner.id)
+    }
+
+    #[getter]
+    /// Role of the element inside the relation
+    pub fn role(&self) -> PyResult<String> {
+        Ok(self.inner.role.clone())
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+/// OpenStreet Relation object
+struct Relation {
+    inner: map::Relation
+}
+
+#[pymethods]
+impl Relation {
+    #[getter]
+    /// A numeric Id
+    pub fn id(&self) -> PyResult<i64> {
+        Ok(self.inner.id)
+    }
+
+    #[getter]
+    /// A Dictionary of tag key and value
+    pub fn tags(&self) -> PyResult<HashMap<String, String>> {
+        Ok(self.inner.tags.clone())
+    }
+
+    #[getter]
+    /// A List of Member objects
+    pub fn members(&self) -> PyResult<Vec<Member>> {
+        Ok(self.inner.members.iter()
+        .map(|m| Member { inner: m.clone() })
+        .collect())
+    }
+}
+
+impl From<map::Relation> for Relation {
+    fn from(relation: map::Relation) -> Self {
+        Relation { inner: relation }
+    }
+}
+
 #[pyclass]
 #[derive(Clone)]
 /// OpenStreet Bounds object
@@ -180,8 +281,59 @@ impl NodeQueryBuilder {
         NodeQueryBuilder { inner: self.inner.clone().by_tag_eq(key, value) }
     }
 
+    #[text_signature = "(self, minlat, minlon, maxlat, maxlon)"]
+    /// Filter Node located inside the bounding box
+    ///
+    /// Parameters
+    /// ----------
+    /// minlat, minlon, maxlat, maxlon : float
+    ///     Corners of the bounding box in degrees.
+    ///
+    /// Returns
+    /// -------
+    /// self : :py:class:`.NodeQueryBuilder`
+    pub fn where_within_bbox(&self, minlat: f64, minlon: f64, maxlat: f64, maxlon: f64) -> NodeQueryBuilder {
+        NodeQueryBuilder { inner: self.inner.clone().within_bbox(minlat, minlon, maxlat, maxlon) }
+    }
+
+    #[text_signature = "(self, lat, lon, meters)"]
+    /// Filter Node located at most ``meters`` away from the point
+    ///
+    /// Parameters
+    /// ----------
+    /// lat, lon : float
+    ///     Center point in degrees.
+    /// meters : float
+    ///     Search radius in meters.
+    ///
+    /// Returns
+    /// -------
+    /// self : :py:class:`.NodeQueryBuilder`
+    pub fn where_within_radius(&self, lat: f64, lon: f64, meters: f64) -> NodeQueryBuilder {
+        NodeQueryBuilder { inner: self.inner.clone().within_radius(lat, lon, meters) }
+    }
+
+    #[text_signature = "(self, lat, lon, k)"]
+    /// Keep the ``k`` Node nearest to the point, ordered by distance
+    ///
+    /// Only Node passing the filters set before this call are counted.
+    ///
+    /// Parameters
+    /// ----------
+    /// lat, lon : float
+    ///     Reference point in degrees.
+    /// k : int
+    ///     Number of Node to keep.
+    ///
+    /// Returns
+    /// -------
+    /// self : :py:class:`.NodeQueryBuilder`
+    pub fn nearest(&self, lat: f64, lon: f64, k: usize) -> NodeQueryBuilder {
+        NodeQueryBuilder { inner: self.inner.clone().nearest(lat, lon, k) }
+    }
+
     #[text_signature = "(self)"]
-    /// Returns the filtered Node list
+    /// Returns the Node passing every filter, all of them without filter
     pub fn get(&self) -> Vec<Node> {
         self.inner.get().iter_mut()
         .map(|n| Node { inner: n.clone() })
@@ -264,8 +416,59 @@ impl WayQueryBuilder {
         WayQueryBuilder { inner: self.inner.clone().contain_nodes(nodes) }
     }
 
+    #[text_signature = "(self, minlat, minlon, maxlat, maxlon)"]
+    /// Filter Way crossing or lying inside the bounding box
+    ///
+    /// Parameters
+    /// ----------
+    /// minlat, minlon, maxlat, maxlon : float
+    ///     Corners of the bounding box in degrees.
+    ///
+    /// Returns
+    /// -------
+    /// self : :py:class:`.WayQueryBuilder`
+    pub fn where_within_bbox(&self, minlat: f64, minlon: f64, maxlat: f64, maxlon: f64) -> WayQueryBuilder {
+        WayQueryBuilder { inner: self.inner.clone().within_bbox(minlat, minlon, maxlat, maxlon) }
+    }
+
+    #[text_signature = "(self, lat, lon, meters)"]
+    /// Filter Way passing at most ``meters`` away from the point
+    ///
+    /// Parameters
+    /// ----------
+    /// lat, lon : float
+    ///     Center point in degrees.
+    /// meters : float
+    ///     Search radius in meters.
+    ///
+    /// Returns
+    /// -------
+    /// self : :py:class:`.WayQueryBuilder`
+    pub fn where_within_radius(&self, lat: f64, lon: f64, meters: f64) -> WayQueryBuilder {
+        WayQueryBuilder { inner: self.inner.clone().within_radius(lat, lon, meters) }
+    }
+
+    #[text_signature = "(self, lat, lon, k)"]
+    /// Keep the ``k`` Way nearest to the point, ordered by distance
+    ///
+    /// Only Way passing the filters set before this call are counted.
+    ///
+    /// Parameters
+    /// ----------
+    /// lat, lon : float
+    ///     Reference point in degrees.
+    /// k : int
+    ///     Number of Way to keep.
+    ///
+    /// Returns
+    /// -------
+    /// self : :py:class:`.WayQueryBuilder`
+    pub fn nearest(&self, lat: f64, lon: f64, k: usize) -> WayQueryBuilder {
+        WayQueryBuilder { inner: self.inner.clone().nearest(lat, lon, k) }
+    }
+
     #[text_signature = "(self)"]
-    /// Returns the filtered Way list
+    /// Returns the Way passing every filter, all of them without filter
     pub fn get(&self) -> Vec<Way> {
         self.inner.get().iter_mut()
         .map(|w| Way { inner: w.clone() })
@@ -308,12 +511,101 @@ impl PyIterProtocol for WayQueryBuilder {
 }
 
 
+#[pyclass]
+/// Object that save filtering operations
+struct RelationQueryBuilder {
+    inner: queries::Builder<map::Relation>,
+}
+
+
+#[pymethods]
+impl RelationQueryBuilder {
+    #[text_signature = "(self, id)"]
+    /// Returns Relation with given ``id``
+    pub fn by_id(&self, id: osm::Id) -> Relation {
+        Relation { inner: self.inner.by_id(id) }
+    }
+
+    #[text_signature = "(self, key, values)"]
+    /// Filter Relation with tag of key ``key`` that contains one of ``values``
+    ///
+    /// See :py:class:`Map` documentation for usage example.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     Key name of the tags.
+    /// values : List[str]
+    ///     Possible tag values to include.
+    pub fn where_tag_in(&self, key: &str, values: Vec<&str>) -> RelationQueryBuilder {
+        RelationQueryBuilder { inner: self.inner.clone().by_tag_in(key, values) }
+    }
+
+    #[text_signature = "(self, key, value)"]
+    /// Filter Relation with tag of key ``key`` equal ``value``
+    ///
+    /// See :py:class:`Map` documentation for usage example.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     Key name of the tags.
+    /// value : str
+    ///     A tag value to filter.
+    pub fn where_tag_eq(&self, key: &str, value: &str) -> RelationQueryBuilder {
+        RelationQueryBuilder { inner: self.inner.clone().by_tag_eq(key, value) }
+    }
+
+    #[text_signature = "(self)"]
+    /// Returns the Relation passing every filter, all of them without filter
+    pub fn get(&self) -> Vec<Relation> {
+        self.inner.get().iter_mut()
+        .map(|r| Relation { inner: r.clone() })
+        .collect::<Vec<Relation>>()
+    }
+}
+
+impl From<queries::Builder<map::Relation>> for RelationQueryBuilder {
+    fn from(builder: queries::Builder<map::Relation>) -> Self {
+        RelationQueryBuilder { inner: builder }
+    }
+}
+
+
+#[pyclass]
+/// Object that save filtering operations
+struct RelationQueryIter {
+    inner: queries::BuilderIter<map::Relation>,
+}
+
+#[pyproto]
+impl PyIterProtocol for RelationQueryIter {
+    fn __next__(mut slf: PyRefMut<Self>) -> IterNextOutput<Relation, &str> {
+        if let Some(result) = slf.inner.next() {
+            IterNextOutput::Yield(result.1.into())
+        } else {
+            IterNextOutput::Return("Exhausted")
+        }
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for RelationQueryBuilder {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<Py<RelationQueryIter>> {
+        let iter = RelationQueryIter {
+            inner: slf.inner.iter()
+        };
+        Py::new(slf.py(), iter)
+    }
+}
+
+
 #[pyclass(subclass)]
 #[derive(Clone)]
 /// Map provide parsing and storage for OSM format
 ///
-/// Map contains three main information: nodes, ways, and bounds.
-/// For ways and nodes, both must be accessed using query style
+/// Map contains four main information: nodes, ways, relations and bounds.
+/// Nodes, ways and relations must be accessed using query style
 /// or fluent interface.
 ///
 /// .. code-block:: python
@@ -322,6 +614,11 @@ impl PyIterProtocol for WayQueryBuilder {
 ///    map = Map("/path/to/map.osm")
 ///    streets = map.ways().where_tag_in("highstreet", [ "primary", "secondary" ]).get()
 ///
+/// The map file may be either OSM XML or OSM PBF (``.osm.pbf``), the format
+/// is picked from the file extension or detected from its content. ``IOError``
+/// is raised when the file can not be read, ``ValueError`` when its
+/// content is invalid.
+///
 /// Tag is an element in OSM format looked like these:
 /// ``<tag key="akeyhere" value="somevalue" />``. So using the ``by_tag_in`` filter
 /// would means looping over all the ways in the OSM with the matching tag "highstreet"
@@ -333,9 +630,11 @@ struct Map {
 #[pymethods]
 impl Map {
     #[new]
-    pub fn new(path: String) -> Self {
-        Self {
-            inner: map::Map::new(path)
+    pub fn new(path: String) -> PyResult<Self> {
+        match map::Map::new(path) {
+            Ok(inner) => Ok(Self { inner }),
+            Err(map::Error::Io(e)) => Err(exceptions::PyIOError::new_err(e.to_string())),
+            Err(e) => Err(exceptions::PyValueError::new_err(e.to_string())),
         }
     }
 
@@ -357,80 +656,1270 @@ impl Map {
         self.inner.nodes().into()
     }
 
+    /// Return query builder to filter relations collection
+    ///
+    /// Refer to RelationQueryBuilder methods for available filters.
+    /// Call :py:func:`RelationQueryBuilder.get` when done to retrieve the result.
+    /// See :py:class:`Map` documentation for example.
+    pub fn relations(&self) -> RelationQueryBuilder {
+        self.inner.relations().into()
+    }
+
     /// Return Bounds object of the map
     pub fn bounds(&self) -> Option<Bounds> {
-        if let Some(bounds) = self.inner.bounds() {
-            Some(Bounds {
-                minlat: bounds.minlat,
-                minlon: bounds.minlon,
-                maxlat: bounds.maxlat,
-                maxlon: bounds.maxlon,
-            })
-        } else {
-            None
-        }
+        self.inner.bounds().map(|bounds| Bounds {
+            minlat: bounds.minlat,
+            minlon: bounds.minlon,
+            maxlat: bounds.maxlat,
+            maxlon: bounds.maxlon,
+        })
     }
 }
 
 
-#[pyclass(subclass, module="openstreet")]
-/// StreetNetwork create graph of street nodes and enable graph operation
+#[pyclass(module="openstreet")]
+#[derive(Clone)]
+/// RoutingProfile decides which streets are routable and how fast
 ///
-/// Operation currently supported is shortest path.
-struct StreetNetwork {
-    inner: Option<network::StreetNetwork>,
+/// Parameters
+/// ----------
+/// name : str
+///     Name of the profile
+/// mode : str
+///     Travel mode, one of ``car``, ``bicycle`` or ``foot``. It decides the
+///     access and oneway tags honoured by the profile.
+/// speeds : dict, optional
+///     Speed in km/h of each routable ``highway`` type, types without a
+///     positive speed are left out
+/// metric : str, optional
+///     Either ``time`` (default) or ``distance``, the quantity minimized by routing
+///
+/// .. code-block:: python
+///    :linenos:
+///
+///    horse = RoutingProfile("horse", "foot", { "bridleway": 8.0, "track": 8.0 })
+///    network = StreetNetwork(map, horse)
+///    network = StreetNetwork(map, RoutingProfile.bicycle())
+struct RoutingProfile {
+    inner: profile::RoutingProfile,
 }
 
 #[pymethods]
-impl StreetNetwork {
+impl RoutingProfile {
     #[new]
-    #[args(args = "*")]
-    pub fn new(args: &PyTuple) -> Self {
-        match args.len() {
-            0 => StreetNetwork {
-                inner: None,
-            },
-            2 => {
-                let map = args.get_item(0).extract::<Map>().expect("First argument must be a Map");
-                let street_type = args.get_item(1).extract::<Vec<&str>>().expect("Second argument must be a list of highway type");
+    #[args(speeds = "None", metric = "\"time\"")]
+    pub fn new(name: &str, mode: &str, speeds: Option<HashMap<String, f64>>, metric: &str) -> PyResult<Self> {
+        let mode = mode.parse::<profile::TravelMode>().map_err(exceptions::PyValueError::new_err)?;
+        let metric = metric.parse::<profile::Metric>().map_err(exceptions::PyValueError::new_err)?;
 
-                Self {
-                    inner: Some(network::StreetNetwork::new(&map.inner, street_type))
-                }
-            }
-            _ => unreachable!(),
-        }
+        let inner = speeds.unwrap_or_default().iter()
+        .fold(profile::RoutingProfile::new(name, mode), |p, (highway, kmh)| p.with_speed(highway, *kmh))
+        .with_metric(metric);
+        Ok(Self { inner })
     }
 
-    /// Returns Node Id list from @param{a} to @param{b}
-    ///
-    /// The returned array may be empty if there is no possible
-    /// route from @param{a} to @param{b}.
-    pub fn shortest_path(&mut self, a: i64, b: i64) -> PyResult<Vec<i64>> {
-        if let Some(inner) = self.inner.as_mut() {
-            return Ok(inner.shortest_path(a, b));
-        }
+    #[staticmethod]
+    /// Built-in car profile, optimized for travel time
+    pub fn car() -> Self {
+        Self { inner: profile::RoutingProfile::car() }
+    }
 
-        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    #[staticmethod]
+    /// Built-in bicycle profile, optimized for travel time
+    pub fn bicycle() -> Self {
+        Self { inner: profile::RoutingProfile::bicycle() }
     }
 
-    pub fn __getstate__(&self, py: Python) -> PyResult<PyObject> {
-        if let Some(inner) = self.inner.as_ref() {
-            return Ok(PyBytes::new(py, &inner.serialize()).to_object(py));
-        }
+    #[staticmethod]
+    /// Built-in foot profile, optimized for travel time
+    pub fn foot() -> Self {
+        Self { inner: profile::RoutingProfile::foot() }
+    }
 
-        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    #[getter]
+    pub fn name(&self) -> String {
+        self.inner.name.clone()
     }
 
-    fn __setstate__(&mut self, py: Python, state: PyObject) -> PyResult<()> {
-        match state.extract::<&PyBytes>(py) {
-            Ok(s) => {
-                self.inner = Some(network::StreetNetwork::deserialize(s.as_bytes().to_vec()));
-                Ok(())
-            }
-            Err(e) => Err(e),
+    #[getter]
+    /// Travel mode, ``car``, ``bicycle`` or ``foot``
+    pub fn mode(&self) -> &'static str {
+        self.inner.mode.as_str()
+    }
+
+    #[getter]
+    /// Either ``time`` or ``distance``
+    pub fn metric(&self) -> &'static str {
+        match self.inner.metric {
+            profile::Metric::Time => "time",
+            profile::Metric::Distance => "distance",
         }
     }
+
+    #[getter]
+    /// Speed in km/h of each routable ``highway`` type
+    pub fn speeds(&self) -> HashMap<String, f64> {
+        self.inner.speeds.clone()
+    }
+}
+
+
+#[pyclass(module="openstreet")]
+#[derive(Clone)]
+/// Route between two nodes of a :py:class:`StreetNetwork`
+///
+/// .. code-block:: python
+///    :linenos:
+///
+///    route = network.route(a, b)
+///    print(route.distance, route.duration, route.names)
+struct Route {
+    inner: route::Route,
+}
+
+#[pymethods]
+impl Route {
+    #[getter]
+    /// OSM node ids from the origin to the destination
+    pub fn nodes(&self) -> Vec<i64> {
+        self.inner.nodes.clone()
+    }
+
+    #[getter]
+    /// List of (lat, lon) tuples of each node
+    pub fn geometry(&self) -> Vec<(f64, f64)> {
+        self.inner.geometry.clone()
+    }
+
+    #[getter]
+    /// Ids of the traversed ways, without consecutive repeats
+    pub fn ways(&self) -> Vec<i64> {
+        self.inner.ways.clone()
+    }
+
+    #[getter]
+    /// Names of the traversed streets, without consecutive repeats
+    pub fn names(&self) -> Vec<String> {
+        self.inner.names.clone()
+    }
+
+    #[getter]
+    /// Length in meters
+    pub fn distance(&self) -> f64 {
+        self.inner.distance
+    }
+
+    #[getter]
+    /// Travel time in seconds
+    pub fn duration(&self) -> f64 {
+        self.inner.duration
+    }
+
+    #[getter]
+    /// Sum of the edge weights, meters or milliseconds depending on the profile metric
+    pub fn weight(&self) -> usize {
+        self.inner.weight
+    }
+
+    #[getter]
+    /// One :py:class:`Leg` between each pair of consecutive waypoints
+    pub fn legs(&self) -> Vec<Leg> {
+        self.inner.legs.iter().cloned().map(|inner| Leg { inner }).collect()
+    }
+}
+
+
+#[pyclass(module="openstreet")]
+#[derive(Clone)]
+/// Part of a :py:class:`Route` between two consecutive waypoints,
+/// see :py:func:`StreetNetwork.route_via`
+struct Leg {
+    inner: route::Leg,
+}
+
+#[pymethods]
+impl Leg {
+    #[getter]
+    /// OSM id of the waypoint the leg starts at
+    pub fn origin(&self) -> i64 {
+        self.inner.from
+    }
+
+    #[getter]
+    /// OSM id of the waypoint the leg ends at
+    pub fn destination(&self) -> i64 {
+        self.inner.to
+    }
+
+    #[getter]
+    /// Index in the route nodes of the first node of the leg
+    pub fn start(&self) -> usize {
+        self.inner.start
+    }
+
+    #[getter]
+    /// Index in the route nodes of the last node of the leg
+    pub fn end(&self) -> usize {
+        self.inner.end
+    }
+
+    #[getter]
+    /// Length in meters
+    pub fn distance(&self) -> f64 {
+        self.inner.distance
+    }
+
+    #[getter]
+    /// Travel time in seconds
+    pub fn duration(&self) -> f64 {
+        self.inner.duration
+    }
+
+    #[getter]
+    /// Sum of the edge weights
+    pub fn weight(&self) -> usize {
+        self.inner.weight
+    }
+}
+
+
+#[pyclass(module="openstreet")]
+#[derive(Clone)]
+/// Navigation step of a :py:class:`Route`, see :py:func:`StreetNetwork.instructions`
+struct Step {
+    inner: instructions::Step,
+}
+
+#[pymethods]
+impl Step {
+    #[getter]
+    /// One of ``depart``, ``continue``, ``slight left``, ``left``, ``sharp left``,
+    /// ``slight right``, ``right``, ``sharp right``, ``uturn``, ``roundabout``
+    /// or ``arrive``
+    pub fn maneuver(&self) -> &'static str {
+        self.inner.maneuver.as_str()
+    }
+
+    #[getter]
+    /// Exit to take for a ``roundabout`` maneuver, None otherwise
+    pub fn exit(&self) -> Option<usize> {
+        match self.inner.maneuver {
+            instructions::Maneuver::Roundabout { exit } => Some(exit),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    /// OSM id of the node where the maneuver takes place
+    pub fn node(&self) -> i64 {
+        self.inner.node
+    }
+
+    #[getter]
+    /// (lat, lon) of the node
+    pub fn location(&self) -> (f64, f64) {
+        self.inner.location
+    }
+
+    #[getter]
+    /// Name of the street followed after the maneuver, empty when unnamed
+    pub fn name(&self) -> String {
+        self.inner.name.clone()
+    }
+
+    #[getter]
+    /// Bearing in degrees when leaving the node, 0 is north and 90 is east
+    pub fn bearing(&self) -> f64 {
+        self.inner.bearing
+    }
+
+    #[getter]
+    /// Length in meters until the next step
+    pub fn distance(&self) -> f64 {
+        self.inner.distance
+    }
+
+    #[getter]
+    /// Travel time in seconds until the next step
+    pub fn duration(&self) -> f64 {
+        self.inner.duration
+    }
+
+    #[getter]
+    /// Human readable instruction, e.g. "Turn left onto Jalan Merdeka"
+    pub fn text(&self) -> String {
+        self.inner.to_string()
+    }
+}
+
+
+#[pyclass(module="openstreet")]
+#[derive(Clone)]
+/// Intersection or dead end of a :py:class:`StreetNetwork`,
+/// see :py:func:`StreetNetwork.intersections`
+struct Intersection {
+    inner: intersection::Intersection,
+}
+
+#[pymethods]
+impl Intersection {
+    #[getter]
+    /// OSM node id
+    pub fn id(&self) -> i64 {
+        self.inner.id
+    }
+
+    #[getter]
+    pub fn lat(&self) -> f64 {
+        self.inner.lat
+    }
+
+    #[getter]
+    pub fn lon(&self) -> f64 {
+        self.inner.lon
+    }
+
+    #[getter]
+    /// One of ``dead_end``, ``tee``, ``cross``, ``multi_way`` and ``roundabout``
+    pub fn kind(&self) -> &'static str {
+        self.inner.kind.as_str()
+    }
+
+    #[getter]
+    /// Number of streets meeting at the node
+    pub fn degree(&self) -> usize {
+        self.inner.streets.len()
+    }
+
+    #[getter]
+    /// List of (way id, name or None, bearing) tuples, one for each street
+    /// meeting at the node, by increasing bearing
+    pub fn streets(&self) -> Vec<(i64, Option<String>, f64)> {
+        self.inner.streets.iter().map(|s| (s.way, s.name.clone(), s.bearing)).collect()
+    }
+
+    #[getter]
+    /// Distinct names of the streets
+    pub fn names(&self) -> Vec<String> {
+        self.inner.names()
+    }
+}
+
+
+#[pyclass(module="openstreet")]
+#[derive(Clone)]
+/// Point of a street closest to a coordinate, see :py:func:`StreetNetwork.snap`
+struct Snap {
+    inner: snap::Snap,
+}
+
+#[pymethods]
+impl Snap {
+    #[getter]
+    /// (lat, lon) of the point on the street
+    pub fn location(&self) -> (f64, f64) {
+        self.inner.location
+    }
+
+    #[getter]
+    /// Distance in meters from the coordinate
+    pub fn distance(&self) -> f64 {
+        self.inner.distance
+    }
+
+    #[getter]
+    /// Id of the way of the street
+    pub fn way(&self) -> i64 {
+        self.inner.way
+    }
+
+    #[getter]
+    /// (from, to) OSM ids of the nodes at both ends of the segment
+    pub fn nodes(&self) -> (i64, i64) {
+        (self.inner.from, self.inner.to)
+    }
+
+    #[getter]
+    /// Position along the segment, from 0 at its first node to 1 at the
+    /// second one
+    pub fn ratio(&self) -> f64 {
+        self.inner.ratio
+    }
+}
+
+
+#[pyclass(module="openstreet")]
+#[derive(Clone)]
+/// GPS trace matched to the streets, see :py:func:`StreetNetwork.match_trace`
+struct Matching {
+    inner: matching::Matching,
+}
+
+#[pymethods]
+impl Matching {
+    #[getter]
+    /// One :py:class:`Snap` for each point of the trace, None for the
+    /// points too far from the streets
+    pub fn snaps(&self) -> Vec<Option<Snap>> {
+        self.inner.points.iter().map(|p| p.snap.clone().map(|inner| Snap { inner })).collect()
+    }
+
+    #[getter]
+    /// Probability of the matched street of each point, 0 for unmatched
+    /// points
+    pub fn confidences(&self) -> Vec<f64> {
+        self.inner.points.iter().map(|p| p.confidence).collect()
+    }
+
+    #[getter]
+    /// Ids of the ways driven along, without consecutive repeats
+    pub fn ways(&self) -> Vec<i64> {
+        self.inner.ways.clone()
+    }
+
+    #[getter]
+    /// OSM ids of the nodes driven through
+    pub fn nodes(&self) -> Vec<i64> {
+        self.inner.nodes.clone()
+    }
+
+    #[getter]
+    /// Length in meters of the matched route
+    pub fn distance(&self) -> f64 {
+        self.inner.distance
+    }
+}
+
+
+#[pyclass(subclass, module="openstreet")]
+/// StreetNetwork create graph of street nodes and enable graph operation
+///
+/// Operation currently supported is shortest path.
+///
+/// The network is built from a map and either a list of ``highway`` types
+/// (car access, weighted by distance), the name of a built-in profile
+/// (``car``, ``bicycle`` or ``foot``) or a :py:class:`RoutingProfile`.
+/// With ``largest_component`` set to ``strong`` or ``weak``, only the
+/// largest connected component of the streets is kept.
+///
+/// .. code-block:: python
+///    :linenos:
+///
+///    network = StreetNetwork(map, [ "primary", "secondary", "residential" ])
+///    network = StreetNetwork(map, "bicycle")
+///    network = StreetNetwork(map, "car", largest_component="strong")
+struct StreetNetwork {
+    inner: Option<network::StreetNetwork>,
+}
+
+#[pymethods]
+impl StreetNetwork {
+    #[new]
+    #[args(args = "*", kwargs = "**")]
+    pub fn new(args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<Self> {
+        let largest_component = match kwargs.and_then(|k| k.get_item("largest_component")) {
+            Some(value) => value.extract::<Option<&str>>()?,
+            None => None,
+        };
+        match args.len() {
+            0 => Ok(StreetNetwork {
+                inner: None,
+            }),
+            2 => {
+                let map = args.get_item(0).extract::<Map>().expect("First argument must be a Map");
+                let second = args.get_item(1);
+
+                let inner = if let Ok(profile) = second.extract::<RoutingProfile>() {
+                    network::StreetNetwork::from_profile(&map.inner, profile.inner)
+                } else if let Ok(name) = second.extract::<&str>() {
+                    let profile = profile::RoutingProfile::by_name(name)
+                    .ok_or_else(|| exceptions::PyValueError::new_err(format!("Unknown profile {}", name)))?;
+                    network::StreetNetwork::from_profile(&map.inner, profile)
+                } else {
+                    let street_type = second.extract::<Vec<&str>>()
+                    .expect("Second argument must be a list of highway type or a profile");
+                    network::StreetNetwork::new(&map.inner, street_type)
+                };
+                let mut inner = inner;
+                if let Some(connectivity) = largest_component {
+                    inner.keep_largest_component(connectivity_arg(connectivity)?);
+                }
+
+                Ok(Self {
+                    inner: Some(inner)
+                })
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[getter]
+    /// The :py:class:`RoutingProfile` the network is built with
+    pub fn profile(&self) -> PyResult<RoutingProfile> {
+        if let Some(inner) = self.inner.as_ref() {
+            return Ok(RoutingProfile { inner: inner.profile.clone() });
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    /// Returns Node Id list from @param{a} to @param{b}
+    ///
+    /// The returned array may be empty if there is no possible
+    /// route from @param{a} to @param{b}.
+    ///
+    /// The graph is prepared on the first call and reused afterwards.
+    /// The GIL is released while searching, so several threads may
+    /// query the same network at once.
+    pub fn shortest_path(&self, py: Python, a: i64, b: i64) -> PyResult<Vec<i64>> {
+        if let Some(inner) = self.inner.as_ref() {
+            return Ok(py.allow_threads(|| inner.shortest_path(a, b)));
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, a, b, avoid_ways=None, avoid_nodes=None, avoid_tags=None, avoid_polygons=None)"]
+    #[args(avoid_ways = "None", avoid_nodes = "None", avoid_tags = "None", avoid_polygons = "None")]
+    /// Best route from node ``a`` to node ``b`` for the network profile
+    ///
+    /// Exclusions are applied at query time, the network is left as it is.
+    ///
+    /// Parameters
+    /// ----------
+    /// a : int
+    ///     OSM id of the origin node
+    /// b : int
+    ///     OSM id of the destination node
+    /// avoid_ways : list of int, optional
+    ///     Ids of closed ways
+    /// avoid_nodes : list of int, optional
+    ///     Ids of closed nodes
+    /// avoid_tags : list of tuple, optional
+    ///     (key, value) tags closing the ways that have them, such as
+    ///     ``("toll", "yes")``
+    /// avoid_polygons : list of list of tuple, optional
+    ///     Closed rings of (lat, lon) pairs the route does not enter
+    ///
+    /// Returns
+    /// -------
+    /// Route
+    ///     The route with its distance in meters and duration in seconds
+    ///
+    /// Raises
+    /// ------
+    /// UnknownNodeError
+    ///     If either node is not part of the network
+    /// NoRouteError
+    ///     If ``b`` can not be reached from ``a``
+    #[allow(clippy::too_many_arguments)]
+    pub fn route(
+        &self,
+        py: Python,
+        a: i64,
+        b: i64,
+        avoid_ways: Option<Vec<i64>>,
+        avoid_nodes: Option<Vec<i64>>,
+        avoid_tags: Option<Vec<(String, String)>>,
+        avoid_polygons: Option<Vec<Vec<(f64, f64)>>>,
+    ) -> PyResult<Route> {
+        if let Some(inner) = self.inner.as_ref() {
+            let mut avoid = avoid::Avoid::new();
+            avoid.ways.extend(avoid_ways.unwrap_or_default());
+            avoid.nodes.extend(avoid_nodes.unwrap_or_default());
+            avoid.tags = avoid_tags.unwrap_or_default();
+            for ring in avoid_polygons.unwrap_or_default() {
+                avoid = avoid.polygon(ring);
+            }
+
+            let route = py.allow_threads(|| inner.route_avoiding(a, b, &avoid))?;
+            return Ok(Route { inner: route });
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, lat, lon, max_distance=50.0, heading=None)"]
+    #[args(max_distance = "50.0", heading = "None")]
+    /// Closest point of a street to a coordinate
+    ///
+    /// Parameters
+    /// ----------
+    /// lat, lon : float
+    ///     Coordinate to snap
+    /// max_distance : float
+    ///     Largest distance in meters to the street
+    /// heading : float, optional
+    ///     Direction of travel in degrees clockwise from north, streets
+    ///     that can not be followed within 45 degrees of it are skipped
+    ///
+    /// Returns
+    /// -------
+    /// Snap or None
+    ///     None when no street is close enough
+    pub fn snap(&self, py: Python, lat: f64, lon: f64, max_distance: f64, heading: Option<f64>) -> PyResult<Option<Snap>> {
+        if let Some(inner) = self.inner.as_ref() {
+            let snapped = py.allow_threads(|| inner.snap(lat, lon, max_distance, heading));
+            return Ok(snapped.map(|inner| Snap { inner }));
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, origin, destination, max_distance=50.0)"]
+    #[args(max_distance = "50.0")]
+    /// Best route between two coordinates
+    ///
+    /// Both coordinates are snapped to the closest street, the route starts
+    /// and ends partway along these streets at virtual nodes, with the two
+    /// smallest 64 bit ids ``-2**63`` and ``-2**63 + 1`` so that they can
+    /// not be mistaken for OSM nodes.
+    ///
+    /// Parameters
+    /// ----------
+    /// origin, destination : tuple of float
+    ///     (lat, lon) coordinates
+    /// max_distance : float
+    ///     Largest distance in meters between a coordinate and its street
+    ///
+    /// Returns
+    /// -------
+    /// Route
+    ///     Raises NoRouteError when a coordinate is too far from the
+    ///     streets or there is no route
+    pub fn route_coords(&self, py: Python, origin: (f64, f64), destination: (f64, f64), max_distance: f64) -> PyResult<Route> {
+        if let Some(inner) = self.inner.as_ref() {
+            let route = py.allow_threads(|| inner.route_coords(origin, destination, max_distance))?;
+            return Ok(Route { inner: route });
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, trace, sigma=5.0, beta=5.0, radius=50.0, max_speed=200.0)"]
+    #[args(sigma = "5.0", beta = "5.0", radius = "50.0", max_speed = "200.0")]
+    /// Match a GPS trace to the streets with a hidden Markov model
+    ///
+    /// Parameters
+    /// ----------
+    /// trace : list of tuple
+    ///     (lat, lon, timestamp) of each point, timestamps in seconds
+    /// sigma : float
+    ///     Standard deviation in meters of the GPS positions
+    /// beta : float
+    ///     Scale in meters of the difference between route and straight
+    ///     distances of consecutive points
+    /// radius : float
+    ///     Largest distance in meters between a point and its street
+    /// max_speed : float
+    ///     Largest speed in km/h between consecutive points
+    ///
+    /// Returns
+    /// -------
+    /// Matching
+    ///
+    /// Raises
+    /// ------
+    /// ValueError
+    ///     If an option is not a positive number
+    pub fn match_trace(&self, py: Python, trace: Vec<(f64, f64, f64)>, sigma: f64, beta: f64, radius: f64, max_speed: f64) -> PyResult<Matching> {
+        if let Some(inner) = self.inner.as_ref() {
+            let trace = trace.into_iter()
+            .map(|(lat, lon, time)| matching::GpsPoint { lat, lon, time })
+            .collect::<Vec<matching::GpsPoint>>();
+            let options = matching::MatchOptions::new()
+            .with_sigma(sigma)
+            .with_beta(beta)
+            .with_radius(radius)
+            .with_max_speed(max_speed);
+            let matched = py.allow_threads(|| inner.match_trace(&trace, &options))
+            .map_err(exceptions::PyValueError::new_err)?;
+            return Ok(Matching { inner: matched });
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, connectivity=\"strong\")"]
+    #[args(connectivity = "\"strong\"")]
+    /// Connected component of every node
+    ///
+    /// Parameters
+    /// ----------
+    /// connectivity : str
+    ///     ``strong`` to follow the direction of travel, ``weak`` to ignore it
+    ///
+    /// Returns
+    /// -------
+    /// dict
+    ///     Component of each OSM node id, 0 for the largest component
+    pub fn component_ids(&self, py: Python, connectivity: &str) -> PyResult<HashMap<i64, usize>> {
+        let connectivity = connectivity_arg(connectivity)?;
+        if let Some(inner) = self.inner.as_ref() {
+            return Ok(py.allow_threads(|| inner.component_ids(connectivity)));
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, connectivity=\"strong\")"]
+    #[args(connectivity = "\"strong\"")]
+    /// Statistics of the connected components
+    ///
+    /// Parameters
+    /// ----------
+    /// connectivity : str
+    ///     ``strong`` to follow the direction of travel, ``weak`` to ignore it
+    ///
+    /// Returns
+    /// -------
+    /// dict
+    ///     ``count`` of components, their ``sizes`` in nodes largest first,
+    ///     number of ``nodes``, ``largest_share`` of the nodes in the
+    ///     largest component and number of ``isolated`` nodes
+    pub fn component_stats<'p>(&self, py: Python<'p>, connectivity: &str) -> PyResult<&'p PyDict> {
+        let connectivity = connectivity_arg(connectivity)?;
+        if let Some(inner) = self.inner.as_ref() {
+            let stats = py.allow_threads(|| inner.component_stats(connectivity));
+            let dict = PyDict::new(py);
+            dict.set_item("connectivity", stats.connectivity.as_str())?;
+            dict.set_item("count", stats.count())?;
+            dict.set_item("sizes", stats.sizes.clone())?;
+            dict.set_item("nodes", stats.nodes)?;
+            dict.set_item("largest_share", stats.largest_share())?;
+            dict.set_item("isolated", stats.isolated())?;
+            return Ok(dict);
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, connectivity=\"strong\")"]
+    #[args(connectivity = "\"strong\"")]
+    /// Drop every node outside of the largest connected component
+    ///
+    /// Returns
+    /// -------
+    /// int
+    ///     Number of removed nodes
+    pub fn keep_largest_component(&mut self, py: Python, connectivity: &str) -> PyResult<usize> {
+        let connectivity = connectivity_arg(connectivity)?;
+        if let Some(inner) = self.inner.as_mut() {
+            return Ok(py.allow_threads(|| inner.keep_largest_component(connectivity)));
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self)"]
+    /// Collapse the nodes where the network only bends or passes from a
+    /// way to the next
+    ///
+    /// Edges then run between intersections and dead ends. Routes still
+    /// list every node and coordinate they pass through, but removed nodes
+    /// can no longer be used as origin or destination.
+    pub fn simplify(&mut self, py: Python) -> PyResult<()> {
+        if let Some(inner) = self.inner.as_mut() {
+            py.allow_threads(|| inner.simplify());
+            return Ok(());
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, bounds=None)"]
+    #[args(bounds = "None")]
+    /// Summary statistics of the network
+    ///
+    /// Parameters
+    /// ----------
+    /// bounds : Bounds, optional
+    ///     Area the densities are computed over, such as :py:func:`Map.bounds`,
+    ///     the convex hull of the nodes when missing
+    ///
+    /// Returns
+    /// -------
+    /// dict
+    ///     Number of ``nodes`` and directed ``edges``, ``edge_length_total``
+    ///     and ``edge_length_avg`` in meters, number of ``streets`` whatever
+    ///     the direction of travel with ``street_length_total``,
+    ///     ``street_length_avg`` and ``street_length_by_highway`` in meters,
+    ///     ``streets_per_node_avg`` and ``streets_per_node_counts``, number of
+    ///     ``intersections``, ``area`` in km², ``intersection_density`` per
+    ///     km², ``circuity_avg`` and ``self_loop_proportion``
+    pub fn stats<'p>(&self, py: Python<'p>, bounds: Option<Bounds>) -> PyResult<&'p PyDict> {
+        if let Some(inner) = self.inner.as_ref() {
+            let bounds = bounds.map(|b| map::Bounds { minlat: b.minlat, minlon: b.minlon, maxlat: b.maxlat, maxlon: b.maxlon });
+            let stats = py.allow_threads(|| match bounds.as_ref() {
+                Some(bounds) => inner.stats_within(bounds),
+                None => inner.stats(),
+            });
+            let dict = PyDict::new(py);
+            dict.set_item("nodes", stats.nodes)?;
+            dict.set_item("edges", stats.edges)?;
+            dict.set_item("edge_length_total", stats.edge_length_total)?;
+            dict.set_item("edge_length_avg", stats.edge_length_avg)?;
+            dict.set_item("streets", stats.streets)?;
+            dict.set_item("street_length_total", stats.street_length_total)?;
+            dict.set_item("street_length_avg", stats.street_length_avg)?;
+            dict.set_item("street_length_by_highway", stats.street_length_by_highway)?;
+            dict.set_item("streets_per_node_avg", stats.streets_per_node_avg)?;
+            dict.set_item("streets_per_node_counts", stats.streets_per_node_counts)?;
+            dict.set_item("intersections", stats.intersections)?;
+            dict.set_item("area", stats.area)?;
+            dict.set_item("intersection_density", stats.intersection_density)?;
+            dict.set_item("circuity_avg", stats.circuity_avg)?;
+            dict.set_item("self_loop_proportion", stats.self_loop_proportion)?;
+            return Ok(dict);
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self)"]
+    /// Every intersection and dead end of the network
+    ///
+    /// Returns
+    /// -------
+    /// list of Intersection
+    ///     Nodes where one street ends or three streets or more meet, by
+    ///     node id
+    pub fn intersections(&self, py: Python) -> PyResult<Vec<Intersection>> {
+        if let Some(inner) = self.inner.as_ref() {
+            let found = py.allow_threads(|| inner.intersections());
+            return Ok(found.into_iter().map(|inner| Intersection { inner }).collect());
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, waypoints, uturns=True)"]
+    #[args(uturns = "true")]
+    /// Route through an ordered list of waypoints
+    ///
+    /// Parameters
+    /// ----------
+    /// waypoints : list of int
+    ///     OSM ids of the origin, the intermediate stops and the destination
+    /// uturns : bool, optional
+    ///     Whether a leg may leave a stop back along the street the route
+    ///     arrived by
+    ///
+    /// Returns
+    /// -------
+    /// Route
+    ///     The combined route, :py:attr:`Route.legs` has one leg for each
+    ///     pair of consecutive waypoints
+    ///
+    /// Raises
+    /// ------
+    /// UnknownNodeError
+    ///     If a waypoint is not part of the network
+    /// NoRouteError
+    ///     If a waypoint can not be reached from the previous one
+    /// ValueError
+    ///     If no waypoint is given
+    pub fn route_via(&self, py: Python, waypoints: Vec<i64>, uturns: bool) -> PyResult<Route> {
+        if let Some(inner) = self.inner.as_ref() {
+            let route = py.allow_threads(|| inner.route_via(&waypoints, uturns))?;
+            return Ok(Route { inner: route });
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, a, b, k)"]
+    /// The k best loopless routes between two nodes (Yen's algorithm)
+    ///
+    /// Parameters
+    /// ----------
+    /// a : int
+    ///     OSM id of the origin node
+    /// b : int
+    ///     OSM id of the destination node
+    /// k : int
+    ///     Number of routes
+    ///
+    /// Returns
+    /// -------
+    /// list of Route
+    ///     Routes by increasing weight, fewer than k when the network does
+    ///     not have as many
+    ///
+    /// Raises
+    /// ------
+    /// UnknownNodeError
+    ///     If either node is not part of the network
+    /// NoRouteError
+    ///     If the destination can not be reached
+    pub fn k_shortest_paths(&self, py: Python, a: i64, b: i64, k: usize) -> PyResult<Vec<Route>> {
+        if let Some(inner) = self.inner.as_ref() {
+            let routes = py.allow_threads(|| inner.k_shortest_paths(a, b, k))?;
+            return Ok(routes.into_iter().map(|inner| Route { inner }).collect());
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, a, b, count=3, max_stretch=1.25, max_overlap=0.6)"]
+    #[args(count = "3", max_stretch = "1.25", max_overlap = "0.6")]
+    /// Reasonably different routes between two nodes (penalty method)
+    ///
+    /// Parameters
+    /// ----------
+    /// a : int
+    ///     OSM id of the origin node
+    /// b : int
+    ///     OSM id of the destination node
+    /// count : int, optional
+    ///     Largest number of routes, the best route included
+    /// max_stretch : float, optional
+    ///     Largest weight of an alternative relative to the best route
+    /// max_overlap : float, optional
+    ///     Largest share of the length of an alternative traversed by
+    ///     another route
+    ///
+    /// Returns
+    /// -------
+    /// list of Route
+    ///     The best route first, then the alternatives
+    ///
+    /// Raises
+    /// ------
+    /// UnknownNodeError
+    ///     If either node is not part of the network
+    /// NoRouteError
+    ///     If the destination can not be reached
+    pub fn alternatives(&self, py: Python, a: i64, b: i64, count: usize, max_stretch: f64, max_overlap: f64) -> PyResult<Vec<Route>> {
+        if let Some(inner) = self.inner.as_ref() {
+            let routes = py.allow_threads(|| inner.alternatives(a, b, count, max_stretch, max_overlap))?;
+            return Ok(routes.into_iter().map(|inner| Route { inner }).collect());
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, sources, targets, annotation=\"distance\")"]
+    #[args(annotation = "\"distance\"")]
+    /// Cost matrix of the best routes from each source to each target
+    ///
+    /// Rows are computed in parallel on the prepared graph with the GIL
+    /// released.
+    ///
+    /// Parameters
+    /// ----------
+    /// sources : list of int
+    ///     OSM ids of the origin nodes
+    /// targets : list of int
+    ///     OSM ids of the destination nodes
+    /// annotation : str, optional
+    ///     Either ``distance`` (meters, default) or ``duration`` (seconds)
+    ///
+    /// Returns
+    /// -------
+    /// list of list of float
+    ///     One row per source and one column per target, None where the
+    ///     target can not be reached or a node is not part of the network.
+    pub fn matrix(&self, py: Python, sources: Vec<i64>, targets: Vec<i64>, annotation: &str) -> PyResult<Vec<Vec<Option<f64>>>> {
+        let inner = match self.inner.as_ref() {
+            Some(inner) => inner,
+            None => return Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized")),
+        };
+
+        let matrix = py.allow_threads(|| inner.matrix(&sources, &targets));
+        match annotation {
+            "distance" => Ok(matrix.distances),
+            "duration" => Ok(matrix.durations),
+            _ => Err(exceptions::PyValueError::new_err(format!("Unknown annotation {}", annotation))),
+        }
+    }
+
+    #[text_signature = "(self, route)"]
+    /// Turn-by-turn instructions of a route computed on this network
+    ///
+    /// Parameters
+    /// ----------
+    /// route : Route
+    ///     Route returned by :py:func:`StreetNetwork.route`
+    ///
+    /// Returns
+    /// -------
+    /// list of Step
+    ///     Steps from the departure to the arrival
+    pub fn instructions(&self, route: &Route) -> PyResult<Vec<Step>> {
+        if let Some(inner) = self.inner.as_ref() {
+            return Ok(inner.instructions(&route.inner).into_iter().map(|inner| Step { inner }).collect());
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, metric=\"distance\", samples=None, seed=0, normalized=True)"]
+    #[args(metric = "\"distance\"", samples = "None", seed = "0", normalized = "true")]
+    /// Betweenness centrality of every node
+    ///
+    /// The score of a node is the number of shortest paths between two
+    /// other nodes going through it. Sources are processed in parallel.
+    ///
+    /// Parameters
+    /// ----------
+    /// metric : str, optional
+    ///     Either ``distance`` (default) or ``time``
+    /// samples : int, optional
+    ///     Estimate the scores from the paths out of this many nodes drawn
+    ///     at random, all nodes by default
+    /// seed : int, optional
+    ///     Seed of the random draw
+    /// normalized : bool, optional
+    ///     Divide the scores by the number of pairs of other nodes
+    ///
+    /// Returns
+    /// -------
+    /// dict of int to float
+    ///     Score of each OSM node id
+    pub fn betweenness(&self, py: Python, metric: &str, samples: Option<usize>, seed: u64, normalized: bool) -> PyResult<HashMap<i64, f64>> {
+        let inner = match self.inner.as_ref() {
+            Some(inner) => inner,
+            None => return Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized")),
+        };
+        let metric = metric.parse::<profile::Metric>().map_err(exceptions::PyValueError::new_err)?;
+
+        Ok(py.allow_threads(|| match samples {
+            Some(samples) => inner.sampled_betweenness(metric, samples, seed, normalized),
+            None => inner.betweenness(metric, normalized),
+        }))
+    }
+
+    #[text_signature = "(self, metric=\"distance\")"]
+    #[args(metric = "\"distance\"")]
+    /// Closeness centrality of every node
+    ///
+    /// The inverse of the mean cost of the shortest paths to the reached
+    /// nodes, scaled by the share of the nodes reached.
+    ///
+    /// Parameters
+    /// ----------
+    /// metric : str, optional
+    ///     Either ``distance`` (default) or ``time``
+    ///
+    /// Returns
+    /// -------
+    /// dict of int to float
+    ///     Score of each OSM node id
+    pub fn closeness(&self, py: Python, metric: &str) -> PyResult<HashMap<i64, f64>> {
+        let inner = match self.inner.as_ref() {
+            Some(inner) => inner,
+            None => return Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized")),
+        };
+        let metric = metric.parse::<profile::Metric>().map_err(exceptions::PyValueError::new_err)?;
+
+        Ok(py.allow_threads(|| inner.closeness(metric)))
+    }
+
+    #[text_signature = "(self)"]
+    /// Straightness centrality of every node
+    ///
+    /// The mean ratio between the straight distance and the length of the
+    /// shortest path to every other node.
+    ///
+    /// Returns
+    /// -------
+    /// dict of int to float
+    ///     Score of each OSM node id
+    pub fn straightness(&self, py: Python) -> PyResult<HashMap<i64, f64>> {
+        if let Some(inner) = self.inner.as_ref() {
+            return Ok(py.allow_threads(|| inner.straightness()));
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, scores)"]
+    /// Node scores with the node coordinates as GeoJSON
+    ///
+    /// Parameters
+    /// ----------
+    /// scores : dict of str to dict
+    ///     Scores by OSM node id for each property name, e.g.
+    ///     ``{"betweenness": network.betweenness()}``
+    ///
+    /// Returns
+    /// -------
+    /// str
+    ///     FeatureCollection with a Point feature for each node
+    pub fn scores_geojson(&self, scores: HashMap<String, HashMap<i64, f64>>) -> PyResult<String> {
+        if let Some(inner) = self.inner.as_ref() {
+            let mut names = scores.keys().collect::<Vec<&String>>();
+            names.sort();
+            let scores = names.into_iter().map(|n| (n.as_str(), &scores[n])).collect::<Vec<_>>();
+            return Ok(centrality::to_geojson(inner, &scores).to_string());
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, start, budget, metric=\"time\")"]
+    #[args(metric = "\"time\"")]
+    /// Every node reachable from a start within a budget
+    ///
+    /// Parameters
+    /// ----------
+    /// start : int or tuple of float
+    ///     OSM id of the start node, or (lat, lon) snapped to the nearest node
+    /// budget : float
+    ///     Largest cost, in seconds for ``time`` and meters for ``distance``
+    /// metric : str, optional
+    ///     Either ``time`` (default) or ``distance``
+    ///
+    /// Returns
+    /// -------
+    /// dict of int to float
+    ///     Cost of each reachable node from the start
+    pub fn reachable(&self, py: Python, start: &PyAny, budget: f64, metric: &str) -> PyResult<HashMap<i64, f64>> {
+        let inner = match self.inner.as_ref() {
+            Some(inner) => inner,
+            None => return Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized")),
+        };
+        let metric = metric.parse::<profile::Metric>().map_err(exceptions::PyValueError::new_err)?;
+        let start = start_node(inner, start)?;
+
+        let nodes = py.allow_threads(|| inner.reachable(start, budget, metric))?;
+        Ok(nodes.into_iter().collect())
+    }
+
+    #[text_signature = "(self, start, cutoffs, metric=\"time\", alpha=150.0)"]
+    #[args(metric = "\"time\"", alpha = "150.0")]
+    /// Isochrone polygons around a start for several cutoffs
+    ///
+    /// Walking catchments of 5, 10 and 15 minutes are
+    /// ``network.isochrones(node, [300, 600, 900])`` on a foot network.
+    ///
+    /// Parameters
+    /// ----------
+    /// start : int or tuple of float
+    ///     OSM id of the start node, or (lat, lon) snapped to the nearest node
+    /// cutoffs : list of float
+    ///     Budgets, in seconds for ``time`` and meters for ``distance``
+    /// metric : str, optional
+    ///     Either ``time`` (default) or ``distance``
+    /// alpha : float, optional
+    ///     Largest circumradius in meters of the triangles of the alpha
+    ///     shape, smaller values follow the streets more closely
+    ///
+    /// Returns
+    /// -------
+    /// str
+    ///     GeoJSON FeatureCollection with one MultiPolygon per cutoff, the
+    ///     cutoff is in the ``cutoff`` property
+    pub fn isochrones(&self, py: Python, start: &PyAny, cutoffs: Vec<f64>, metric: &str, alpha: f64) -> PyResult<String> {
+        let inner = match self.inner.as_ref() {
+            Some(inner) => inner,
+            None => return Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized")),
+        };
+        let metric = metric.parse::<profile::Metric>().map_err(exceptions::PyValueError::new_err)?;
+        let start = start_node(inner, start)?;
+
+        let isochrones = py.allow_threads(|| inner.isochrones(start, &cutoffs, metric, alpha))?;
+        Ok(isochrone::to_geojson(&isochrones).to_string())
+    }
+
+    #[text_signature = "(self, edges=None, ways=None)"]
+    #[args(edges = "None", ways = "None")]
+    /// Update the speeds of edges and ways from live traffic
+    ///
+    /// All updates are applied at once. The next query prepares the graph
+    /// again, reusing the node ordering of the previous preparation.
+    ///
+    /// Parameters
+    /// ----------
+    /// edges : list of tuple, optional
+    ///     (from, to, speed) with OSM node ids and the speed in km/h
+    /// ways : list of tuple, optional
+    ///     (way, speed) applying to both directions of the way
+    ///
+    /// Returns
+    /// -------
+    /// int
+    ///     Number of updated edges
+    pub fn update_speeds(&mut self, py: Python, edges: Option<Vec<(i64, i64, f64)>>, ways: Option<Vec<(i64, f64)>>) -> PyResult<usize> {
+        let inner = match self.inner.as_mut() {
+            Some(inner) => inner,
+            None => return Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized")),
+        };
+
+        let update = traffic::TrafficUpdate {
+            edges: edges.unwrap_or_default(),
+            ways: ways.unwrap_or_default(),
+        };
+        Ok(py.allow_threads(|| inner.update(&update)))
+    }
+
+    #[text_signature = "(self, a, b, speed)"]
+    /// Set the speed in km/h from node ``a`` to node ``b``
+    ///
+    /// Returns
+    /// -------
+    /// int
+    ///     Number of updated edges
+    pub fn set_edge_speed(&mut self, a: i64, b: i64, speed: f64) -> PyResult<usize> {
+        if let Some(inner) = self.inner.as_mut() {
+            return Ok(inner.set_edge_speed(a, b, speed));
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    #[text_signature = "(self, way, speed)"]
+    /// Set the speed in km/h along both directions of a way
+    ///
+    /// Returns
+    /// -------
+    /// int
+    ///     Number of updated edges
+    pub fn set_way_speed(&mut self, way: i64, speed: f64) -> PyResult<usize> {
+        if let Some(inner) = self.inner.as_mut() {
+            return Ok(inner.set_way_speed(way, speed));
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    pub fn __getstate__(&self, py: Python) -> PyResult<PyObject> {
+        if let Some(inner) = self.inner.as_ref() {
+            return Ok(PyBytes::new(py, &inner.serialize()).to_object(py));
+        }
+
+        Err(PyErr::new::<exceptions::PyRuntimeError, _>("Has not been initialized"))
+    }
+
+    fn __setstate__(&mut self, py: Python, state: PyObject) -> PyResult<()> {
+        match state.extract::<&PyBytes>(py) {
+            Ok(s) => {
+                self.inner = Some(network::StreetNetwork::deserialize(s.as_bytes().to_vec()));
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+
+/// Connectivity named ``strong`` or ``weak``
+fn connectivity_arg(connectivity: &str) -> PyResult<components::Connectivity> {
+    connectivity.parse::<components::Connectivity>().map_err(exceptions::PyValueError::new_err)
+}
+
+/// OSM id of a node given either as an id or as a (lat, lon) pair
+fn start_node(network: &network::StreetNetwork, start: &PyAny) -> PyResult<i64> {
+    if let Ok(id) = start.extract::<i64>() {
+        return Ok(id);
+    }
+    let (lat, lon) = start.extract::<(f64, f64)>()?;
+    if !lat.is_finite() || !lon.is_finite() {
+        return Err(exceptions::PyValueError::new_err("Coordinates must be finite numbers"));
+    }
+    network.nearest_node(lat, lon)
+    .ok_or_else(|| NoRouteError::new_err("The network has no nodes"))
 }
 
 
@@ -442,10 +1931,22 @@ fn _binding(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Map>()?;
     m.add_class::<Node>()?;
     m.add_class::<Way>()?;
+    m.add_class::<Relation>()?;
+    m.add_class::<Member>()?;
     m.add_class::<Bounds>()?;
     m.add_class::<NodeQueryBuilder>()?;
     m.add_class::<WayQueryBuilder>()?;
+    m.add_class::<RelationQueryBuilder>()?;
 
+    m.add_class::<RoutingProfile>()?;
+    m.add_class::<Route>()?;
+    m.add_class::<Leg>()?;
+    m.add_class::<Step>()?;
+    m.add_class::<Intersection>()?;
+    m.add_class::<Snap>()?;
+    m.add_class::<Matching>()?;
     m.add_class::<StreetNetwork>()?;
+    m.add("NoRouteError", py.get_type::<NoRouteError>())?;
+    m.add("UnknownNodeError", py.get_type::<UnknownNodeError>())?;
     Ok(())
 }
@@ -1,10 +1,13 @@
 /* query.rs */
+use std::collections::HashSet;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use osm_xml as osm;
 
 use crate::map;
 use crate::map::TaggableElement;
+use crate::spatial::SpatialIndex;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum FilterQuery {
@@ -31,7 +34,7 @@ impl Filter<map::Node> for FilterQuery {
                         return true;
                     }
                 }
-                return false;
+                false
             },
             _ => panic!("You're using exclusive filter on wrong type")
         }
@@ -47,17 +50,33 @@ impl Filter<map::Way> for FilterQuery {
                         return true;
                     }
                 }
-                return false;
+                false
             },
             Self::IsPolygon => item.is_polygon(),
             Self::HasNodes(node_ids) => {
                 // TODO: We will create faster index later
                 for element_id in &item.nodes() {
-                    if node_ids.contains(&element_id) {
+                    if node_ids.contains(element_id) {
                         return true;
                     }
                 }
-                return false;
+                false
+            },
+            _ => panic!("You're using exclusive filter on wrong type")
+        }
+    }
+}
+
+impl Filter<map::Relation> for FilterQuery {
+    fn filter(&self, item: map::Relation) -> bool {
+        match self {
+            Self::ByTag(k, values) => {
+                if let Some(val) = item.get_tag_value(k) {
+                    if values.contains(&val.to_owned()) {
+                        return true;
+                    }
+                }
+                false
             },
             _ => panic!("You're using exclusive filter on wrong type")
         }
@@ -82,6 +101,10 @@ pub trait QueryBuilder<T> {
         unimplemented!()
     }
 
+    /// Elements passing every filter, or all of them when there is none
+    ///
+    /// Filters are combined like [`Builder::iter`] does, an element passing
+    /// several filters is returned once.
     fn get(&self) -> Vec<T>;
 
     fn by_id(&self, id: i64) -> T;
@@ -90,60 +113,166 @@ pub trait QueryBuilder<T> {
 // #[derive(Clone)]
 pub struct BuilderIter<T> where T: Clone {
     // iter: dyn std::iter::IntoIterator<Item = (i64, T), IntoIter = std::collections::hash_map::IntoIter<i64, T>>,
-    into_iter: std::collections::hash_map::IntoIter<i64, T>,
+    into_iter: std::vec::IntoIter<(i64, T)>,
     // iter: std::collections::hash_map::Iter<'a, osm::Id, T>,
     conditions: Vec<FilterQuery>,
 }
 
-impl<'a, T: Clone> BuilderIter<T> {
-    fn new(into_iter: std::collections::hash_map::IntoIter<i64, T>, conditions: Vec<FilterQuery>) -> Self {
+impl<T: Clone> BuilderIter<T> {
+    fn new(into_iter: std::vec::IntoIter<(i64, T)>, conditions: Vec<FilterQuery>) -> Self {
         BuilderIter { into_iter, conditions }
     }
 }
 
-impl<'a, T: TaggableElement + Debug + Clone> Iterator for BuilderIter<T>
+impl<T: TaggableElement + Debug + Clone> Iterator for BuilderIter<T>
 where
     FilterQuery: Filter<T>
 {
     type Item = (osm::Id, T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some((k, v)) = self.into_iter.next() {
+        for (k, v) in self.into_iter.by_ref() {
             if self.conditions.iter().all(|c| c.filter(v.clone())) {
                 return Some((k, v));
             }
         }
-        return None;
+        None
     }
 }
 
 #[derive(Clone)]
 pub struct Builder<T: Clone> {
-    storage: std::sync::Arc<fnv::FnvHashMap<osm::Id, T>>,
+    storage: Arc<fnv::FnvHashMap<osm::Id, T>>,
     // iter: Option<std::collections::hash_map::Iter<'static, osm::Id, T>>,
     conditions: Vec<FilterQuery>,
+    index: Option<Arc<SpatialIndex>>,
+    // Ids selected by spatial filters, in the order they are returned
+    candidates: Option<Arc<Vec<osm::Id>>>,
 }
 
 impl<T: Clone> Builder<T> {
     pub fn new(s: fnv::FnvHashMap<osm::Id, T>) -> Builder<T> {
         Builder {
-            storage: std::sync::Arc::new(s),
+            storage: Arc::new(s),
             conditions: vec![],
+            index: None,
+            candidates: None,
+        }
+    }
+
+    /// Create builder that also support spatial filters
+    pub fn with_index(s: fnv::FnvHashMap<osm::Id, T>, index: Arc<SpatialIndex>) -> Builder<T> {
+        Builder {
+            index: Some(index),
+            ..Builder::new(s)
         }
     }
 
     pub fn iter(&self) -> BuilderIter<T> {
-        let storage = (*self.storage).clone();
+        let items = match &self.candidates {
+            Some(ids) => ids.iter()
+                .filter_map(|id| self.storage.get(id).map(|v| (*id, v.clone())))
+                .collect::<Vec<(osm::Id, T)>>(),
+            None => (*self.storage).clone().into_iter().collect(),
+        };
         let conditions = self.conditions.clone();
-        BuilderIter::new(storage.into_iter(), conditions)
+        BuilderIter::new(items.into_iter(), conditions)
+    }
+
+    fn spatial_index(&self) -> Arc<SpatialIndex> {
+        self.index.clone().expect("Spatial filters require a builder created from Map")
+    }
+
+    /// Keep only `ids` among the current candidates
+    fn restrict(mut self, ids: Vec<osm::Id>) -> Self {
+        let ids = match &self.candidates {
+            Some(current) => {
+                let ids = ids.into_iter().collect::<HashSet<osm::Id>>();
+                current.iter().filter(|id| ids.contains(id)).copied().collect()
+            },
+            None => ids,
+        };
+        self.candidates = Some(Arc::new(ids));
+        self
     }
 
+    /// Keep the first `k` ids of `ordered` that are candidates and pass `accept`
+    fn take_nearest<F>(mut self, ordered: impl Iterator<Item = osm::Id>, k: usize, accept: F) -> Self
+    where
+        F: Fn(&T) -> bool
+    {
+        let current = self.candidates.as_ref()
+        .map(|ids| ids.iter().copied().collect::<HashSet<osm::Id>>());
+
+        let nearest = ordered
+        .filter(|id| current.as_ref().map(|c| c.contains(id)).unwrap_or(true))
+        .filter(|id| {
+            self.storage.get(id).map(&accept).unwrap_or(false)
+        })
+        .take(k)
+        .collect();
+
+        self.candidates = Some(Arc::new(nearest));
+        self
+    }
+
+    #[cfg(test)]
     fn filters(&self) -> Vec<FilterQuery> {
         self.conditions.clone()
     }
 }
 
+impl Builder<map::Node> {
+    /// Filter Node located inside the bounding box
+    pub fn within_bbox(self, minlat: f64, minlon: f64, maxlat: f64, maxlon: f64) -> Self {
+        let bounds = map::Bounds { minlat, minlon, maxlat, maxlon };
+        let ids = self.spatial_index().nodes_in_bbox(&bounds);
+        self.restrict(ids)
+    }
+
+    /// Filter Node located at most `meters` away from the point
+    pub fn within_radius(self, lat: f64, lon: f64, meters: f64) -> Self {
+        let ids = self.spatial_index().nodes_within_radius(lat, lon, meters);
+        self.restrict(ids)
+    }
+
+    /// Keep the `k` Node nearest to the point, ordered by distance
+    ///
+    /// Only Node passing the filters added before this call are counted.
+    pub fn nearest(self, lat: f64, lon: f64, k: usize) -> Self {
+        let index = self.spatial_index();
+        let conditions = self.conditions.clone();
+        self.take_nearest(index.nearest_nodes(lat, lon), k, |v| {
+            conditions.iter().all(|c| c.filter(v.clone()))
+        })
+    }
+}
+
 impl Builder<map::Way> {
+    /// Filter Way crossing or lying inside the bounding box
+    pub fn within_bbox(self, minlat: f64, minlon: f64, maxlat: f64, maxlon: f64) -> Self {
+        let bounds = map::Bounds { minlat, minlon, maxlat, maxlon };
+        let ids = self.spatial_index().ways_in_bbox(&bounds);
+        self.restrict(ids)
+    }
+
+    /// Filter Way passing at most `meters` away from the point
+    pub fn within_radius(self, lat: f64, lon: f64, meters: f64) -> Self {
+        let ids = self.spatial_index().ways_within_radius(lat, lon, meters);
+        self.restrict(ids)
+    }
+
+    /// Keep the `k` Way nearest to the point, ordered by distance
+    ///
+    /// Only Way passing the filters added before this call are counted.
+    pub fn nearest(self, lat: f64, lon: f64, k: usize) -> Self {
+        let index = self.spatial_index();
+        let conditions = self.conditions.clone();
+        self.take_nearest(index.nearest_ways(lat, lon), k, |v| {
+            conditions.iter().all(|c| c.filter(v.clone()))
+        })
+    }
+
     pub fn contain_nodes(mut self, node_ids: Vec<i64>) -> Self {
         self.conditions.push(FilterQuery::HasNodes(node_ids));
         self
@@ -163,20 +292,12 @@ impl QueryBuilder<map::Way> for Builder<map::Way> {
 
     fn by_id(&self, id: i64) -> map::Way {
         self.storage.get(&id)
-        .map(|w| w.clone())
-        .expect(format!("No data with id {} found", id).as_str())
+        .cloned()
+        .unwrap_or_else(|| panic!("No data with id {} found", id))
     }
 
     fn get(&self) -> Vec<map::Way> {
-        let mut r: Vec<map::Way> = vec![];
-        for (_k, v) in self.storage.iter() {
-            for c in &self.conditions {
-                if c.filter(v.clone()) {
-                    r.push(v.clone())
-                }
-            }
-        }
-        r
+        self.iter().map(|(_k, v)| v).collect()
     }
 }
 
@@ -187,20 +308,28 @@ impl QueryBuilder<map::Node> for Builder<map::Node> {
 
     fn by_id(&self, id: i64) -> map::Node {
         self.storage.get(&id)
-        .map(|n| n.clone())
-        .expect(format!("No data with id {} found", id).as_str())
+        .cloned()
+        .unwrap_or_else(|| panic!("No data with id {} found", id))
     }
 
     fn get(&self) -> Vec<map::Node> {
-        let mut r: Vec<map::Node> = vec![];
-        for (_k, v) in self.storage.iter() {
-            for c in &self.conditions {
-                if c.filter(v.clone()) {
-                    r.push(v.clone())
-                }
-            }
-        }
-        r
+        self.iter().map(|(_k, v)| v).collect()
+    }
+}
+
+impl QueryBuilder<map::Relation> for Builder<map::Relation> {
+    fn append_filter(&mut self, f: FilterQuery) {
+        self.conditions.push(f);
+    }
+
+    fn by_id(&self, id: i64) -> map::Relation {
+        self.storage.get(&id)
+        .cloned()
+        .unwrap_or_else(|| panic!("No data with id {} found", id))
+    }
+
+    fn get(&self) -> Vec<map::Relation> {
+        self.iter().map(|(_k, v)| v).collect()
     }
 }
 
@@ -222,7 +351,7 @@ mod test {
             hm.insert(*key, value.into());
         }
 
-        let mut qstreets: Builder<crate::map::Way> = Builder::new(hm)
+        let qstreets: Builder<crate::map::Way> = Builder::new(hm)
         .by_tag_in("highway", vec![
             "primary"      , "secondary"      , "tertiary",
             "primary_link" , "secondary_link" , "tertiary_link",
@@ -243,13 +372,13 @@ mod test {
             hm.insert(*key, value.into());
         }
 
-        let highway_filter = vec![
+        let highway_filter = [
             "primary"      , "secondary"      , "tertiary",
             "primary_link" , "secondary_link" , "tertiary_link",
             "residential"  , "service"
         ];
 
-        let mut qstreets: Builder<crate::map::Way> = Builder::new(hm.clone())
+        let qstreets: Builder<crate::map::Way> = Builder::new(hm.clone())
         .by_tag_in("highway", vec![
             "primary"      , "secondary"      , "tertiary",
             "primary_link" , "secondary_link" , "tertiary_link",
@@ -257,7 +386,7 @@ mod test {
         ]);
 
         std::assert!(
-            FilterQuery::ByTag("highway".to_string(), vec![
+            FilterQuery::ByTag("highway".to_string(), [
                 "primary"      , "secondary"      , "tertiary",
                 "primary_link" , "secondary_link" , "tertiary_link",
                 "residential"  , "service"
@@ -265,9 +394,24 @@ mod test {
             == qstreets.filters().first().unwrap().clone()
         );
 
-        qstreets.iter().for_each(|(k, v)| {
-            let tagval = v.tags.get("highway").map(|s| s.clone()).unwrap_or("".to_owned());
+        qstreets.iter().for_each(|(_k, v)| {
+            let tagval = v.tags.get("highway").cloned().unwrap_or_default();
             assert!(highway_filter.contains(&tagval.as_str()));
         });
     }
+
+    #[test]
+    fn get_requires_every_filter() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.0" lon="0.0"><tag k="amenity" v="cafe"/><tag k="wifi" v="yes"/></node>
+          <node id="2" lat="0.0" lon="0.1"><tag k="amenity" v="cafe"/></node>
+          <node id="3" lat="0.0" lon="0.2"><tag k="wifi" v="yes"/></node>
+        </osm>"#;
+        let map = crate::map::Map::from_xml(xml.as_bytes()).unwrap();
+
+        assert_eq!(map.nodes().get().len(), 3);
+        let cafes = map.nodes().by_tag_eq("amenity", "cafe").by_tag_eq("wifi", "yes").get();
+        assert_eq!(cafes.iter().map(|n| n.id).collect::<Vec<i64>>(), vec![1]);
+    }
 }
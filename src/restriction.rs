@@ -0,0 +1,278 @@
+/* restriction.rs */
+//! Turn restrictions read from ``type=restriction`` relations.
+//!
+//! A restriction forbids (``no_*``) or mandates (``only_*``) the turn from
+//! the ``from`` way onto the ``to`` way, either at a ``via`` node or after
+//! driving along one or more ``via`` ways.
+
+use crate::map::{Map, MemberType, Relation, Way};
+use crate::profile::TravelMode;
+use crate::queries::QueryBuilder;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestrictionKind {
+    /// The turn is forbidden, e.g. ``no_left_turn``
+    No,
+    /// The turn is the only one allowed, e.g. ``only_straight_on``
+    Only,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Junction of a turn restriction
+pub enum Via {
+    Node(i64),
+    /// Chain of ways travelled between the ``from`` and the ``to`` way
+    Ways(Vec<i64>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TurnRestriction {
+    /// Id of the relation
+    pub id: i64,
+    pub kind: RestrictionKind,
+    pub from: i64,
+    pub via: Via,
+    pub to: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Turn restriction expressed with node ids
+///
+/// `nodes` is the sequence entering the junction: the last node of the
+/// ``from`` way before the junction, then every node of the junction.
+/// `to` holds the nodes of the ``to`` way next to the end of the junction.
+pub struct NodeRestriction {
+    pub kind: RestrictionKind,
+    pub nodes: Vec<i64>,
+    pub to: Vec<i64>,
+}
+
+impl TurnRestriction {
+    /// Read the restriction of a relation applying to `mode`
+    ///
+    /// Returns None for other relations, for restrictions not applying to
+    /// `mode` (``restriction:<mode>``, ``except``) and for conditional or
+    /// incomplete ones. Pedestrians are only bound by ``restriction:foot``.
+    pub fn from_relation(relation: &Relation, mode: TravelMode) -> Option<TurnRestriction> {
+        if relation.relation_type() != Some("restriction") {
+            return None;
+        }
+
+        let specific: &[&str] = match mode {
+            TravelMode::Car => &["restriction:motorcar", "restriction:motor_vehicle", "restriction:vehicle"],
+            TravelMode::Bicycle => &["restriction:bicycle", "restriction:vehicle"],
+            TravelMode::Foot => &["restriction:foot"],
+        };
+        let value = specific.iter()
+        .find_map(|k| relation.tags.get(*k))
+        .or_else(|| if mode == TravelMode::Foot { None } else { relation.tags.get("restriction") })?;
+
+        let excepted = relation.tags.get("except")
+        .map(|v| v.split(';').any(|e| match mode {
+            TravelMode::Car => e == "motorcar" || e == "motor_vehicle",
+            TravelMode::Bicycle => e == "bicycle",
+            TravelMode::Foot => e == "foot",
+        }))
+        .unwrap_or(false);
+        if excepted {
+            return None;
+        }
+
+        let kind = if value.starts_with("no_") {
+            RestrictionKind::No
+        } else if value.starts_with("only_") {
+            RestrictionKind::Only
+        } else {
+            return None;
+        };
+
+        let single_way = |role: &str| {
+            match relation.members_with_role(role).as_slice() {
+                [m] if m.member_type == MemberType::Way => Some(m.id),
+                _ => None,
+            }
+        };
+        let (from, to) = (single_way("from")?, single_way("to")?);
+
+        let vias = relation.members_with_role("via");
+        let via = match vias.first()?.member_type {
+            MemberType::Node if vias.len() == 1 => Via::Node(vias[0].id),
+            MemberType::Way if vias.iter().all(|m| m.member_type == MemberType::Way) => {
+                Via::Ways(vias.iter().map(|m| m.id).collect())
+            },
+            _ => return None,
+        };
+
+        Some(TurnRestriction { id: relation.id, kind, from, via, to })
+    }
+
+    /// Every restriction of the map applying to `mode`
+    pub fn from_map(map: &Map, mode: TravelMode) -> Vec<TurnRestriction> {
+        let mut restrictions = map.relations()
+        .by_tag_eq("type", "restriction")
+        .iter()
+        .filter_map(|(_, r)| TurnRestriction::from_relation(&r, mode))
+        .collect::<Vec<TurnRestriction>>();
+        restrictions.sort_by_key(|r| r.id);
+        restrictions
+    }
+
+    /// Express the restriction with node ids
+    ///
+    /// A ``from`` way passing through the junction gives one entry per
+    /// side, hence the list. Returns an empty list when the members are
+    /// missing from the map or are not connected.
+    pub fn node_restrictions(&self, map: &Map) -> Vec<NodeRestriction> {
+        let (from, to) = match (map.way(self.from), map.way(self.to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return vec![],
+        };
+
+        let junction = match &self.via {
+            Via::Node(id) => Some(vec![*id]),
+            Via::Ways(ids) => chain(map, ids, from, to),
+        };
+        let junction = match junction {
+            Some(j) => j,
+            None => return vec![],
+        };
+        let (first, last) = (junction[0], junction[junction.len() - 1]);
+
+        let to_nodes = neighbours(to, last).into_iter()
+        .filter(|n| junction.len() < 2 || *n != junction[junction.len() - 2])
+        .collect::<Vec<i64>>();
+        if to_nodes.is_empty() {
+            return vec![];
+        }
+
+        neighbours(from, first).into_iter()
+        .filter(|n| junction.len() < 2 || *n != junction[1])
+        .map(|entry| NodeRestriction {
+            kind: self.kind,
+            nodes: std::iter::once(entry).chain(junction.iter().copied()).collect(),
+            to: to_nodes.clone(),
+        })
+        .collect()
+    }
+}
+
+/// Nodes next to `node` along `way`
+fn neighbours(way: &Way, node: i64) -> Vec<i64> {
+    let mut found = vec![];
+    for (i, n) in way.nodes.iter().enumerate() {
+        if *n != node {
+            continue;
+        }
+        if i > 0 {
+            found.push(way.nodes[i - 1]);
+        }
+        if i + 1 < way.nodes.len() {
+            found.push(way.nodes[i + 1]);
+        }
+    }
+    found.dedup();
+    found
+}
+
+/// Nodes of the via ways, from the node shared with `from` to the node shared with `to`
+///
+/// None when a via way is missing or has no node left, as in an extract
+/// cutting it off.
+fn chain(map: &Map, ids: &[i64], from: &Way, to: &Way) -> Option<Vec<i64>> {
+    let mut remaining = ids.iter()
+    .map(|id| map.way(*id).filter(|w| !w.nodes.is_empty()).map(|w| w.nodes.clone()))
+    .collect::<Option<Vec<Vec<i64>>>>()?;
+
+    let ends = |w: &Vec<i64>| [w[0], w[w.len() - 1]];
+    let start = remaining.iter()
+    .flat_map(ends)
+    .find(|n| from.nodes.contains(n))?;
+
+    let mut nodes = vec![start];
+    while !remaining.is_empty() {
+        let current = *nodes.last().unwrap();
+        let pos = remaining.iter().position(|w| ends(w).contains(&current))?;
+        let mut way = remaining.swap_remove(pos);
+        if way[0] != current {
+            way.reverse();
+        }
+        nodes.extend_from_slice(&way[1..]);
+    }
+
+    if to.nodes.contains(nodes.last().unwrap()) {
+        Some(nodes)
+    } else {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn relation(tags: &[(&str, &str)]) -> Relation {
+        let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <relation id="1">
+            <member type="way" ref="10" role="from"/>
+            <member type="node" ref="2" role="via"/>
+            <member type="way" ref="11" role="to"/>
+            <tag k="type" v="restriction"/>
+            {}
+          </relation>
+        </osm>"#, tags.iter().map(|(k, v)| format!(r#"<tag k="{}" v="{}"/>"#, k, v)).collect::<String>());
+        Map::from_xml(xml.as_bytes()).unwrap().relation(1).unwrap().clone()
+    }
+
+    #[test]
+    fn restriction_applies_per_mode() {
+        let plain = relation(&[("restriction", "no_left_turn")]);
+        let expected = TurnRestriction {
+            id: 1,
+            kind: RestrictionKind::No,
+            from: 10,
+            via: Via::Node(2),
+            to: 11,
+        };
+        assert_eq!(TurnRestriction::from_relation(&plain, TravelMode::Car), Some(expected));
+        assert!(TurnRestriction::from_relation(&plain, TravelMode::Bicycle).is_some());
+        assert!(TurnRestriction::from_relation(&plain, TravelMode::Foot).is_none());
+
+        let except = relation(&[("restriction", "only_straight_on"), ("except", "bicycle;psv")]);
+        assert_eq!(TurnRestriction::from_relation(&except, TravelMode::Car).unwrap().kind, RestrictionKind::Only);
+        assert!(TurnRestriction::from_relation(&except, TravelMode::Bicycle).is_none());
+
+        let hgv = relation(&[("restriction:hgv", "no_right_turn")]);
+        assert!(TurnRestriction::from_relation(&hgv, TravelMode::Car).is_none());
+
+        let bicycle = relation(&[("restriction", "no_left_turn"), ("restriction:bicycle", "only_left_turn")]);
+        assert_eq!(TurnRestriction::from_relation(&bicycle, TravelMode::Bicycle).unwrap().kind, RestrictionKind::Only);
+    }
+
+    #[test]
+    fn via_ways_without_nodes_are_skipped() {
+        let xml = |via: &str| format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <way id="10"><nd ref="1"/><nd ref="2"/></way>
+          <way id="11"><nd ref="3"/><nd ref="4"/></way>
+          <way id="12">{}</way>
+          <relation id="1">
+            <member type="way" ref="10" role="from"/>
+            <member type="way" ref="12" role="via"/>
+            <member type="way" ref="11" role="to"/>
+            <tag k="type" v="restriction"/>
+            <tag k="restriction" v="no_u_turn"/>
+          </relation>
+        </osm>"#, via);
+
+        let map = Map::from_xml(xml(r#"<nd ref="2"/><nd ref="3"/>"#).as_bytes()).unwrap();
+        let restriction = TurnRestriction::from_relation(map.relation(1).unwrap(), TravelMode::Car).unwrap();
+        assert_eq!(restriction.node_restrictions(&map).len(), 1);
+
+        // All the nodes of the via way fell outside the extract
+        let map = Map::from_xml(xml("").as_bytes()).unwrap();
+        let restriction = TurnRestriction::from_relation(map.relation(1).unwrap(), TravelMode::Car).unwrap();
+        assert!(restriction.node_restrictions(&map).is_empty());
+    }
+}
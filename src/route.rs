@@ -0,0 +1,132 @@
+/* route.rs */
+//! Result of a route search on a street network.
+
+use std::fmt;
+
+/// Reason why no route is returned
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteError {
+    /// The node is not part of the network
+    UnknownNode(i64),
+    /// The destination can not be reached from the origin
+    NoRoute(i64, i64),
+    /// A route through waypoints needs at least one of them
+    NoWaypoints,
+    /// No street within reach of the (lat, lon) coordinate
+    NotSnapped(f64, f64),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownNode(id) => write!(f, "Node {} is not part of the network", id),
+            Self::NoRoute(a, b) => write!(f, "No route from node {} to node {}", a, b),
+            Self::NoWaypoints => write!(f, "No waypoints given"),
+            Self::NotSnapped(lat, lon) => write!(f, "No street near ({}, {})", lat, lon),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Part of a route between two consecutive nodes
+pub struct Segment {
+    /// Way the segment belongs to, 0 for an edge added through
+    /// [`StreetNetwork::graph_mut`](crate::network::StreetNetwork::graph_mut)
+    pub way: i64,
+    /// Length in meters
+    pub distance: f64,
+    /// Travel time in seconds
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Part of a route between two consecutive waypoints
+pub struct Leg {
+    /// OSM id of the waypoint the leg starts at
+    pub from: i64,
+    /// OSM id of the waypoint the leg ends at
+    pub to: i64,
+    /// Index in the route nodes of the first node of the leg
+    pub start: usize,
+    /// Index in the route nodes of the last node of the leg
+    pub end: usize,
+    /// Length in meters
+    pub distance: f64,
+    /// Travel time in seconds
+    pub duration: f64,
+    /// Sum of the edge weights
+    pub weight: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Route between two nodes
+pub struct Route {
+    /// OSM node ids from the origin to the destination
+    pub nodes: Vec<i64>,
+    /// (lat, lon) pairs of each node
+    pub geometry: Vec<(f64, f64)>,
+    /// One segment for each pair of consecutive nodes
+    pub segments: Vec<Segment>,
+    /// Ids of the traversed ways, without consecutive repeats
+    pub ways: Vec<i64>,
+    /// Names of the traversed streets, without consecutive repeats,
+    /// unnamed ways are skipped
+    pub names: Vec<String>,
+    /// Length in meters
+    pub distance: f64,
+    /// Travel time in seconds
+    pub duration: f64,
+    /// Sum of the edge weights, see [`RoutingProfile::weight`](crate::profile::RoutingProfile::weight)
+    pub weight: usize,
+    /// One leg between each pair of consecutive waypoints, a single leg
+    /// for a route without intermediate stops
+    pub legs: Vec<Leg>,
+}
+
+impl Route {
+    /// Route following each of `routes` one after the other
+    ///
+    /// Each route has to start where the previous one ends, the shared node
+    /// is kept once. Every route becomes one leg.
+    pub fn chain(routes: Vec<Route>) -> Route {
+        let mut chained = Route {
+            nodes: vec![],
+            geometry: vec![],
+            segments: vec![],
+            ways: vec![],
+            names: vec![],
+            distance: 0.0,
+            duration: 0.0,
+            weight: 0,
+            legs: vec![],
+        };
+
+        for route in routes {
+            let skip = if chained.nodes.is_empty() { 0 } else { 1 };
+            let start = chained.nodes.len().saturating_sub(skip);
+            chained.nodes.extend(route.nodes.iter().skip(skip));
+            chained.geometry.extend(route.geometry.iter().skip(skip));
+            chained.segments.extend(route.segments);
+            chained.ways.extend(route.ways);
+            chained.names.extend(route.names);
+            chained.distance += route.distance;
+            chained.duration += route.duration;
+            chained.weight += route.weight;
+            chained.legs.push(Leg {
+                from: route.nodes[0],
+                to: route.nodes[route.nodes.len() - 1],
+                start,
+                end: chained.nodes.len() - 1,
+                distance: route.distance,
+                duration: route.duration,
+                weight: route.weight,
+            });
+        }
+
+        chained.ways.dedup();
+        chained.names.dedup();
+        chained
+    }
+}
@@ -0,0 +1,177 @@
+/* search.rs */
+//! Dijkstra search over the edges of a street network.
+//!
+//! The contraction hierarchy only answers point to point queries with the
+//! weights it was prepared with. Bounded searches and searches with other
+//! edge costs go through here instead.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::network::{Edge, StreetNetwork};
+
+#[derive(Clone, Copy, PartialEq)]
+struct Entry {
+    cost: f64,
+    node: usize,
+}
+
+impl Eq for Entry {}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, the heap pops the cheapest entry first
+        other.cost.partial_cmp(&self.cost)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Shortest path tree, indexed by graph node id
+pub struct Tree {
+    /// Cost of each graph node, infinite when it is not reached
+    pub cost: Vec<f64>,
+    /// Index of the edge reaching each graph node
+    pub parent: Vec<Option<usize>>,
+}
+
+impl Tree {
+    pub fn is_reached(&self, node: usize) -> bool {
+        self.cost[node].is_finite()
+    }
+
+    /// Edges (index in `network.edges`) from the source to `node`, empty
+    /// when it is the source or is not reached
+    pub fn edges(&self, network: &StreetNetwork, node: usize) -> Vec<usize> {
+        let mut edges = vec![];
+        let mut current = node;
+        while let Some(edge) = self.parent[current] {
+            edges.push(edge);
+            current = network.edges[edge].from;
+        }
+        edges.reverse();
+        edges
+    }
+}
+
+/// Grow the shortest path tree from `sources` up to `limit`
+///
+/// Sources are (graph node, initial cost) pairs. `cost` gives the cost of
+/// an edge from its index, None leaves the edge out. The search stops once
+/// `done` returns true for a settled node.
+pub fn dijkstra<C, D>(network: &StreetNetwork, sources: &[(usize, f64)], limit: f64, cost: C, done: D) -> Tree
+where
+    C: Fn(usize, &Edge) -> Option<f64>,
+    D: Fn(usize) -> bool,
+{
+    let size = network.node_idx.len();
+    let mut tree = Tree {
+        cost: vec![f64::INFINITY; size],
+        parent: vec![None; size],
+    };
+    let mut settled = vec![false; size];
+    let mut heap = BinaryHeap::new();
+
+    for (node, cost) in sources {
+        if *cost < tree.cost[*node] {
+            tree.cost[*node] = *cost;
+            heap.push(Entry { cost: *cost, node: *node });
+        }
+    }
+
+    while let Some(Entry { cost: current, node }) = heap.pop() {
+        if settled[node] {
+            continue;
+        }
+        settled[node] = true;
+        if done(node) {
+            break;
+        }
+
+        for i in network.out_edges(node) {
+            let edge = &network.edges[*i];
+            let next = match cost(*i, edge) {
+                Some(c) => current + c,
+                None => continue,
+            };
+            if next <= limit && next < tree.cost[edge.to] {
+                tree.cost[edge.to] = next;
+                tree.parent[edge.to] = Some(*i);
+                heap.push(Entry { cost: next, node: edge.to });
+            }
+        }
+    }
+
+    tree
+}
+
+/// Every shortest path from a source, as needed by Brandes' betweenness
+pub struct Paths {
+    /// Cost of each graph node, infinite when it is not reached
+    pub cost: Vec<f64>,
+    /// Number of shortest paths reaching each graph node
+    pub count: Vec<f64>,
+    /// Graph nodes before each graph node on its shortest paths
+    pub previous: Vec<Vec<usize>>,
+    /// Reached graph nodes by increasing cost
+    pub order: Vec<usize>,
+}
+
+/// Shortest paths from `source` to every graph node with the edge `cost`
+///
+/// Costs within a relative 1e-9 of each other are equal, so that paths of
+/// the same length adding up in another order are all counted.
+pub fn all_paths<C>(network: &StreetNetwork, source: usize, cost: C) -> Paths
+where
+    C: Fn(usize, &Edge) -> Option<f64>,
+{
+    let size = network.node_idx.len();
+    let mut paths = Paths {
+        cost: vec![f64::INFINITY; size],
+        count: vec![0.0; size],
+        previous: vec![vec![]; size],
+        order: vec![],
+    };
+    let mut settled = vec![false; size];
+    let mut heap = BinaryHeap::new();
+    paths.cost[source] = 0.0;
+    paths.count[source] = 1.0;
+    heap.push(Entry { cost: 0.0, node: source });
+
+    while let Some(Entry { cost: current, node }) = heap.pop() {
+        if settled[node] {
+            continue;
+        }
+        settled[node] = true;
+        paths.order.push(node);
+
+        for i in network.out_edges(node) {
+            let edge = &network.edges[*i];
+            let next = match cost(*i, edge) {
+                Some(c) => current + c,
+                None => continue,
+            };
+            if settled[edge.to] {
+                continue;
+            }
+            let known = paths.cost[edge.to];
+            if known.is_finite() && (next - known).abs() <= 1e-9 * next.max(known) {
+                paths.count[edge.to] += paths.count[node];
+                paths.previous[edge.to].push(node);
+            } else if next < known {
+                paths.cost[edge.to] = next;
+                paths.count[edge.to] = paths.count[node];
+                paths.previous[edge.to] = vec![node];
+                heap.push(Entry { cost: next, node: edge.to });
+            }
+        }
+    }
+
+    paths
+}
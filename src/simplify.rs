@@ -0,0 +1,203 @@
+/* simplify.rs */
+//! Collapse the nodes of a street network where nothing can happen.
+//!
+//! A node is removed when it has exactly two neighbours and every edge
+//! entering it from one neighbour continues to the other one, so no route
+//! can turn there. The two edges around it are merged into one, which keeps
+//! the originals as pieces. Junctions split by turn restrictions are kept
+//! along with their copies.
+
+use std::collections::HashMap;
+
+use crate::network::{Edge, StreetNetwork};
+use crate::structure::NodeMap;
+
+/// Graph of a simplified network, see [`StreetNetwork::simplify`]
+pub(crate) struct Simplified {
+    pub node_idx: NodeMap<i64>,
+    pub edges: Vec<Edge>,
+    pub copies: HashMap<i64, Vec<usize>>,
+    pub coords: Vec<(f64, f64)>,
+    /// (lat, lon) of the removed nodes
+    pub shape_nodes: HashMap<i64, (f64, f64)>,
+}
+
+/// Pairs of (entering, leaving) edges through `node` when it can be removed
+fn through_pairs(edges: &[Option<Edge>], incoming: &[usize], outgoing: &[usize], node: usize) -> Option<Vec<(usize, usize)>> {
+    let mut neighbours = incoming.iter().map(|i| edges[*i].as_ref().unwrap().from)
+    .chain(outgoing.iter().map(|i| edges[*i].as_ref().unwrap().to))
+    .collect::<Vec<usize>>();
+    neighbours.sort_unstable();
+    neighbours.dedup();
+    if neighbours.len() != 2 || neighbours.contains(&node) || incoming.len() != outgoing.len() {
+        return None;
+    }
+
+    let mut pairs = vec![];
+    for i in incoming {
+        let from = edges[*i].as_ref().unwrap().from;
+        let mut next = outgoing.iter().filter(|o| edges[**o].as_ref().unwrap().to != from);
+        match (next.next(), next.next()) {
+            (Some(o), None) if !pairs.iter().any(|(_, p)| p == o) => pairs.push((*i, *o)),
+            _ => return None,
+        }
+    }
+    Some(pairs)
+}
+
+pub(crate) fn simplify(network: &StreetNetwork) -> Simplified {
+    let size = network.node_idx.len();
+    let mut edges = network.edges.iter()
+    .map(|e| Some(Edge { pieces: network.pieces(e), ..e.clone() }))
+    .collect::<Vec<Option<Edge>>>();
+    let mut incoming: Vec<Vec<usize>> = vec![vec![]; size];
+    let mut outgoing: Vec<Vec<usize>> = vec![vec![]; size];
+    for (i, edge) in network.edges.iter().enumerate() {
+        outgoing[edge.from].push(i);
+        incoming[edge.to].push(i);
+    }
+
+    let mut removed = vec![false; size];
+    let mut shape_nodes = HashMap::new();
+    for node in 0..size {
+        let id = network.node_idx.node(node);
+        if network.node_idx.find(id) != Some(node) || network.copies.contains_key(&id) {
+            continue;
+        }
+        let pairs = match through_pairs(&edges, &incoming[node], &outgoing[node], node) {
+            Some(pairs) => pairs,
+            None => continue,
+        };
+
+        for (i, o) in pairs {
+            let (a, b) = (edges[i].take().unwrap(), edges[o].take().unwrap());
+            outgoing[a.from].retain(|e| *e != i);
+            incoming[b.to].retain(|e| *e != o);
+
+            let merged = Edge {
+                from: a.from,
+                to: b.to,
+                way: a.way,
+                distance: a.distance + b.distance,
+                duration: a.duration + b.duration,
+                weight: a.weight + b.weight,
+                pieces: a.pieces.into_iter().chain(b.pieces).collect(),
+            };
+            outgoing[merged.from].push(edges.len());
+            incoming[merged.to].push(edges.len());
+            edges.push(Some(merged));
+        }
+        incoming[node].clear();
+        outgoing[node].clear();
+        removed[node] = true;
+        shape_nodes.insert(id, network.coords[node]);
+    }
+
+    let edges = edges.into_iter().flatten().collect();
+    renumber(network, &removed, edges, shape_nodes)
+}
+
+/// Graph of `network` without the `removed` graph nodes, `edges` are the
+/// remaining edges with their ids before renumbering
+pub(crate) fn renumber(network: &StreetNetwork, removed: &[bool], edges: Vec<Edge>, shape_nodes: HashMap<i64, (f64, f64)>) -> Simplified {
+    // Renumber the remaining graph nodes in the same order
+    let mut node_idx: NodeMap<i64> = NodeMap::new();
+    let mut renumbered: Vec<Option<usize>> = vec![None; removed.len()];
+    let mut coords = vec![];
+    for node in (0..removed.len()).filter(|n| !removed[*n]) {
+        let id = network.node_idx.node(node);
+        renumbered[node] = Some(match network.node_idx.find(id) == Some(node) {
+            true => node_idx.get_or_insert(id),
+            false => node_idx.insert_copy(id),
+        });
+        coords.push(network.coords[node]);
+    }
+
+    // A copy stands for the node when the original graph node is removed
+    let copies = network.copies.iter()
+    .map(|(id, nodes)| {
+        let canonical = node_idx.find(*id);
+        (*id, nodes.iter().filter_map(|n| renumbered[*n]).filter(|n| Some(*n) != canonical).collect::<Vec<usize>>())
+    })
+    .filter(|(_, nodes)| !nodes.is_empty())
+    .collect();
+
+    let edges = edges.into_iter()
+    .map(|mut edge| {
+        edge.from = renumbered[edge.from].unwrap();
+        edge.to = renumbered[edge.to].unwrap();
+        if edge.pieces.len() == 1 {
+            edge.pieces.clear();
+        }
+        edge
+    })
+    .collect();
+
+    Simplified { node_idx, edges, copies, coords, shape_nodes }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::map::Map;
+    use crate::network::StreetNetwork;
+    use crate::profile::RoutingProfile;
+
+    /// Street 1-2-3-4-5 made of two ways joined at 3, side street 4-6-7
+    /// with a oneway part 6-7, and a bend 8 on the oneway loop 5-8-1
+    const STREETS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.0001" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.0001" lon="0.003"/>
+      <node id="5" lat="0.000" lon="0.004"/>
+      <node id="6" lat="0.001" lon="0.003"/>
+      <node id="7" lat="0.002" lon="0.003"/>
+      <node id="8" lat="-0.001" lon="0.002"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/><tag k="name" v="West Street"/></way>
+      <way id="11"><nd ref="3"/><nd ref="4"/><nd ref="5"/><tag k="highway" v="residential"/><tag k="name" v="East Street"/></way>
+      <way id="12"><nd ref="4"/><nd ref="6"/><tag k="highway" v="residential"/></way>
+      <way id="13"><nd ref="6"/><nd ref="7"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+      <way id="14"><nd ref="5"/><nd ref="8"/><nd ref="1"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+    </osm>"#;
+
+    #[test]
+    fn shape_nodes_are_collapsed() {
+        let map = Map::from_xml(STREETS.as_bytes()).unwrap();
+        let full = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        let mut simple = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        simple.simplify();
+
+        // 6 stays, coming from 4 a route may turn back there
+        let mut kept = (0..simple.node_idx.len()).map(|n| simple.node_idx.node(n)).collect::<Vec<i64>>();
+        kept.sort_unstable();
+        assert_eq!(kept, vec![1, 4, 5, 6, 7]);
+        assert_eq!(simple.shape_nodes.len(), 3);
+        assert_eq!(simple.location(8), full.location(8));
+        assert_eq!(simple.edges.len(), 8);
+
+        let edge = simple.edge(1, 4).unwrap();
+        assert_eq!(edge.pieces.iter().map(|p| (p.node, p.way)).collect::<Vec<_>>(), vec![(2, 10), (3, 10), (4, 11)]);
+        let length = [(1, 2), (2, 3), (3, 4)].iter().map(|(a, b)| full.edge(*a, *b).unwrap().distance).sum::<f64>();
+        assert!((edge.distance - length).abs() < 1e-9);
+
+        for (a, b) in [(1, 7), (7, 1), (5, 1), (1, 5), (6, 5), (6, 1)] {
+            assert_eq!(simple.route(a, b), full.route(a, b), "{} -> {}", a, b);
+        }
+        let route = simple.route(6, 1).unwrap();
+        assert_eq!(route.nodes, vec![6, 4, 3, 2, 1]);
+        assert_eq!(route.names, vec!["East Street", "West Street"]);
+        assert_eq!(route.geometry.len(), route.nodes.len());
+
+        assert!(simple.route(2, 5).is_err());
+
+        // Traffic on a removed node slows down the piece it is on
+        let mut full = full;
+        assert_eq!(simple.set_edge_speed(2, 3, 5.0), 1);
+        full.set_edge_speed(2, 3, 5.0);
+        assert!((simple.route(1, 5).unwrap().duration - full.route(1, 5).unwrap().duration).abs() < 1e-9);
+        let restored = StreetNetwork::deserialize(simple.serialize());
+        assert_eq!(restored.route(5, 1).unwrap().nodes, full.route(5, 1).unwrap().nodes);
+        assert_eq!(simple.set_way_speed(11, 5.0), 4);
+    }
+}
@@ -0,0 +1,390 @@
+/* snap.rs */
+//! Coordinates snapped onto the street network.
+//!
+//! A coordinate is projected onto the closest segment between two
+//! consecutive nodes of a street. Routes between snapped coordinates start
+//! and end at virtual nodes partway along these segments, the parts of the
+//! segments they use cost in proportion to their length.
+
+use std::collections::HashMap;
+
+use rstar::RTree;
+use rstar::primitives::{GeomWithData, Line};
+
+use crate::geo;
+use crate::isochrone::Projection;
+use crate::network::{Piece, StreetNetwork};
+use crate::route::{Route, RouteError, Segment};
+
+/// Id of the virtual node a route between coordinates starts at
+///
+/// Files not uploaded yet use negative OSM ids, virtual nodes take the
+/// smallest ones no editor hands out.
+pub const START: i64 = i64::MIN;
+/// Id of the virtual node a route between coordinates ends at
+pub const END: i64 = i64::MIN + 1;
+
+/// Largest difference in degrees between a heading and the direction of
+/// travel along a segment it snaps to
+pub const HEADING_TOLERANCE: f64 = 45.0;
+
+/// Piece of an edge, (index in `network.edges`, index of the piece, OSM
+/// id of the node it starts at)
+type PieceLine = GeomWithData<Line<[f64; 2]>, (usize, usize, i64)>;
+
+/// Graph node of an OSM node
+type NodePoint = GeomWithData<[f64; 2], usize>;
+
+/// R-tree over every piece of every edge of a network, and over its nodes
+pub(crate) struct SegmentIndex {
+    projection: Projection,
+    tree: RTree<PieceLine>,
+    nodes: RTree<NodePoint>,
+}
+
+impl SegmentIndex {
+    pub(crate) fn new(network: &StreetNetwork) -> Self {
+        let count = network.coords.len().max(1) as f64;
+        let lat0 = network.coords.iter().map(|c| c.0).sum::<f64>() / count;
+        let lon0 = network.coords.iter().map(|c| c.1).sum::<f64>() / count;
+        let projection = Projection::new(lat0, lon0);
+
+        let mut lines = vec![];
+        for (i, edge) in network.edges.iter().enumerate() {
+            let mut from = network.node_idx.node(edge.from);
+            for (k, piece) in network.pieces(edge).iter().enumerate() {
+                if let (Some(a), Some(b)) = (network.location(from), network.location(piece.node)) {
+                    let line = Line::new(projection.project(a), projection.project(b));
+                    lines.push(PieceLine::new(line, (i, k, from)));
+                }
+                from = piece.node;
+            }
+        }
+
+        let points = (0..network.node_idx.len())
+        .filter(|n| network.node_idx.find(network.node_idx.node(*n)) == Some(*n))
+        .map(|n| NodePoint::new(projection.project(network.coords[n]), n))
+        .collect();
+
+        SegmentIndex { projection, tree: RTree::bulk_load(lines), nodes: RTree::bulk_load(points) }
+    }
+
+    /// Graph node closest to the coordinate, None when the network has no
+    /// node or the coordinate is not finite
+    pub(crate) fn nearest_node(&self, network: &StreetNetwork, lat: f64, lon: f64) -> Option<usize> {
+        if !lat.is_finite() || !lon.is_finite() {
+            return None;
+        }
+        let point = self.projection.project((lat, lon));
+
+        // The projection is only exact at its center, so look a bit past
+        // the nearest node on the plane for the nearest one on the ground
+        let mut best: Option<(usize, f64)> = None;
+        for candidate in self.nodes.nearest_neighbor_iter(&point) {
+            let [x, y] = *candidate.geom();
+            let planar = ((x - point[0]).powi(2) + (y - point[1]).powi(2)).sqrt();
+            if let Some((_, distance)) = best {
+                if planar > distance * 1.1 {
+                    break;
+                }
+            }
+            let (node_lat, node_lon) = network.coords[candidate.data];
+            let distance = geo::distance(lat, lon, node_lat, node_lon);
+            if best.is_none_or(|(_, b)| distance.total_cmp(&b).is_lt()) {
+                best = Some((candidate.data, distance));
+            }
+        }
+        best.map(|(node, _)| node)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Point of a street closest to a coordinate
+pub struct Snap {
+    /// (lat, lon) of the point on the street
+    pub location: (f64, f64),
+    /// Distance in meters from the coordinate
+    pub distance: f64,
+    /// Way of the street
+    pub way: i64,
+    /// OSM id of the node the segment starts at, travelling from `from`
+    /// to `to` is allowed
+    pub from: i64,
+    /// OSM id of the node the segment ends at
+    pub to: i64,
+    /// Position along the segment, from 0 at `from` to 1 at `to`
+    pub ratio: f64,
+    /// (index in `network.edges`, index of the piece, position along the
+    /// piece) for each direction of travel through the point
+    pub(crate) edges: Vec<(usize, usize, f64)>,
+}
+
+/// Difference in degrees between two bearings
+fn turn(a: f64, b: f64) -> f64 {
+    let delta = (a - b).rem_euclid(360.0);
+    delta.min(360.0 - delta)
+}
+
+/// Every street segment within `radius` meters of the coordinate, closest
+/// first
+///
+/// With a `heading`, in degrees clockwise from north, only the directions
+/// of travel within [`HEADING_TOLERANCE`] of it are kept. There is none
+/// for a coordinate that is not finite or a `radius` that is not a number.
+pub fn candidates(network: &StreetNetwork, lat: f64, lon: f64, radius: f64, heading: Option<f64>) -> Vec<Snap> {
+    if !lat.is_finite() || !lon.is_finite() || radius.is_nan() {
+        return vec![];
+    }
+    let index = network.segment_index();
+    let point = index.projection.project((lat, lon));
+
+    // Both directions of a two way street share the segment
+    let mut snaps: Vec<Snap> = vec![];
+    let mut seen: HashMap<(i64, i64, i64), usize> = HashMap::new();
+    for (line, squared) in index.tree.nearest_neighbor_iter_with_distance_2(&point) {
+        if squared.sqrt() > radius {
+            break;
+        }
+        let (i, k, from) = line.data;
+        let piece = &network.pieces(&network.edges[i])[k];
+        let (a, b) = (line.geom().from, line.geom().to);
+
+        if let Some(heading) = heading {
+            let (start, end) = (index.projection.unproject(a), index.projection.unproject(b));
+            if turn(geo::bearing(start.0, start.1, end.0, end.1), heading) > HEADING_TOLERANCE {
+                continue;
+            }
+        }
+
+        let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+        let length = dx * dx + dy * dy;
+        let t = match length > 0.0 {
+            true => (((point[0] - a[0]) * dx + (point[1] - a[1]) * dy) / length).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+
+        let key = (from.min(piece.node), from.max(piece.node), piece.way);
+        if let Some(s) = seen.get(&key) {
+            snaps[*s].edges.push((i, k, t));
+            continue;
+        }
+        let location = index.projection.unproject([a[0] + dx * t, a[1] + dy * t]);
+        seen.insert(key, snaps.len());
+        snaps.push(Snap {
+            location,
+            distance: geo::distance(lat, lon, location.0, location.1),
+            way: piece.way,
+            from,
+            to: piece.node,
+            ratio: t,
+            edges: vec![(i, k, t)],
+        });
+    }
+    snaps
+}
+
+/// Closest point of a street within `max_distance` meters of the
+/// coordinate, see [`candidates`]
+pub fn snap(network: &StreetNetwork, lat: f64, lon: f64, max_distance: f64, heading: Option<f64>) -> Option<Snap> {
+    candidates(network, lat, lon, max_distance, heading).into_iter().next()
+}
+
+/// Part of `piece` between the positions `start` and `end`
+fn part(piece: &Piece, start: f64, end: f64) -> (Segment, f64) {
+    let share = end - start;
+    let segment = Segment { way: piece.way, distance: piece.distance * share, duration: piece.duration * share };
+    (segment, piece.weight as f64 * share)
+}
+
+/// Nodes, geometry and segments of a route being put together
+struct Path {
+    nodes: Vec<i64>,
+    geometry: Vec<(f64, f64)>,
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    /// Follow `pieces` from position `start` of the first one to position
+    /// `end` of the last one, which ends at the virtual node `last` when
+    /// given
+    fn follow(&mut self, network: &StreetNetwork, pieces: &[Piece], start: f64, end: f64, last: Option<(i64, (f64, f64))>) {
+        let count = pieces.len();
+        for (k, piece) in pieces.iter().enumerate() {
+            let from = if k == 0 { start } else { 0.0 };
+            let to = if k == count - 1 { end } else { 1.0 };
+            self.segments.push(part(piece, from, to).0);
+            match last {
+                Some((node, location)) if k == count - 1 => {
+                    self.nodes.push(node);
+                    self.geometry.push(location);
+                },
+                _ => {
+                    self.nodes.push(piece.node);
+                    self.geometry.push(network.location(piece.node).unwrap_or_default());
+                },
+            }
+        }
+    }
+}
+
+/// Weight along `pieces` from position `start` of the first one to
+/// position `end` of the last one
+fn cost(pieces: &[Piece], start: f64, end: f64) -> f64 {
+    let count = pieces.len();
+    pieces.iter().enumerate()
+    .map(|(k, piece)| part(piece, if k == 0 { start } else { 0.0 }, if k == count - 1 { end } else { 1.0 }).1)
+    .sum()
+}
+
+/// Best route from the snapped point `from` to the snapped point `to`
+///
+/// The route starts at the virtual node [`START`] and ends at [`END`], the
+/// parts of the snapped segments it uses are weighted by their share of
+/// the segment.
+pub fn route_between(network: &StreetNetwork, from: &Snap, to: &Snap) -> Result<Route, RouteError> {
+    let mut calculator = fast_paths::create_calculator(network.prepared());
+    route_with(network, &mut calculator, from, to)
+}
+
+/// [`route_between`] with a `calculator` of the prepared graph of
+/// `network`, which callers routing many times can create once
+pub(crate) fn route_with(network: &StreetNetwork, calculator: &mut fast_paths::PathCalculator, from: &Snap, to: &Snap) -> Result<Route, RouteError> {
+    let graph = network.prepared();
+
+    // (weight, origin edge, destination edge, path between them when the
+    // route does not stay on a single edge)
+    let mut best: Option<(f64, usize, usize, Option<fast_paths::ShortestPath>)> = None;
+    for (o, (oi, ok, ot)) in from.edges.iter().enumerate() {
+        for (d, (di, dk, dt)) in to.edges.iter().enumerate() {
+            let (origin, destination) = (&network.edges[*oi], &network.edges[*di]);
+            let candidate = if oi == di && (ok, ot) <= (dk, dt) {
+                let pieces = network.pieces(origin);
+                (cost(&pieces[*ok..=*dk], *ot, *dt), None)
+            } else {
+                let path = match calculator.calc_path(graph, origin.to, destination.from) {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let (head, tail) = (network.pieces(origin), network.pieces(destination));
+                let weight = cost(&head[*ok..], *ot, 1.0) + path.get_weight() as f64 + cost(&tail[..=*dk], 0.0, *dt);
+                (weight, Some(path))
+            };
+            if best.as_ref().is_none_or(|b| candidate.0 < b.0) {
+                best = Some((candidate.0, o, d, candidate.1));
+            }
+        }
+    }
+
+    let (weight, o, d, middle) = best.ok_or(RouteError::NoRoute(START, END))?;
+    let ((oi, ok, ot), (di, dk, dt)) = (from.edges[o], to.edges[d]);
+    let mut path = Path { nodes: vec![START], geometry: vec![from.location], segments: vec![] };
+    let end = Some((END, to.location));
+    let head = network.pieces(&network.edges[oi]);
+
+    match middle {
+        None => path.follow(network, &head[ok..=dk], ot, dt, end),
+        Some(middle) => {
+            path.follow(network, &head[ok..], ot, 1.0, None);
+            let route = network.route_along(middle.get_nodes(), middle.get_weight());
+            path.nodes.extend(route.nodes.iter().skip(1));
+            path.geometry.extend(route.geometry.iter().skip(1));
+            path.segments.extend(route.segments);
+            let tail = network.pieces(&network.edges[di]);
+            path.follow(network, &tail[..=dk], 0.0, dt, end);
+        },
+    }
+
+    Ok(network.assemble(path.nodes, path.geometry, path.segments, weight.round() as usize))
+}
+
+/// Best route between two (lat, lon) coordinates, each snapped to the
+/// closest street within `max_distance` meters
+pub fn route(network: &StreetNetwork, from: (f64, f64), to: (f64, f64), max_distance: f64) -> Result<Route, RouteError> {
+    let origin = snap(network, from.0, from.1, max_distance, None).ok_or(RouteError::NotSnapped(from.0, from.1))?;
+    let destination = snap(network, to.0, to.1, max_distance, None).ok_or(RouteError::NotSnapped(to.0, to.1))?;
+    route_between(network, &origin, &destination)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::RoutingProfile;
+
+    /// Two way street 1-2-3 along the equator, oneway 3-4-1 north of it
+    const STREETS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.001" lon="0.001"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/><tag k="name" v="Low Street"/></way>
+      <way id="11"><nd ref="3"/><nd ref="4"/><nd ref="1"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+    </osm>"#;
+
+    #[test]
+    fn coordinates_snap_to_the_closest_segment() {
+        let map = Map::from_xml(STREETS.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let snapped = network.snap(0.0001, 0.00025, 50.0, None).unwrap();
+        assert_eq!((snapped.way, snapped.from.min(snapped.to), snapped.from.max(snapped.to)), (10, 1, 2));
+        assert!((snapped.location.0).abs() < 1e-9 && (snapped.location.1 - 0.00025).abs() < 1e-9);
+        assert!((snapped.distance - 11.1).abs() < 0.1);
+        assert_eq!(snapped.edges.len(), 2);
+
+        assert_eq!(network.snap(0.01, 0.0, 50.0, None), None);
+        // Heading east is only allowed on the two way street
+        let east = network.snap(0.0004, 0.0005, 100.0, Some(80.0)).unwrap();
+        assert_eq!((east.from, east.to), (1, 2));
+        let south_west = network.snap(0.0004, 0.0005, 100.0, Some(225.0)).unwrap();
+        assert_eq!((south_west.from, south_west.to, south_west.edges.len()), (4, 1, 1));
+        assert_eq!(network.candidates(0.0004, 0.0005, 50.0, None).len(), 2);
+
+        assert_eq!(network.nearest_node(0.0008, 0.0012), Some(4));
+        assert_eq!(network.nearest_node(-1.0, -1.0), Some(1));
+        assert_eq!(network.nearest_node(f64::NAN, 0.0), None);
+        assert_eq!(network.snap(f64::NAN, 0.0, 50.0, None), None);
+        assert!(network.candidates(0.0004, 0.0005, f64::NAN, None).is_empty());
+    }
+
+    #[test]
+    fn routes_between_coordinates_use_part_of_the_edges() {
+        let map = Map::from_xml(STREETS.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        let full = network.route(1, 3).unwrap();
+
+        let route = network.route_coords((0.0001, 0.00025), (-0.0001, 0.00175), 50.0).unwrap();
+        assert_eq!(route.nodes, vec![START, 2, END]);
+        assert_eq!(route.names, vec!["Low Street"]);
+        assert!((route.distance - full.distance * 0.75).abs() < 1e-6);
+        assert!((route.weight as f64 - full.weight as f64 * 0.75).abs() <= 1.0);
+        assert_eq!((route.legs[0].from, route.legs[0].to), (START, END));
+
+        // Negative ids of nodes not uploaded yet are kept apart
+        let local = STREETS.replace("\"2\"", "\"-2\"");
+        let local = StreetNetwork::from_profile(&Map::from_xml(local.as_bytes()).unwrap(), RoutingProfile::car());
+        let kept = local.route_coords((0.0001, 0.00025), (-0.0001, 0.00175), 50.0).unwrap();
+        assert_eq!(kept.nodes, vec![START, -2, END]);
+
+        // Backwards on the same segment
+        let back = network.route_coords((0.0, 0.0008), (0.0, 0.0002), 50.0).unwrap();
+        assert_eq!(back.nodes, vec![START, END]);
+        assert!((back.distance - full.distance * 0.3).abs() < 1e-6);
+
+        // The oneway can only be left forwards, through 1
+        let around = network.route_coords((0.0006, 0.0006), (0.0, 0.0005), 50.0).unwrap();
+        assert_eq!(around.nodes, vec![START, 1, END]);
+        assert_eq!(network.route_coords((0.5, 0.5), (0.0, 0.0), 50.0), Err(RouteError::NotSnapped(0.5, 0.5)));
+        let nan = network.route_coords((0.0, 0.0), (0.0, f64::NAN), 50.0);
+        assert!(matches!(nan, Err(RouteError::NotSnapped(lat, _)) if lat == 0.0));
+
+        // Simplified edges are split the same way
+        let mut simple = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        simple.simplify();
+        let other = simple.route_coords((0.0001, 0.00025), (-0.0001, 0.00175), 50.0).unwrap();
+        assert_eq!(other.nodes, route.nodes);
+        assert!((other.distance - route.distance).abs() < 1e-9);
+    }
+}
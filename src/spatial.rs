@@ -0,0 +1,313 @@
+/* spatial.rs */
+//! R-tree index over map nodes and way segments.
+//!
+//! Coordinates are stored in an equirectangular projection centered on the
+//! mean latitude of the map, so that planar distances in the tree stay
+//! proportional to ground distances for regional extracts.
+
+use std::collections::HashSet;
+
+use fnv::FnvHashMap;
+use rstar::{RTree, AABB};
+use rstar::primitives::{GeomWithData, Line};
+
+use crate::geo;
+use crate::map::{Bounds, Node, Way};
+
+type NodePoint = GeomWithData<[f64; 2], i64>;
+type WaySegment = GeomWithData<Line<[f64; 2]>, i64>;
+
+#[derive(Clone, Copy, Debug)]
+struct Projection {
+    cos_lat: f64,
+}
+
+impl Projection {
+    fn project(&self, lat: f64, lon: f64) -> [f64; 2] {
+        [lon * self.cos_lat, lat]
+    }
+
+    fn unproject(&self, p: &[f64; 2]) -> (f64, f64) {
+        (p[1], p[0] / self.cos_lat)
+    }
+
+    fn envelope(&self, bounds: &Bounds) -> AABB<[f64; 2]> {
+        AABB::from_corners(
+            self.project(bounds.minlat, bounds.minlon),
+            self.project(bounds.maxlat, bounds.maxlon),
+        )
+    }
+}
+
+/// Spatial index of a map, built once when the map is loaded
+pub struct SpatialIndex {
+    projection: Projection,
+    nodes: RTree<NodePoint>,
+    segments: RTree<WaySegment>,
+}
+
+impl SpatialIndex {
+    pub fn new(nodes: &FnvHashMap<i64, Node>, ways: &FnvHashMap<i64, Way>) -> Self {
+        let mean_lat = if nodes.is_empty() {
+            0.0
+        } else {
+            nodes.values().map(|n| n.lat).sum::<f64>() / nodes.len() as f64
+        };
+        let projection = Projection { cos_lat: mean_lat.to_radians().cos() };
+
+        let points = nodes.values()
+        .map(|n| NodePoint::new(projection.project(n.lat, n.lon), n.id))
+        .collect();
+
+        let mut segments = vec![];
+        for way in ways.values() {
+            let coords = way.nodes.iter()
+            .filter_map(|id| nodes.get(id))
+            .map(|n| projection.project(n.lat, n.lon))
+            .collect::<Vec<[f64; 2]>>();
+
+            if coords.len() == 1 {
+                segments.push(WaySegment::new(Line::new(coords[0], coords[0]), way.id));
+            }
+            for w in coords.windows(2) {
+                segments.push(WaySegment::new(Line::new(w[0], w[1]), way.id));
+            }
+        }
+
+        SpatialIndex {
+            projection,
+            nodes: RTree::bulk_load(points),
+            segments: RTree::bulk_load(segments),
+        }
+    }
+
+    /// Ids of the nodes inside `bounds`
+    pub fn nodes_in_bbox(&self, bounds: &Bounds) -> Vec<i64> {
+        self.nodes.locate_in_envelope(&self.projection.envelope(bounds))
+        .map(|p| p.data)
+        .collect()
+    }
+
+    /// Ids of the ways crossing or lying inside `bounds`
+    pub fn ways_in_bbox(&self, bounds: &Bounds) -> Vec<i64> {
+        let envelope = self.projection.envelope(bounds);
+        let mut seen = HashSet::new();
+        self.segments.locate_in_envelope_intersecting(&envelope)
+        .filter(|s| segment_intersects(s.geom(), &envelope))
+        .map(|s| s.data)
+        .filter(|id| seen.insert(*id))
+        .collect()
+    }
+
+    /// Radius in the projection covering every point at most `meters`
+    /// away from the latitude `lat`
+    fn planar_radius(&self, lat: f64, meters: f64) -> f64 {
+        // Longitudes are scaled by the cosine of the mean latitude but
+        // shrink with the cosine of their own latitude on the ground, most
+        // on the side of the circle closest to a pole
+        let degrees = meters / geo::METERS_PER_DEGREE;
+        let poleward = (lat.abs() + degrees).min(90.0).to_radians().cos();
+        let stretch = (self.projection.cos_lat / poleward).max(1.0);
+        // Some more for the curvature of the earth
+        degrees * stretch * 1.1
+    }
+
+    /// Ids of the nodes at most `meters` away from the point
+    pub fn nodes_within_radius(&self, lat: f64, lon: f64, meters: f64) -> Vec<i64> {
+        // The projection is only exact on the mean latitude, so take a
+        // margin and filter the candidates with the real distance.
+        let radius = self.planar_radius(lat, meters);
+        self.nodes.locate_within_distance(self.projection.project(lat, lon), radius * radius)
+        .filter(|p| {
+            let (plat, plon) = self.projection.unproject(p.geom());
+            geo::distance(lat, lon, plat, plon) <= meters
+        })
+        .map(|p| p.data)
+        .collect()
+    }
+
+    /// Ids of the ways passing at most `meters` away from the point
+    pub fn ways_within_radius(&self, lat: f64, lon: f64, meters: f64) -> Vec<i64> {
+        // Same margin as for the nodes, then the real distance
+        let radius = self.planar_radius(lat, meters);
+        let mut seen = HashSet::new();
+        self.segments.locate_within_distance(self.projection.project(lat, lon), radius * radius)
+        .filter(|s| self.segment_distance(lat, lon, s.geom()) <= meters)
+        .map(|s| s.data)
+        .filter(|id| seen.insert(*id))
+        .collect()
+    }
+
+    /// Distance in meters from the point to the closest point of `line`
+    fn segment_distance(&self, lat: f64, lon: f64, line: &Line<[f64; 2]>) -> f64 {
+        let (a, b) = (self.projection.unproject(&line.from), self.projection.unproject(&line.to));
+
+        // Closest point found on a plane tangent at the point
+        let cos_lat = lat.to_radians().cos();
+        let (ax, ay) = ((a.1 - lon) * cos_lat, a.0 - lat);
+        let (dx, dy) = ((b.1 - a.1) * cos_lat, b.0 - a.0);
+        let length = dx * dx + dy * dy;
+        let t = if length > 0.0 { (-(ax * dx + ay * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+
+        geo::distance(lat, lon, a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+    }
+
+    /// Node ids ordered from the nearest to the point, none when the
+    /// point is not finite
+    pub fn nearest_nodes(&self, lat: f64, lon: f64) -> impl Iterator<Item = i64> + '_ {
+        let point = self.finite_point(lat, lon);
+        point.map(|p| self.nodes.nearest_neighbor_iter(&p)).into_iter().flatten()
+        .map(|p| p.data)
+    }
+
+    /// Way ids ordered from the nearest to the point, none when the point
+    /// is not finite
+    pub fn nearest_ways(&self, lat: f64, lon: f64) -> impl Iterator<Item = i64> + '_ {
+        let mut seen = HashSet::new();
+        let point = self.finite_point(lat, lon);
+        point.map(|p| self.segments.nearest_neighbor_iter(&p)).into_iter().flatten()
+        .map(|s| s.data)
+        .filter(move |id| seen.insert(*id))
+    }
+
+    /// Projection of the point, the R-trees can not order distances to a
+    /// point that is not finite
+    fn finite_point(&self, lat: f64, lon: f64) -> Option<[f64; 2]> {
+        match lat.is_finite() && lon.is_finite() {
+            true => Some(self.projection.project(lat, lon)),
+            false => None,
+        }
+    }
+}
+
+/// Clip the segment against the box (Liang–Barsky)
+fn segment_intersects(line: &Line<[f64; 2]>, envelope: &AABB<[f64; 2]>) -> bool {
+    let (lower, upper) = (envelope.lower(), envelope.upper());
+    let (from, to) = (line.from, line.to);
+    let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+
+    let mut t0: f64 = 0.0;
+    let mut t1: f64 = 1.0;
+    for (p, q) in [
+        (-dx, from[0] - lower[0]),
+        (dx, upper[0] - from[0]),
+        (-dy, from[1] - lower[1]),
+        (dy, upper[1] - from[1]),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    t0 <= t1
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::map::Map;
+    use crate::queries::QueryBuilder;
+
+    fn sample() -> Map {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="-6.200" lon="106.800"><tag k="amenity" v="cafe"/></node>
+          <node id="2" lat="-6.201" lon="106.800"><tag k="amenity" v="cafe"/></node>
+          <node id="3" lat="-6.210" lon="106.810"/>
+          <node id="4" lat="-6.300" lon="106.900"><tag k="amenity" v="cafe"/></node>
+          <way id="10"><nd ref="1"/><nd ref="3"/><tag k="highway" v="primary"/></way>
+          <way id="11"><nd ref="3"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+        </osm>"#;
+        Map::from_xml(xml.as_bytes()).unwrap()
+    }
+
+    fn ids<T: crate::map::TaggableElement>(items: Vec<T>) -> Vec<i64> {
+        let mut ids = items.iter().map(|i| i.get_id()).collect::<Vec<i64>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn nodes_within_bbox_and_radius() {
+        let map = sample();
+
+        let found = map.nodes().within_bbox(-6.205, 106.795, -6.199, 106.805).get();
+        assert_eq!(ids(found), vec![1, 2]);
+
+        let found = map.nodes().within_radius(-6.200, 106.800, 200.0).get();
+        assert_eq!(ids(found), vec![1, 2]);
+
+        let found = map.nodes().within_radius(-6.200, 106.800, 50.0).get();
+        assert_eq!(ids(found), vec![1]);
+    }
+
+    #[test]
+    fn nearest_nodes_respect_previous_filters() {
+        let map = sample();
+
+        let found = map.nodes().nearest(-6.2095, 106.8095, 2).get();
+        assert_eq!(found.iter().map(|n| n.id).collect::<Vec<i64>>(), vec![3, 2]);
+
+        let found = map.nodes()
+        .by_tag_eq("amenity", "cafe")
+        .nearest(-6.2095, 106.8095, 2)
+        .get();
+        assert_eq!(found.iter().map(|n| n.id).collect::<Vec<i64>>(), vec![2, 1]);
+
+        let found = map.nodes()
+        .within_bbox(-6.25, 106.79, -6.199, 106.85)
+        .nearest(-6.3, 106.9, 1)
+        .get();
+        assert_eq!(ids(found), vec![3]);
+    }
+
+    #[test]
+    fn ways_crossing_bbox() {
+        let map = sample();
+
+        // No node of way 10 lies in this box, but the way crosses it
+        let found = map.ways().within_bbox(-6.206, 106.804, -6.204, 106.806).get();
+        assert_eq!(ids(found), vec![10]);
+
+        let found = map.ways().within_radius(-6.205, 106.805, 10.0).get();
+        assert_eq!(ids(found), vec![10]);
+
+        // Way 10 passes 78.4 m away from this point
+        assert_eq!(ids(map.ways().within_radius(-6.205, 106.806, 80.0).get()), vec![10]);
+        assert!(map.ways().within_radius(-6.205, 106.806, 77.0).get().is_empty());
+
+        let found = map.ways().nearest(-6.3, 106.9, 2).get();
+        assert_eq!(found.iter().map(|w| w.id).collect::<Vec<i64>>(), vec![11, 10]);
+
+        assert!(map.nodes().nearest(f64::NAN, 0.0, 1).get().is_empty());
+        assert!(map.ways().nearest(0.0, f64::INFINITY, 1).get().is_empty());
+    }
+
+    #[test]
+    fn radius_holds_far_from_the_mean_latitude() {
+        // The projection is centered on 40°, node 3 lies 945 m east of the
+        // query point at 60° and way 10 as far
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="0.0" lon="10.0"/>
+          <node id="2" lat="60.0" lon="10.0"/>
+          <node id="3" lat="60.0" lon="10.017"/>
+          <node id="4" lat="60.001" lon="10.017"/>
+          <way id="10"><nd ref="3"/><nd ref="4"/></way>
+        </osm>"#;
+        let map = Map::from_xml(xml.as_bytes()).unwrap();
+
+        assert_eq!(ids(map.nodes().within_radius(60.0, 10.0, 950.0).get()), vec![2, 3]);
+        assert_eq!(ids(map.nodes().within_radius(60.0, 10.0, 940.0).get()), vec![2]);
+        assert_eq!(ids(map.ways().within_radius(60.0005, 10.0, 950.0).get()), vec![10]);
+        assert!(map.ways().within_radius(60.0005, 10.0, 940.0).get().is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+/* stats.rs */
+//! Summary statistics of a street network, in the manner of OSMnx
+//! `basic_stats`.
+//!
+//! Figures are taken from the graph as it stands: nodes removed by
+//! [`StreetNetwork::simplify`] are not counted as nodes, and an edge
+//! running through them counts once with its full length, which circuity
+//! compares with the straight line between its ends. A junction split by
+//! turn restrictions counts as a single node and its duplicated edges once.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::geo;
+use crate::intersection::IntersectionKind;
+use crate::map::Bounds;
+use crate::network::StreetNetwork;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkStats {
+    /// Number of nodes of the graph
+    pub nodes: usize,
+    /// Number of directed edges, two way streets count twice
+    pub edges: usize,
+    /// Total length of the edges in meters
+    pub edge_length_total: f64,
+    /// Mean length of the edges in meters
+    pub edge_length_avg: f64,
+    /// Number of street segments, edges whatever their direction of travel
+    pub streets: usize,
+    /// Total length of the street segments in meters
+    pub street_length_total: f64,
+    /// Mean length of the street segments in meters
+    pub street_length_avg: f64,
+    /// Length of the street segments in meters by `highway` tag
+    pub street_length_by_highway: HashMap<String, f64>,
+    /// Number of nodes by number of street segments meeting there
+    pub streets_per_node_counts: HashMap<usize, usize>,
+    /// Mean number of street segments meeting at a node
+    pub streets_per_node_avg: f64,
+    /// Number of intersections, dead ends aside, see
+    /// [`intersection::intersections`](crate::intersection::intersections)
+    pub intersections: usize,
+    /// Area in square kilometers the densities are computed over
+    pub area: f64,
+    /// Number of intersections per square kilometer
+    pub intersection_density: f64,
+    /// Ratio between the length of the street segments and the straight
+    /// distance between their ends
+    pub circuity_avg: f64,
+    /// Share of the edges that end where they start
+    pub self_loop_proportion: f64,
+}
+
+/// `total / count`, 0 when there is nothing to count
+fn ratio(total: f64, count: f64) -> f64 {
+    if count > 0.0 { total / count } else { 0.0 }
+}
+
+/// Area of `bounds` on the sphere in square kilometers
+fn bounds_area(bounds: &Bounds) -> f64 {
+    let height = bounds.maxlat.to_radians().sin() - bounds.minlat.to_radians().sin();
+    let width = (bounds.maxlon - bounds.minlon).to_radians();
+    (geo::EARTH_RADIUS * geo::EARTH_RADIUS * height * width).abs() / 1e6
+}
+
+/// Area of the convex hull of the (lat, lon) `points` in square
+/// kilometers, projected on a plane around their mean latitude
+fn hull_area(points: &[(f64, f64)]) -> f64 {
+    if points.is_empty() {
+        return 0.0;
+    }
+    let mean = points.iter().map(|p| p.0).sum::<f64>() / points.len() as f64;
+    let scale = mean.to_radians().cos();
+    let mut xy = points.iter()
+    .map(|(lat, lon)| (lon * scale * geo::METERS_PER_DEGREE, lat * geo::METERS_PER_DEGREE))
+    .collect::<Vec<(f64, f64)>>();
+    xy.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    xy.dedup();
+
+    // Andrew's monotone chain, lower then upper half
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0);
+    let mut hull: Vec<(f64, f64)> = vec![];
+    for pass in 0..2 {
+        let start = hull.len();
+        for point in xy.iter() {
+            while hull.len() >= start + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], *point) <= 0.0 {
+                hull.pop();
+            }
+            hull.push(*point);
+        }
+        hull.pop();
+        if pass == 0 {
+            xy.reverse();
+        }
+    }
+
+    let twice = (0..hull.len())
+    .map(|i| {
+        let (a, b) = (hull[i], hull[(i + 1) % hull.len()]);
+        a.0 * b.1 - b.0 * a.1
+    })
+    .sum::<f64>();
+    twice.abs() / 2.0 / 1e6
+}
+
+/// Summary of the network
+///
+/// Densities are computed over `bounds`, such as
+/// [`Map::bounds`](crate::map::Map::bounds), or over the convex hull of the
+/// nodes when None.
+pub fn summary(network: &StreetNetwork, bounds: Option<&Bounds>) -> NetworkStats {
+    // OSM nodes along each edge identify it among the copies
+    let mut edges: HashMap<Vec<i64>, usize> = HashMap::new();
+    for (i, edge) in network.edges.iter().enumerate() {
+        let mut nodes = vec![network.node_idx.node(edge.from)];
+        nodes.extend(network.pieces(edge).iter().map(|p| p.node));
+        edges.entry(nodes).or_insert(i);
+    }
+    let mut streets: HashMap<Vec<i64>, usize> = HashMap::new();
+    for (nodes, edge) in edges.iter() {
+        let reversed = nodes.iter().rev().copied().collect::<Vec<i64>>();
+        streets.entry(nodes.clone().min(reversed)).or_insert(*edge);
+    }
+
+    let edge_length_total = edges.values().map(|e| network.edges[*e].distance).sum::<f64>();
+    let self_loops = edges.keys().filter(|nodes| nodes.first() == nodes.last()).count();
+
+    let mut street_length_total = 0.0;
+    let mut straight_total = 0.0;
+    let mut street_length_by_highway: HashMap<String, f64> = HashMap::new();
+    let mut streets_per_node: HashMap<i64, usize> = HashMap::new();
+    for (nodes, edge) in streets.iter() {
+        let edge = &network.edges[*edge];
+        street_length_total += edge.distance;
+        let ((from_lat, from_lon), (to_lat, to_lon)) = (network.coords[edge.from], network.coords[edge.to]);
+        straight_total += geo::distance(from_lat, from_lon, to_lat, to_lon);
+        for piece in network.pieces(edge) {
+            let highway = network.ways.get(&piece.way).and_then(|w| w.tags.get("highway"));
+            if let Some(highway) = highway {
+                *street_length_by_highway.entry(highway.clone()).or_insert(0.0) += piece.distance;
+            }
+        }
+        for end in [nodes[0], nodes[nodes.len() - 1]].iter() {
+            *streets_per_node.entry(*end).or_insert(0) += 1;
+        }
+    }
+
+    let ids = (0..network.node_idx.len())
+    .map(|n| network.node_idx.node(n))
+    .collect::<HashSet<i64>>();
+    let mut streets_per_node_counts = HashMap::new();
+    for id in ids.iter() {
+        *streets_per_node_counts.entry(streets_per_node.get(id).copied().unwrap_or(0)).or_insert(0) += 1;
+    }
+
+    let intersections = network.intersections().iter()
+    .filter(|i| i.kind != IntersectionKind::DeadEnd)
+    .count();
+    let area = match bounds {
+        Some(bounds) => bounds_area(bounds),
+        None => {
+            let points = network.coords.iter().chain(network.shape_nodes.values()).copied().collect::<Vec<(f64, f64)>>();
+            hull_area(&points)
+        }
+    };
+
+    NetworkStats {
+        nodes: ids.len(),
+        edges: edges.len(),
+        edge_length_total,
+        edge_length_avg: ratio(edge_length_total, edges.len() as f64),
+        streets: streets.len(),
+        street_length_total,
+        street_length_avg: ratio(street_length_total, streets.len() as f64),
+        street_length_by_highway,
+        streets_per_node_counts,
+        streets_per_node_avg: ratio(streets_per_node.values().sum::<usize>() as f64, ids.len() as f64),
+        intersections,
+        area,
+        intersection_density: ratio(intersections as f64, area),
+        circuity_avg: ratio(street_length_total, straight_total),
+        self_loop_proportion: ratio(self_loops as f64, edges.len() as f64),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::RoutingProfile;
+
+    /// Street 1-2-3 with a primary spur 2-4 and a oneway loop 3-5-6-3
+    const LOOP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.001" lon="0.001"/>
+      <node id="5" lat="0.001" lon="0.003"/>
+      <node id="6" lat="-0.001" lon="0.003"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+      <way id="11"><nd ref="2"/><nd ref="4"/><tag k="highway" v="primary"/></way>
+      <way id="12"><nd ref="3"/><nd ref="5"/><nd ref="6"/><nd ref="3"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+    </osm>"#;
+
+    #[test]
+    fn simplified_streets_wind() {
+        let map = Map::from_xml(LOOP.as_bytes()).unwrap();
+        let mut network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let stats = network.stats();
+        assert_eq!((stats.nodes, stats.edges, stats.streets, stats.intersections), (6, 9, 6, 2));
+        assert_eq!(stats.streets_per_node_counts, vec![(1, 2), (2, 2), (3, 2)].into_iter().collect());
+        assert!((stats.streets_per_node_avg - 2.0).abs() < 1e-9);
+        assert!((stats.circuity_avg - 1.0).abs() < 1e-6);
+        assert_eq!(stats.self_loop_proportion, 0.0);
+        let primary = geo::distance(0.0, 0.001, 0.001, 0.001);
+        assert!((stats.street_length_by_highway["primary"] - primary).abs() < 1e-6);
+        // Every street but the oneway loop is a pair of edges
+        let ring = stats.street_length_by_highway["residential"] - 2.0 * geo::distance(0.0, 0.0, 0.0, 0.001);
+        assert!((stats.edge_length_total - (2.0 * stats.street_length_total - ring)).abs() < 1e-6);
+        assert!((stats.area - 4e-6 * (geo::METERS_PER_DEGREE / 1e3).powi(2)).abs() < 1e-6);
+        assert!((stats.intersection_density - 2.0 / stats.area).abs() < 1e-9);
+
+        let bounds = Bounds { minlat: 0.0, minlon: 0.0, maxlat: 0.01, maxlon: 0.01 };
+        let within = network.stats_within(&bounds);
+        assert!((within.area - (0.01 * geo::METERS_PER_DEGREE / 1e3).powi(2)).abs() < 1e-6);
+
+        network.simplify();
+        let simplified = network.stats();
+        // A node of the loop is kept so that no edge ends where it starts
+        assert_eq!((simplified.nodes, simplified.edges, simplified.streets), (5, 8, 5));
+        assert_eq!(simplified.self_loop_proportion, 0.0);
+        assert!((simplified.street_length_total - stats.street_length_total).abs() < 1e-6);
+        assert_eq!(simplified.street_length_by_highway.len(), 2);
+        assert_eq!(simplified.streets_per_node_counts, vec![(1, 2), (2, 1), (3, 2)].into_iter().collect());
+        assert!(simplified.circuity_avg > 1.0);
+        assert_eq!((simplified.intersections, simplified.area), (stats.intersections, stats.area));
+    }
+}
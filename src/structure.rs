@@ -18,6 +18,12 @@ pub struct NodeMap<T: Copy + Ord + Debug + Serialize> {
     id_to_node: Vec<T>,
 }
 
+impl<T: Copy + Ord + Debug + Serialize> Default for NodeMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Copy + Ord + Debug + Serialize> NodeMap<T> {
     pub fn new() -> NodeMap<T> {
         NodeMap {
@@ -36,6 +42,21 @@ impl<T: Copy + Ord + Debug + Serialize> NodeMap<T> {
         id
     }
 
+    /// Add another graph node standing for `node`
+    ///
+    /// The copy translates back to `node`, while `get` and `find` keep
+    /// returning the first id of `node`.
+    pub fn insert_copy(&mut self, node: T) -> NodeId {
+        let id = self.id_to_node.len();
+        self.node_to_id.entry(node).or_insert(id);
+        self.id_to_node.push(node);
+        id
+    }
+
+    pub fn find(&self, node: T) -> Option<NodeId> {
+        self.node_to_id.get(&node).copied()
+    }
+
     pub fn get(&self, node: T) -> NodeId {
         if let Some(id) = self.node_to_id.get(&node) {
             *id
@@ -44,6 +65,20 @@ impl<T: Copy + Ord + Debug + Serialize> NodeMap<T> {
         }
     }
 
+    /// Node of the graph id
+    pub fn node(&self, id: NodeId) -> T {
+        self.id_to_node[id]
+    }
+
+    /// Number of nodes in the graph
+    pub fn len(&self) -> usize {
+        self.id_to_node.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id_to_node.is_empty()
+    }
+
     pub fn translate(&self, path: &ShortestPath) -> Vec<T> {
         path.get_nodes()
             .iter()
@@ -59,7 +94,10 @@ impl<T: Copy + Ord + Debug + Serialize> NodeMap<T> {
         //
         // We sometimes add nodes that aren't used yet, so that we can reuse the same node ordering
         // later. Detect if the last node isn't used.
-        let last_node = self.id_to_node.len() - 1;
+        let last_node = match self.id_to_node.len() {
+            0 => return,
+            len => len - 1,
+        };
         input_graph.freeze();
         for edge in input_graph.get_edges() {
             if edge.from == last_node || edge.to == last_node {
@@ -95,7 +133,7 @@ pub fn deserialize_nodemap<
     let id_to_node = inner.id_to_node;
     let mut node_to_id = BTreeMap::new();
     for (id, node) in id_to_node.iter().enumerate() {
-        node_to_id.insert(*node, id);
+        node_to_id.entry(*node).or_insert(id);
     }
 
     Ok(NodeMap {
@@ -0,0 +1,161 @@
+/* traffic.rs */
+//! Live speed updates of a street network.
+//!
+//! A [`TrafficUpdate`] collects new speeds for single edges and for whole
+//! ways, [`StreetNetwork::update`] applies them all at once.
+
+use std::collections::HashMap;
+
+use crate::network::StreetNetwork;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Batch of new speeds in km/h
+///
+/// Speeds that are not strictly positive are ignored, later speeds of the
+/// batch win over earlier ones for the same edge.
+///
+/// ```ignore
+/// let update = TrafficUpdate::new()
+///     .edge(1, 2, 12.0)
+///     .way(10, 25.0);
+/// network.update(&update);
+/// ```
+pub struct TrafficUpdate {
+    /// (from, to, speed) with OSM node ids
+    pub edges: Vec<(i64, i64, f64)>,
+    /// (way, speed) applying to both directions
+    pub ways: Vec<(i64, f64)>,
+}
+
+impl TrafficUpdate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Speed from node `a` to node `b`
+    pub fn edge(mut self, a: i64, b: i64, kmh: f64) -> Self {
+        self.edges.push((a, b, kmh));
+        self
+    }
+
+    /// Speed along both directions of `way`
+    pub fn way(mut self, way: i64, kmh: f64) -> Self {
+        self.ways.push((way, kmh));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.edges.len() + self.ways.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// (index in `network.edges`, index of the piece, new speed) of every part
+/// of an edge selected by `update`
+///
+/// Edges of a simplified network are updated piece by piece, edges that
+/// were not simplified have a single piece 0.
+pub(crate) fn changes(network: &StreetNetwork, update: &TrafficUpdate) -> Vec<(usize, usize, f64)> {
+    let usable = |kmh: &f64| kmh.is_finite() && *kmh > 0.0;
+    let mut speeds: HashMap<(usize, usize), f64> = HashMap::new();
+    // (from, to) OSM ids of every piece, junctions split by turn
+    // restrictions have an edge for each copy
+    let mut steps: HashMap<(i64, i64), Vec<(usize, usize)>> = HashMap::new();
+    let mut ways: HashMap<i64, Vec<(usize, usize)>> = HashMap::new();
+    for (i, edge) in network.edges.iter().enumerate() {
+        let mut from = network.node_idx.node(edge.from);
+        for (k, piece) in network.pieces(edge).iter().enumerate() {
+            steps.entry((from, piece.node)).or_default().push((i, k));
+            ways.entry(piece.way).or_default().push((i, k));
+            from = piece.node;
+        }
+    }
+
+    for (way, kmh) in update.ways.iter().filter(|(_, kmh)| usable(kmh)) {
+        for part in ways.get(way).into_iter().flatten() {
+            speeds.insert(*part, *kmh);
+        }
+    }
+    for (a, b, kmh) in update.edges.iter().filter(|(_, _, kmh)| usable(kmh)) {
+        for part in steps.get(&(*a, *b)).into_iter().flatten() {
+            speeds.insert(*part, *kmh);
+        }
+    }
+
+    let mut changes = speeds.into_iter()
+    .map(|((i, k), kmh)| (i, k, kmh))
+    .collect::<Vec<(usize, usize, f64)>>();
+    changes.sort_by_key(|(i, k, _)| (*i, *k));
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map::Map;
+    use crate::profile::{Metric, RoutingProfile};
+
+    /// Direct street 1-2-3 and a detour 1-4-3 half as fast
+    const DETOUR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.001" lon="0.001"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="primary"/></way>
+      <way id="11"><nd ref="1"/><nd ref="4"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+    </osm>"#;
+
+    #[test]
+    fn slow_traffic_changes_the_route() {
+        let map = Map::from_xml(DETOUR.as_bytes()).unwrap();
+        let mut network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        assert_eq!(network.route(1, 3).unwrap().nodes, vec![1, 2, 3]);
+        assert!(network.order.is_empty());
+
+        // One direction only
+        assert_eq!(network.set_edge_speed(2, 3, 5.0), 1);
+        assert!(!network.order.is_empty());
+        assert_eq!(network.route(1, 3).unwrap().nodes, vec![1, 4, 3]);
+        assert_eq!(network.route(3, 1).unwrap().nodes, vec![3, 2, 1]);
+        let edge = network.edge(2, 3).unwrap();
+        assert!((edge.duration - edge.distance / (5.0 / 3.6)).abs() < 1e-9);
+
+        // Traffic clears on the whole way
+        assert_eq!(network.set_way_speed(10, 50.0), 4);
+        assert_eq!(network.route(1, 3).unwrap().nodes, vec![1, 2, 3]);
+        assert_eq!(network.route(1, 3).unwrap().duration, network.matrix(&[1], &[3]).durations[0][0].unwrap());
+    }
+
+    #[test]
+    fn batch_updates_apply_together() {
+        let map = Map::from_xml(DETOUR.as_bytes()).unwrap();
+        let mut network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+        network.prepared();
+
+        let update = TrafficUpdate::new()
+        .way(10, 5.0)
+        .edge(1, 2, 60.0)
+        .edge(4, 3, -1.0)
+        .edge(1, 99, 10.0);
+        assert_eq!(update.len(), 4);
+        assert_eq!(network.update(&update), 4);
+        assert_eq!(network.edge(1, 2).unwrap().duration, network.edge(1, 2).unwrap().distance / (60.0 / 3.6));
+        assert_eq!(network.route(1, 3).unwrap().nodes, vec![1, 4, 3]);
+        assert_eq!(network.update(&TrafficUpdate::new()), 0);
+
+        // The stored ordering survives a round trip
+        let restored = StreetNetwork::deserialize(network.serialize());
+        assert_eq!(restored.order, network.order);
+        assert_eq!(restored.route(1, 3).unwrap(), network.route(1, 3).unwrap());
+
+        // Speeds do not change distance weights
+        let mut network = StreetNetwork::from_profile(&map, RoutingProfile::car().with_metric(Metric::Distance));
+        let weight = network.edge(1, 2).unwrap().weight;
+        network.set_way_speed(10, 5.0);
+        assert_eq!(network.edge(1, 2).unwrap().weight, weight);
+    }
+}
@@ -0,0 +1,134 @@
+/* via.rs */
+//! Routes through an ordered list of waypoints.
+//!
+//! Each leg starts from the graph node the previous leg arrived at, so a
+//! turn restriction at a waypoint still applies when leaving it.
+
+use crate::network::{Edge, StreetNetwork};
+use crate::route::{Route, RouteError};
+use crate::search;
+
+/// Route from the first to the last of `waypoints`, stopping at the others
+/// in order
+///
+/// When `uturns` is false a leg does not leave a waypoint back along the
+/// street the previous leg arrived by, a waypoint at a dead end then has
+/// no route through it. The result has one leg for each pair of
+/// consecutive waypoints.
+pub fn route_via(network: &StreetNetwork, waypoints: &[i64], uturns: bool) -> Result<Route, RouteError> {
+    let first = *waypoints.first().ok_or(RouteError::NoWaypoints)?;
+    if let Some(unknown) = waypoints.iter().find(|w| network.arrivals(**w).is_empty()) {
+        return Err(RouteError::UnknownNode(*unknown));
+    }
+    let mut current = network.node_idx.get(first);
+    if waypoints.len() == 1 {
+        return Ok(network.route_along(&[current], 0));
+    }
+
+    let graph = network.prepared();
+    let mut calculator = fast_paths::create_calculator(graph);
+    // OSM node the route arrived at the current waypoint from
+    let mut back: Option<i64> = None;
+    let mut legs = vec![];
+
+    for pair in waypoints.windows(2) {
+        let targets = network.arrivals(pair[1]);
+        // (leg, graph node it ends at)
+        let mut leg = targets.iter()
+        .filter_map(|t| calculator.calc_path(graph, current, *t))
+        .min_by_key(|p| p.get_weight())
+        .map(|p| (network.route_along(p.get_nodes(), p.get_weight()), *p.get_nodes().last().unwrap()));
+
+        let turns_back = leg.as_ref().is_some_and(|(route, _)| route.nodes.len() > 1 && Some(route.nodes[1]) == back);
+        if !uturns && turns_back {
+            let start = current;
+            let cost = |_: usize, e: &Edge| {
+                if e.from == start && Some(network.pieces(e)[0].node) == back {
+                    return None;
+                }
+                Some(e.weight as f64)
+            };
+            let tree = search::dijkstra(network, &[(start, 0.0)], f64::INFINITY, cost, |n| targets.contains(&n));
+            leg = targets.iter()
+            .filter(|t| tree.is_reached(**t))
+            .min_by(|a, b| tree.cost[**a].partial_cmp(&tree.cost[**b]).unwrap())
+            .map(|t| (network.route_over(start, &tree.edges(network, *t), tree.cost[*t] as usize), *t));
+        }
+
+        let (route, end) = leg.ok_or(RouteError::NoRoute(pair[0], pair[1]))?;
+        if route.nodes.len() > 1 {
+            back = Some(route.nodes[route.nodes.len() - 2]);
+        }
+        current = end;
+        legs.push(route);
+    }
+
+    Ok(Route::chain(legs))
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::map::Map;
+    use crate::network::StreetNetwork;
+    use crate::profile::RoutingProfile;
+    use crate::route::RouteError;
+
+    /// Two parallel streets 1-2-3 and 4-5-6 joined by 1-4, 2-5 and 3-6
+    const GRID: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    <osm version="0.6">
+      <node id="1" lat="0.000" lon="0.000"/>
+      <node id="2" lat="0.000" lon="0.001"/>
+      <node id="3" lat="0.000" lon="0.002"/>
+      <node id="4" lat="0.001" lon="0.000"/>
+      <node id="5" lat="0.001" lon="0.001"/>
+      <node id="6" lat="0.001" lon="0.002"/>
+      <node id="7" lat="0.000" lon="0.003"/>
+      <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="7"/><tag k="highway" v="residential"/></way>
+      <way id="11"><nd ref="4"/><nd ref="5"/><nd ref="6"/><tag k="highway" v="residential"/></way>
+      <way id="12"><nd ref="1"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+      <way id="13"><nd ref="2"/><nd ref="5"/><tag k="highway" v="residential"/></way>
+      <way id="14"><nd ref="3"/><nd ref="6"/><tag k="highway" v="residential"/></way>
+    </osm>"#;
+
+    #[test]
+    fn legs_are_joined_at_waypoints() {
+        let map = Map::from_xml(GRID.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        let route = network.route_via(&[1, 3, 6, 6, 4], true).unwrap();
+        assert_eq!(route.nodes, vec![1, 2, 3, 6, 5, 4]);
+        assert_eq!(route.legs.len(), 4);
+        let bounds = route.legs.iter().map(|l| (l.from, l.to, l.start, l.end)).collect::<Vec<_>>();
+        assert_eq!(bounds, vec![(1, 3, 0, 2), (3, 6, 2, 3), (6, 6, 3, 3), (6, 4, 3, 5)]);
+        assert_eq!(route.segments.len(), route.nodes.len() - 1);
+        assert_eq!(route.ways, vec![10, 14, 11]);
+
+        let direct = network.route(1, 3).unwrap();
+        assert_eq!(route.legs[0].distance, direct.distance);
+        assert_eq!(route.legs[0].weight, direct.weight);
+        assert!((route.distance - route.legs.iter().map(|l| l.distance).sum::<f64>()).abs() < 1e-9);
+        assert_eq!(route.weight, route.legs.iter().map(|l| l.weight).sum::<usize>());
+
+        let single = network.route_via(&[2], true).unwrap();
+        assert_eq!((single.nodes, single.legs.len()), (vec![2], 1));
+    }
+
+    #[test]
+    fn no_uturn_at_waypoints() {
+        let map = Map::from_xml(GRID.as_bytes()).unwrap();
+        let network = StreetNetwork::from_profile(&map, RoutingProfile::car());
+
+        assert_eq!(network.route_via(&[1, 2, 1], true).unwrap().nodes, vec![1, 2, 1]);
+        let route = network.route_via(&[1, 2, 1], false).unwrap();
+        assert_eq!(route.nodes, vec![1, 2, 5, 4, 1]);
+        assert_eq!((route.legs[1].start, route.legs[1].end), (1, 4));
+
+        // 7 is a dead end
+        assert_eq!(network.route_via(&[3, 7, 1], false), Err(RouteError::NoRoute(7, 1)));
+        assert_eq!(network.route_via(&[3, 7, 1], true).unwrap().nodes, vec![3, 7, 3, 2, 1]);
+
+        assert_eq!(network.route_via(&[], true), Err(RouteError::NoWaypoints));
+        assert_eq!(network.route_via(&[1, 99, 2], true), Err(RouteError::UnknownNode(99)));
+    }
+}